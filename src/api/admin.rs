@@ -0,0 +1,80 @@
+//! Access to the admin routes
+//!
+//! Admin routes take an [`Admin`] guard, which only lets through requests
+//! sending the key configured as `admin_api_key` in an
+//! `Authorization: Bearer <key>` header. While no key is configured, every
+//! admin request is refused.
+
+use rocket::http::Status;
+use rocket::request::{self, FromRequest, Request};
+
+/// Actor recorded for changes made with the admin key
+const ADMIN_ACTOR: &str = "admin";
+
+/// The configured admin key, managed as state
+pub struct AdminKey(Option<String>);
+
+impl AdminKey {
+    /// # Arguments
+    /// * `key` - The key admin requests must send, or `None` to refuse them all
+    pub fn new(key: Option<String>) -> Self {
+        Self(key.filter(|key| !key.trim().is_empty()))
+    }
+
+    fn accepts(&self, sent: &str) -> bool {
+        match &self.0 {
+            Some(key) => constant_time_eq(key.as_bytes(), sent.as_bytes()),
+            None => false,
+        }
+    }
+}
+
+/// Compares two secrets in a time that does not depend on where they differ
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Whether the request sends the admin key
+fn is_admin(req: &Request<'_>) -> bool {
+    let Some(key) = req.rocket().state::<AdminKey>() else {
+        return false;
+    };
+    req.headers()
+        .get_one("Authorization")
+        .and_then(|header| header.strip_prefix("Bearer "))
+        .is_some_and(|sent| key.accepts(sent.trim()))
+}
+
+/// The authenticated user sending the request, `None` for anonymous requests
+///
+/// The admin key is the only credential requests can send.
+pub fn identity(req: &Request<'_>) -> Option<&'static str> {
+    is_admin(req).then_some(ADMIN_ACTOR)
+}
+
+/// Who a request's changes are attributed to
+///
+/// Requests are not authenticated apart from the admin key, so any other
+/// request is identified by its client IP.
+pub fn actor(req: &Request<'_>) -> Option<String> {
+    match identity(req) {
+        Some(identity) => Some(identity.to_string()),
+        None => req.client_ip().map(|ip| ip.to_string()),
+    }
+}
+
+/// Request guard for routes only admins may use
+pub struct Admin;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Admin {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        if is_admin(req) {
+            request::Outcome::Success(Admin)
+        } else {
+            request::Outcome::Error((Status::Unauthorized, ()))
+        }
+    }
+}
@@ -0,0 +1,247 @@
+//! HTTP caching for read endpoints
+//!
+//! [`Cached`] wraps an [`ApiResult`] and turns it into a cacheable response:
+//! it adds an `ETag` computed from the serialized body, a `Last-Modified`
+//! date when a single item knows when it last changed, and a `Cache-Control`
+//! header chosen per route. Requests carrying a matching `If-None-Match` or
+//! a recent enough `If-Modified-Since` get an empty 304 response instead.
+//! The ETag is computed per response format, so each format is cached
+//! separately.
+
+use rocket::http::{Header, Method, Status};
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use serde::Serialize;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::format::{Format, Formatted};
+use super::ApiResult;
+use crate::db::blog_item::{BlogItem, Content};
+use crate::db::project_item::{DescItem, ProjectItem};
+use crate::db::shop_item::{ShopImage, ShopItem, ShopItemDesc};
+use crate::db::tag::{Tag, TagUsage};
+use crate::db::tag_category::TagCategory;
+use crate::db::user::User;
+
+/// How clients and shared caches may reuse a response
+#[derive(Debug, Clone, Copy)]
+pub enum CachePolicy {
+    /// Any cache may reuse the response for the given number of seconds
+    Public(u32),
+    /// Only the client may store the response, and it must revalidate it
+    /// with the server before every reuse
+    Private,
+}
+
+impl CachePolicy {
+    fn header_value(&self) -> String {
+        match self {
+            CachePolicy::Public(max_age) => format!("public, max-age={}", max_age),
+            CachePolicy::Private => "private, no-cache".to_string(),
+        }
+    }
+}
+
+/// Data that knows when it was last modified
+///
+/// The default implementation returns `None`, in which case responses are
+/// only validated by their ETag.
+pub trait LastModified {
+    fn last_modified(&self) -> Option<SystemTime> {
+        None
+    }
+}
+
+/// Lists are only validated by their ETag
+///
+/// The newest `updated_at` of the items does not change when an item is
+/// deleted or drops out of the list, so it cannot tell whether the list
+/// changed. The ETag covers the whole body and does.
+impl<T> LastModified for Vec<T> {}
+
+/// Models that embed [`crate::db::stamps::Stamps`] were last modified when they were last updated
+macro_rules! last_modified_from_stamps {
+    ($($model:ty),* $(,)?) => {
+        $(
+            impl LastModified for $model {
+                fn last_modified(&self) -> Option<SystemTime> {
+                    self.stamps.last_modified()
+                }
+            }
+        )*
+    };
+}
+
+last_modified_from_stamps!(
+    BlogItem,
+    Content,
+    ProjectItem,
+    DescItem,
+    ShopItem,
+    ShopImage,
+    ShopItemDesc,
+    Tag,
+    TagCategory,
+);
+
+impl LastModified for TagUsage {}
+impl LastModified for User {}
+
+/// A cacheable API result
+pub struct Cached<T> {
+    result: ApiResult<T>,
+    policy: CachePolicy,
+}
+
+impl<T> Cached<T> {
+    pub fn new(result: ApiResult<T>, policy: CachePolicy) -> Self {
+        Self { result, policy }
+    }
+}
+
+/// Hashes bytes with 64-bit FNV-1a
+///
+/// Fast and stable across builds, but not collision resistant, so only
+/// suitable for telling representations of the same resource apart.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Computes a strong ETag from the response body
+fn compute_etag(body: &[u8]) -> String {
+    format!("\"{:016x}\"", fnv1a(body))
+}
+
+/// Whether an `If-None-Match` header matches the ETag, using weak comparison
+fn etag_matches(header: &str, etag: &str) -> bool {
+    header
+        .split(',')
+        .map(str::trim)
+        .any(|candidate| candidate == "*" || candidate.trim_start_matches("W/") == etag)
+}
+
+/// Drops sub-second precision, which HTTP dates cannot represent
+fn truncate_to_seconds(time: SystemTime) -> SystemTime {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+    UNIX_EPOCH + Duration::from_secs(seconds)
+}
+
+/// Evaluates the conditional request headers against the current representation
+///
+/// `If-Modified-Since` is only considered when `If-None-Match` is absent.
+fn is_not_modified(req: &Request<'_>, etag: &str, last_modified: Option<SystemTime>) -> bool {
+    if let Some(if_none_match) = req.headers().get_one("If-None-Match") {
+        return etag_matches(if_none_match, etag);
+    }
+
+    let if_modified_since = req
+        .headers()
+        .get_one("If-Modified-Since")
+        .and_then(|date| httpdate::parse_http_date(date).ok());
+    match (if_modified_since, last_modified) {
+        (Some(since), Some(modified)) => truncate_to_seconds(modified) <= since,
+        _ => false,
+    }
+}
+
+impl<'r, T: Serialize + LastModified> Responder<'r, 'static> for Cached<T> {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let Formatted(response) = match self.result {
+            Ok(response) => response,
+            Err(error) => return error.respond_to(req),
+        };
+
+        let last_modified = response.data.as_ref().and_then(LastModified::last_modified);
+        let (content_type, body) = match Format::negotiate(req).and_then(|format| format.render(&response)) {
+            Ok(rendered) => rendered,
+            Err(error) => return error.respond_to(req),
+        };
+        let etag = compute_etag(&body);
+
+        let conditional = matches!(req.method(), Method::Get | Method::Head);
+        let mut builder = if conditional && is_not_modified(req, &etag, last_modified) {
+            let mut builder = Response::build();
+            builder.status(Status::NotModified);
+            builder
+        } else {
+            Response::build_from((content_type, body).respond_to(req)?)
+        };
+
+        builder
+            .header(Header::new("ETag", etag))
+            .header(Header::new("Vary", "Accept"))
+            .header(Header::new("Cache-Control", self.policy.header_value()));
+        if let Some(last_modified) = last_modified {
+            builder.header(Header::new("Last-Modified", httpdate::fmt_http_date(last_modified)));
+        }
+
+        Ok(builder.finalize())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::stamps::Stamps;
+    use time::OffsetDateTime;
+
+    #[test]
+    fn etags_are_quoted_hashes_of_the_body() {
+        let etag = compute_etag(b"{\"id\":1}");
+
+        assert_eq!(etag.len(), 18);
+        assert!(etag.starts_with('"') && etag.ends_with('"'));
+        assert_eq!(etag, compute_etag(b"{\"id\":1}"));
+        assert_ne!(etag, compute_etag(b"{\"id\":2}"));
+    }
+
+    #[test]
+    fn fnv1a_matches_the_reference_values() {
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+    }
+
+    #[test]
+    fn if_none_match_uses_weak_comparison() {
+        let etag = "\"0123456789abcdef\"";
+
+        assert!(etag_matches("\"0123456789abcdef\"", etag));
+        assert!(etag_matches("W/\"0123456789abcdef\"", etag));
+        assert!(etag_matches("\"other\", \"0123456789abcdef\"", etag));
+        assert!(etag_matches("*", etag));
+
+        assert!(!etag_matches("\"other\"", etag));
+        assert!(!etag_matches("0123456789abcdef", etag));
+        assert!(!etag_matches("", etag));
+    }
+
+    #[test]
+    fn truncating_drops_sub_second_precision() {
+        let time = UNIX_EPOCH + Duration::from_millis(1_700_000_000_999);
+
+        assert_eq!(
+            truncate_to_seconds(time),
+            UNIX_EPOCH + Duration::from_secs(1_700_000_000)
+        );
+    }
+
+    #[test]
+    fn lists_have_no_last_modified_date() {
+        let category = TagCategory {
+            id: Some(1),
+            name: "language".to_string(),
+            stamps: Stamps {
+                updated_at: Some(OffsetDateTime::UNIX_EPOCH),
+                ..Default::default()
+            },
+        };
+
+        assert!(category.last_modified().is_some());
+        assert!(vec![category].last_modified().is_none());
+    }
+}
@@ -0,0 +1,206 @@
+//! Optimistic concurrency for update routes
+//!
+//! Updates of versioned resources must say which version they were based on,
+//! either in an `If-Match` header or in the body's `version` field. Updated
+//! resources are answered with their version as the ETag, `"v<version>"`,
+//! which is what `If-Match` expects; `If-Match: *` accepts any version of an
+//! existing resource. A stale `If-Match` is answered with 412 Precondition
+//! Failed and a stale body version with 409 Conflict; both include the
+//! current version.
+
+use rocket::http::{Header, Status};
+use rocket::request::{self, FromRequest, Request};
+use rocket::response::{self, Responder};
+use serde::Serialize;
+
+use super::{ApiError, ApiResponse, ErrorCode};
+use crate::db::blog_item::BlogItem;
+use crate::db::project_item::ProjectItem;
+use crate::db::shop_item::ShopItem;
+
+/// The raw `If-Match` header of a request, if any
+pub struct IfMatch(Option<String>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for IfMatch {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        request::Outcome::Success(IfMatch(
+            req.headers().get_one("If-Match").map(str::to_string),
+        ))
+    }
+}
+
+/// Formats a version as the ETag of the resource at that version
+pub fn version_etag(version: i32) -> String {
+    format!("\"v{}\"", version)
+}
+
+/// Reads the version named by an `If-Match` header
+///
+/// Accepts the `"v<version>"` ETags of updated resources as well as bare
+/// `"<version>"` numbers.
+///
+/// # Returns
+/// * `Some(None)` for `*`, which matches any version
+/// * `None` if the header names no version
+fn parse_if_match(header: &str) -> Option<Option<i32>> {
+    let header = header.trim();
+    if header == "*" {
+        return Some(None);
+    }
+
+    let tag = header.trim_start_matches("W/").trim_matches('"');
+    tag.strip_prefix('v').unwrap_or(tag).parse().ok().map(Some)
+}
+
+/// The version an update expects to replace
+pub struct ExpectedVersion {
+    /// `None` if any version of an existing resource may be replaced
+    pub version: Option<i32>,
+    /// Status to answer with if the version turns out to be stale
+    mismatch_status: Status,
+}
+
+impl ExpectedVersion {
+    /// Picks the expected version from the `If-Match` header or the body
+    ///
+    /// The header takes precedence. Fails with 428 if neither is given, and
+    /// with 412 if the header does not name a version.
+    pub fn resolve(if_match: &IfMatch, body_version: Option<i32>) -> Result<Self, ApiError> {
+        match (&if_match.0, body_version) {
+            (Some(header), _) => parse_if_match(header)
+                .map(|version| ExpectedVersion {
+                    version,
+                    mismatch_status: Status::PreconditionFailed,
+                })
+                .ok_or_else(|| {
+                    ApiError::new(
+                        "If-Match must name a version of this resource",
+                        Status::PreconditionFailed,
+                    )
+                    .with_code(ErrorCode::VersionConflict)
+                }),
+            (None, Some(version)) => Ok(ExpectedVersion {
+                version: Some(version),
+                mismatch_status: Status::Conflict,
+            }),
+            (None, None) => Err(ApiError::new(
+                "Updates require an If-Match header or a version field",
+                Status::PreconditionRequired,
+            )),
+        }
+    }
+
+    /// The error for an update whose expected version is stale
+    pub fn mismatch(&self, current_version: i32) -> ApiError {
+        let message = match self.version {
+            Some(version) => format!(
+                "Expected version {} but the current version is {}",
+                version, current_version
+            ),
+            None => format!("The current version is {}", current_version),
+        };
+        ApiError::new(message, self.mismatch_status).with_code(ErrorCode::VersionConflict)
+    }
+}
+
+/// Resources with a version that updates are checked against
+pub trait Versioned {
+    fn version(&self) -> Option<i32>;
+}
+
+/// Versioned models carry their version in a `version` field
+macro_rules! versioned_by_field {
+    ($($model:ty),* $(,)?) => {
+        $(
+            impl Versioned for $model {
+                fn version(&self) -> Option<i32> {
+                    self.version
+                }
+            }
+        )*
+    };
+}
+
+versioned_by_field!(BlogItem, ProjectItem, ShopItem);
+
+/// A successful response for a versioned resource, with its version as the ETag
+///
+/// The ETag can be sent back as `If-Match` to update the resource again.
+pub struct WithVersion<T>(pub T);
+
+impl<'r, T: Serialize + Versioned> Responder<'r, 'static> for WithVersion<T> {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let etag = self.0.version().map(version_etag);
+        let mut response = ApiResponse::success(self.0).respond_to(req)?;
+        if let Some(etag) = etag {
+            response.set_header(Header::new("ETag", etag));
+        }
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn if_match_accepts_version_etags() {
+        assert_eq!(parse_if_match(&version_etag(3)), Some(Some(3)));
+        assert_eq!(parse_if_match("\"v3\""), Some(Some(3)));
+        assert_eq!(parse_if_match("W/\"v3\""), Some(Some(3)));
+        assert_eq!(parse_if_match(" \"v12\" "), Some(Some(12)));
+    }
+
+    #[test]
+    fn if_match_accepts_bare_versions() {
+        assert_eq!(parse_if_match("\"3\""), Some(Some(3)));
+        assert_eq!(parse_if_match("3"), Some(Some(3)));
+    }
+
+    #[test]
+    fn if_match_star_matches_any_version() {
+        assert_eq!(parse_if_match("*"), Some(None));
+        assert_eq!(parse_if_match(" * "), Some(None));
+    }
+
+    #[test]
+    fn if_match_without_a_version_is_rejected() {
+        assert_eq!(parse_if_match(""), None);
+        assert_eq!(parse_if_match("\"0123456789abcdef\""), None);
+        assert_eq!(parse_if_match("\"v\""), None);
+        assert_eq!(parse_if_match("\"v3\", \"v4\""), None);
+    }
+
+    #[test]
+    fn header_takes_precedence_over_the_body_version() {
+        let if_match = IfMatch(Some("\"v2\"".to_string()));
+        let expected = ExpectedVersion::resolve(&if_match, Some(5)).unwrap();
+
+        assert_eq!(expected.version, Some(2));
+        assert_eq!(expected.mismatch_status, Status::PreconditionFailed);
+    }
+
+    #[test]
+    fn body_version_is_used_without_a_header() {
+        let expected = ExpectedVersion::resolve(&IfMatch(None), Some(5)).unwrap();
+
+        assert_eq!(expected.version, Some(5));
+        assert_eq!(expected.mismatch_status, Status::Conflict);
+    }
+
+    #[test]
+    fn updates_without_a_version_are_refused() {
+        let missing = ExpectedVersion::resolve(&IfMatch(None), None)
+            .err()
+            .unwrap();
+        assert_eq!(missing.status, Status::PreconditionRequired);
+
+        let invalid = IfMatch(Some("\"abc\"".to_string()));
+        let invalid = ExpectedVersion::resolve(&invalid, None).err().unwrap();
+        assert_eq!(invalid.status, Status::PreconditionFailed);
+        assert_eq!(invalid.code, ErrorCode::VersionConflict);
+    }
+}
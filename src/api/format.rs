@@ -0,0 +1,187 @@
+//! Response formats
+//!
+//! Responses are serialized in the format the client asks for, either with
+//! a `?format=` query parameter or through the `Accept` header, and fall back
+//! to JSON. JSON, MessagePack and YAML carry the full response envelope. CSV
+//! is only offered for lists: it renders the `data` array with one row per
+//! item, and nested values are written as JSON.
+
+use rocket::http::{ContentType, Header, Status};
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use rocket::serde::json::{self, Value};
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::fmt::Display;
+use std::io::Cursor;
+use tracing::error;
+
+use super::ApiError;
+
+/// A serialization format clients can ask for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    MessagePack,
+    Csv,
+    Yaml,
+}
+
+impl Format {
+    /// Parses the value of a `?format=` parameter
+    fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "json" => Some(Format::Json),
+            "msgpack" | "messagepack" => Some(Format::MessagePack),
+            "csv" => Some(Format::Csv),
+            "yaml" | "yml" => Some(Format::Yaml),
+            _ => None,
+        }
+    }
+
+    /// Maps an accepted media type onto a format, if it is supported
+    fn from_media_type(top: &str, sub: &str) -> Option<Self> {
+        match (top, sub) {
+            ("application", "json") | ("application", "*") | ("*", "*") => Some(Format::Json),
+            ("application", "msgpack" | "x-msgpack" | "vnd.msgpack") => Some(Format::MessagePack),
+            ("text", "csv") => Some(Format::Csv),
+            ("application", "yaml" | "x-yaml") | ("text", "yaml") => Some(Format::Yaml),
+            _ => None,
+        }
+    }
+
+    /// Picks the format for the response to a request
+    ///
+    /// `?format=` wins over `Accept`. Within `Accept`, the supported media
+    /// type with the highest quality is used, and JSON if none is supported.
+    ///
+    /// # Returns
+    /// * `ApiError` - 406 if `?format=` names an unsupported format
+    pub fn negotiate(req: &Request<'_>) -> Result<Self, ApiError> {
+        if let Some(Ok(name)) = req.query_value::<&str>("format") {
+            return Self::from_name(name).ok_or_else(|| {
+                ApiError::new(
+                    format!("Unsupported format '{}', expected json, msgpack, csv or yaml", name),
+                    Status::NotAcceptable,
+                )
+            });
+        }
+
+        let mut accepted: Vec<_> = req
+            .accept()
+            .map(|accept| accept.iter().collect())
+            .unwrap_or_default();
+        accepted.sort_by(|a, b| {
+            b.weight_or(1.0)
+                .partial_cmp(&a.weight_or(1.0))
+                .unwrap_or(Ordering::Equal)
+        });
+
+        Ok(accepted
+            .into_iter()
+            .find_map(|media_type| {
+                Self::from_media_type(
+                    &media_type.top().as_str().to_ascii_lowercase(),
+                    &media_type.sub().as_str().to_ascii_lowercase(),
+                )
+            })
+            .unwrap_or(Format::Json))
+    }
+
+    /// Serializes a value in this format
+    ///
+    /// # Returns
+    /// * `ApiError` - 406 if CSV was asked for but the value holds no list
+    pub fn render<T: Serialize>(self, value: &T) -> Result<(ContentType, Vec<u8>), ApiError> {
+        match self {
+            Format::Json => {
+                let body = json::to_string(value).map_err(serialize_error)?;
+                Ok((ContentType::JSON, body.into_bytes()))
+            }
+            Format::MessagePack => {
+                let body = rmp_serde::to_vec_named(value).map_err(serialize_error)?;
+                Ok((ContentType::MsgPack, body))
+            }
+            Format::Csv => Ok((ContentType::CSV, to_csv(value)?)),
+            Format::Yaml => {
+                let body = serde_yaml::to_string(value).map_err(serialize_error)?;
+                Ok((ContentType::new("application", "yaml"), body.into_bytes()))
+            }
+        }
+    }
+}
+
+fn serialize_error<E: Display>(error: E) -> ApiError {
+    error!(%error, "Failed to serialize response");
+    ApiError::new("Failed to serialize response", Status::InternalServerError)
+}
+
+/// Renders a single CSV cell, writing nested values as JSON
+fn csv_cell(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(text)) => text.clone(),
+        Some(other) => other.to_string(),
+    }
+}
+
+/// Writes the `data` list of a response as CSV
+///
+/// The columns are the union of the fields of all rows. Lists of plain
+/// values get a single `value` column.
+fn to_csv<T: Serialize>(value: &T) -> Result<Vec<u8>, ApiError> {
+    let value = json::to_value(value).map_err(serialize_error)?;
+    let rows = match value.get("data").unwrap_or(&value) {
+        Value::Array(rows) => rows,
+        _ => {
+            return Err(ApiError::new(
+                "CSV is only available for list endpoints",
+                Status::NotAcceptable,
+            ))
+        }
+    };
+
+    let mut columns: Vec<&str> = Vec::new();
+    for row in rows {
+        if let Value::Object(fields) = row {
+            for field in fields.keys() {
+                if !columns.contains(&field.as_str()) {
+                    columns.push(field);
+                }
+            }
+        }
+    }
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    if columns.is_empty() {
+        writer.write_record(["value"]).map_err(serialize_error)?;
+        for row in rows {
+            writer.write_record([csv_cell(Some(row))]).map_err(serialize_error)?;
+        }
+    } else {
+        writer.write_record(&columns).map_err(serialize_error)?;
+        for row in rows {
+            let record = columns.iter().map(|column| csv_cell(row.get(*column)));
+            writer.write_record(record).map_err(serialize_error)?;
+        }
+    }
+    writer.into_inner().map_err(serialize_error)
+}
+
+/// A response body serialized in the format negotiated with the client
+pub struct Formatted<T>(pub T);
+
+impl<'r, T: Serialize> Responder<'r, 'static> for Formatted<T> {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let (content_type, body) = match Format::negotiate(req).and_then(|format| format.render(&self.0)) {
+            Ok(rendered) => rendered,
+            Err(error) => return error.respond_to(req),
+        };
+
+        Response::build()
+            .header(content_type)
+            .header(Header::new("Vary", "Accept"))
+            .sized_body(body.len(), Cursor::new(body))
+            .ok()
+    }
+}
@@ -3,20 +3,40 @@
 //! This module provides standardized response types and error handling
 //! for the API endpoints. It includes structures for successful responses
 //! and error responses, along with helper methods for creating them.
+//! 
+//! Errors carry a stable [`ErrorCode`] that clients can match on instead of
+//! the human-readable message. They are rendered as JSON by default, or as
+//! RFC 7807 problem details when the client accepts `application/problem+json`.
+//! 
+//! Responses are serialized in the format negotiated by [`format::Format`].
+//! Errors follow it too, except that they fall back to JSON instead of CSV.
 
-use rocket::serde::json::Json;
-use rocket::http::Status;
+use rocket::http::{ContentType, Status};
 use rocket::response::status;
 use rocket::request::Request;
 use rocket::response::{self, Responder};
-use serde::{Serialize, Deserialize};
+use serde::{Serialize, Serializer, Deserialize};
+use sqlx::error::ErrorKind;
 use std::fmt::Debug;
+use tracing::{debug, error};
+use utoipa::ToSchema;
+
+use crate::db::pagination::{Page, PageInfo};
+use format::{Format, Formatted};
+use validation::BodyErrors;
+
+pub mod admin;
+pub mod cache;
+pub mod concurrency;
+pub mod format;
+pub mod openapi;
+pub mod validation;
 
 /// Standard API response wrapper for successful operations
 /// 
 /// # Type Parameters
 /// * `T` - The type of data being returned
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct ApiResponse<T> {
     /// Indicates if the operation was successful
     pub success: bool,
@@ -24,20 +44,137 @@ pub struct ApiResponse<T> {
     pub message: Option<String>,
     /// The actual data being returned
     pub data: Option<T>,
+    /// Pagination metadata, present only on paginated list responses
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pagination: Option<PageInfo>,
+}
+
+/// Stable, machine-readable error codes
+/// 
+/// These are part of the public API: existing variants must keep their
+/// serialized names so that clients can rely on them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    /// The request is malformed
+    BadRequest,
+    /// One or more fields failed validation
+    ValidationFailed,
+    /// The request refers to a record that does not exist
+    InvalidReference,
+    /// The requested sort field is not supported by the endpoint
+    InvalidSortField,
+    /// The requested resource does not exist
+    NotFound,
+    /// A record with the same unique value already exists
+    AlreadyExists,
+    /// The request conflicts with the current state of the resource
+    Conflict,
+    /// The update was based on an outdated version of the resource
+    VersionConflict,
+    /// The update did not say which version of the resource it is based on
+    PreconditionRequired,
+    /// The idempotency key was already used for a different request
+    IdempotencyKeyReused,
+    /// The operation was skipped because an earlier operation of its batch failed
+    NotExecuted,
+    /// The client sent too many requests and should retry after `Retry-After` seconds
+    RateLimited,
+    /// Too many failed logins locked the account for `Retry-After` seconds
+    AccountLocked,
+    /// The route requires credentials the request did not send
+    Unauthorized,
+    /// The database failed to process the request
+    DatabaseError,
+    /// An unexpected server error occurred
+    InternalError,
+}
+
+impl From<Status> for ErrorCode {
+    fn from(status: Status) -> Self {
+        match status.code {
+            400 => ErrorCode::BadRequest,
+            401 => ErrorCode::Unauthorized,
+            404 => ErrorCode::NotFound,
+            409 => ErrorCode::Conflict,
+            412 => ErrorCode::VersionConflict,
+            422 => ErrorCode::ValidationFailed,
+            423 => ErrorCode::AccountLocked,
+            424 => ErrorCode::NotExecuted,
+            428 => ErrorCode::PreconditionRequired,
+            429 => ErrorCode::RateLimited,
+            _ => ErrorCode::InternalError,
+        }
+    }
+}
+
+/// A validation error tied to a single input field
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct FieldError {
+    /// Name of the offending field
+    pub field: String,
+    /// What is wrong with the field's value
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
 }
 
 /// Error response structure for API failures
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct ApiError {
     /// Always false for error responses
     pub success: bool,
     /// Error message describing what went wrong
     pub message: String,
+    /// Machine-readable error code
+    pub code: ErrorCode,
     /// HTTP status code for the error
-    #[serde(skip)]
+    #[serde(serialize_with = "serialize_status")]
+    #[schema(value_type = u16)]
     status: Status,
+    /// Field-level validation errors, if any
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
 }
 
+/// RFC 7807 rendering of an ApiError
+#[derive(Serialize)]
+struct ProblemDetails<'a> {
+    #[serde(rename = "type")]
+    problem_type: &'static str,
+    title: &'static str,
+    #[serde(serialize_with = "serialize_status")]
+    status: Status,
+    detail: &'a str,
+    instance: String,
+    code: ErrorCode,
+    #[serde(skip_serializing_if = "<[FieldError]>::is_empty")]
+    errors: &'a [FieldError],
+}
+
+fn serialize_status<S: Serializer>(status: &Status, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u16(status.code)
+}
+
+/// Unique constraints that map onto a single input field
+const UNIQUE_CONSTRAINT_FIELDS: &[(&str, &str)] = &[
+    ("app_user_username_key", "username"),
+    ("app_user_email_key", "email"),
+    ("shop_item_iname_key", "iname"),
+    ("blog_item_blog_title_key", "blog_title"),
+    ("project_item_title_key", "title"),
+    ("tag_text_key", "text"),
+    ("tag_text_lower_key", "text"),
+    ("category_name_key", "name"),
+];
+
 impl<T> ApiResponse<T> {
     /// Creates a new successful API response
     /// 
@@ -45,12 +182,31 @@ impl<T> ApiResponse<T> {
     /// * `data` - The data to be included in the response
     /// 
     /// # Returns
-    /// * Json wrapped ApiResponse with the provided data
-    pub fn success(data: T) -> Json<Self> {
-        Json(Self {
+    /// * ApiResponse with the provided data, in the negotiated format
+    pub fn success(data: T) -> Formatted<Self> {
+        Formatted(Self {
             success: true,
             message: None,
             data: Some(data),
+            pagination: None,
+        })
+    }
+}
+
+impl<T> ApiResponse<Vec<T>> {
+    /// Creates a new successful API response for a page of a list
+    /// 
+    /// # Arguments
+    /// * `page` - The page of items and its pagination metadata
+    /// 
+    /// # Returns
+    /// * ApiResponse with the items as data, in the negotiated format
+    pub fn paginated(page: Page<T>) -> Formatted<Self> {
+        Formatted(Self {
+            success: true,
+            message: None,
+            data: Some(page.items),
+            pagination: Some(page.info),
         })
     }
 }
@@ -68,11 +224,93 @@ impl ApiError {
         Self {
             success: false,
             message: message.into(),
+            code: ErrorCode::from(status),
             status,
+            errors: Vec::new(),
+        }
+    }
+
+    /// Replaces the error code derived from the HTTP status
+    pub fn with_code(mut self, code: ErrorCode) -> Self {
+        self.code = code;
+        self
+    }
+
+    /// Attaches field-level validation errors
+    pub fn with_field_errors(mut self, errors: Vec<FieldError>) -> Self {
+        self.errors = errors;
+        self
+    }
+
+    /// Maps a database error onto the matching HTTP status and error code
+    /// 
+    /// Missing rows become 404, unique violations 409, foreign-key violations
+    /// and other constraint failures 422, and anything else 500. The database
+    /// error is logged, at `error` level if it caused a 500 and at `debug`
+    /// level otherwise, since it is not passed on to the client.
+    /// 
+    /// # Arguments
+    /// * `error` - The database error
+    /// * `message` - Error message describing what went wrong
+    pub fn from_sqlx(error: sqlx::Error, message: impl Into<String>) -> Self {
+        let api_error = Self::classify_sqlx(&error, message.into());
+        if api_error.status.class().is_server_error() {
+            error!(%error, "{}", api_error.message);
+        } else {
+            debug!(%error, status = api_error.status.code, "{}", api_error.message);
+        }
+        api_error
+    }
+
+    fn classify_sqlx(error: &sqlx::Error, message: String) -> Self {
+        let database_error = match error {
+            sqlx::Error::RowNotFound => {
+                return Self::new(message, Status::NotFound);
+            }
+            sqlx::Error::Database(database_error) => database_error,
+            _ => {
+                return Self::new(message, Status::InternalServerError);
+            }
+        };
+
+        match database_error.kind() {
+            ErrorKind::UniqueViolation => {
+                let errors = database_error
+                    .constraint()
+                    .and_then(|constraint| {
+                        UNIQUE_CONSTRAINT_FIELDS
+                            .iter()
+                            .find(|(name, _)| *name == constraint)
+                    })
+                    .map(|(_, field)| vec![FieldError::new(*field, "already exists")])
+                    .unwrap_or_default();
+
+                Self::new(message, Status::Conflict)
+                    .with_code(ErrorCode::AlreadyExists)
+                    .with_field_errors(errors)
+            }
+            ErrorKind::ForeignKeyViolation => {
+                Self::new(message, Status::UnprocessableEntity).with_code(ErrorCode::InvalidReference)
+            }
+            ErrorKind::NotNullViolation | ErrorKind::CheckViolation => {
+                Self::new(message, Status::UnprocessableEntity)
+            }
+            _ => Self::new(message, Status::InternalServerError).with_code(ErrorCode::DatabaseError),
         }
     }
 }
 
+/// Whether the client asked for RFC 7807 problem details
+fn wants_problem_json(req: &Request<'_>) -> bool {
+    req.accept()
+        .map(|accept| {
+            accept
+                .media_types()
+                .any(|media_type| media_type.top() == "application" && media_type.sub() == "problem+json")
+        })
+        .unwrap_or(false)
+}
+
 /// Implementation of Rocket's Responder trait for ApiError
 /// 
 /// This allows ApiError to be returned directly from route handlers
@@ -80,12 +318,47 @@ impl ApiError {
 impl<'r> Responder<'r, 'static> for ApiError {
     fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
         let status = self.status;
-        let json = Json(self);
-        status::Custom(status, json).respond_to(req)
+
+        if wants_problem_json(req) {
+            let problem = ProblemDetails {
+                problem_type: "about:blank",
+                title: status.reason().unwrap_or("Unknown Error"),
+                status,
+                detail: &self.message,
+                instance: req.uri().path().to_string(),
+                code: self.code,
+                errors: &self.errors,
+            };
+            let body = rocket::serde::json::to_string(&problem).map_err(|_| Status::InternalServerError)?;
+            let content_type = ContentType::new("application", "problem+json");
+            return status::Custom(status, (content_type, body)).respond_to(req);
+        }
+
+        let format = match Format::negotiate(req) {
+            Ok(Format::Csv) | Err(_) => Format::Json,
+            Ok(format) => format,
+        };
+        let (content_type, body) = format.render(&self).map_err(|_| Status::InternalServerError)?;
+        status::Custom(status, (content_type, body)).respond_to(req)
     }
 }
 
+/// Renders errors raised outside of route handlers, such as unmatched
+/// routes or malformed JSON bodies, in the same format as ApiError
+/// 
+/// Field errors left behind by a rejected `Validated` body are included.
+#[catch(default)]
+pub fn default_catcher(status: Status, req: &Request<'_>) -> ApiError {
+    let BodyErrors(errors) = req.local_cache(BodyErrors::default);
+    let message = if status == Status::UnprocessableEntity && !errors.is_empty() {
+        "Validation failed"
+    } else {
+        status.reason().unwrap_or("Unknown Error")
+    };
+    ApiError::new(message, status).with_field_errors(errors.clone())
+}
+
 /// Type alias for the standard result type used by API endpoints
 /// 
 /// This type combines ApiResponse for success cases and ApiError for failures
-pub type ApiResult<T> = Result<Json<ApiResponse<T>>, ApiError>;
+pub type ApiResult<T> = Result<Formatted<ApiResponse<T>>, ApiError>;
@@ -0,0 +1,85 @@
+//! OpenAPI document for the JSON API
+//!
+//! The document is generated from the `#[utoipa::path]` annotations on the
+//! route handlers and the `ToSchema` derives on the models. New routes must
+//! be listed in [`ApiDoc`] to show up in it.
+
+use utoipa::OpenApi;
+
+use crate::routes;
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Projects Rust Server API",
+        description = "Shop, blog, project and tag endpoints used by the frontends"
+    ),
+    paths(
+        routes::shop::shop_items,
+        routes::shop::shop_items_by_tag,
+        routes::shop::create_shop_item,
+        routes::shop::update_shop_item,
+        routes::shop::delete_shop_item,
+        routes::shop::shop_item_images,
+        routes::shop::create_shop_item_image,
+        routes::shop::shop_item_descs,
+        routes::shop::create_shop_item_desc,
+        routes::shop::create_shop_item_desc_many,
+        routes::blog::blogs,
+        routes::blog::blogs_by_tag,
+        routes::blog::blog_contents,
+        routes::blog::create_blog,
+        routes::blog::update_blog,
+        routes::blog::delete_blog,
+        routes::project::projects,
+        routes::project::projects_by_tag,
+        routes::project::add_tags_to_project,
+        routes::project::project_descs,
+        routes::project::create_project_item,
+        routes::project::update_project_item,
+        routes::project::delete_project_item,
+        routes::project::create_project_desc,
+        routes::project::create_project_desc_many,
+        routes::tag::tags,
+        routes::tag::tag_cloud,
+        routes::tag::tags_autocomplete,
+        routes::tag::tag_category,
+        routes::tag::remove_tag_category,
+        routes::tag::tag_project,
+        routes::tag::create_tag,
+        routes::tag::rename_tag,
+        routes::tag::merge_tags,
+        routes::tag::delete_tag,
+        routes::tag::tags_by_project,
+        routes::tag::tags_by_category,
+        routes::tag::tags_by_blog,
+        routes::tag::tags_by_shop_item,
+        routes::tag::tagged,
+        routes::tag::tag_blog,
+        routes::tag::untag_blog,
+        routes::tag::tag_shop_item,
+        routes::tag::untag_shop_item,
+        routes::category::categories,
+        routes::category::create_category,
+        routes::category::update_category,
+        routes::category::delete_category,
+        routes::user::users,
+        routes::user::create_user,
+        routes::user::delete_user,
+        routes::batch::batch,
+        routes::admin::audit_log,
+        routes::admin::restore,
+        routes::admin::purge,
+    ),
+    tags(
+        (name = "shop", description = "Shop items, their images and descriptions"),
+        (name = "blog", description = "Blog posts and their content blocks"),
+        (name = "project", description = "Portfolio projects and their descriptions"),
+        (name = "tag", description = "Tags and their links to other content"),
+        (name = "category", description = "Categories that tags are grouped under"),
+        (name = "user", description = "User accounts"),
+        (name = "batch", description = "Several operations in one transaction"),
+        (name = "admin", description = "Audit and maintenance, requiring the admin key"),
+    )
+)]
+pub struct ApiDoc;
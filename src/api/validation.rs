@@ -0,0 +1,355 @@
+//! Request body validation
+//!
+//! Models describe their rules by implementing [`Validate`], usually by
+//! chaining the checks of a [`Validator`]. Routes then accept
+//! `Validated<T>` instead of `Json<T>`: the body is deserialized as JSON and
+//! validated before the handler runs, and any failure is answered with 422
+//! and a list of per-field errors.
+
+use rocket::data::{self, Data, FromData, Limits, Outcome};
+use rocket::http::Status;
+use rocket::request::Request;
+use rocket::serde::json::{self, serde_json::error::Category};
+use serde::Deserialize;
+use std::ops::Deref;
+
+use super::FieldError;
+
+/// Types whose values can be checked before they are used
+pub trait Validate {
+    /// Returns every rule the value breaks, or an empty list if it is valid
+    fn validate(&self) -> Vec<FieldError>;
+}
+
+/// A list is valid when each of its elements is, with errors reported as `[index].field`
+impl<T: Validate> Validate for Vec<T> {
+    fn validate(&self) -> Vec<FieldError> {
+        Validator::new().each("", self).finish()
+    }
+}
+
+/// Collects field errors from a chain of rules
+#[derive(Default)]
+pub struct Validator {
+    errors: Vec<FieldError>,
+}
+
+impl Validator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Applies a custom rule, recording `message` when `valid` is false
+    pub fn check(mut self, field: &str, valid: bool, message: &str) -> Self {
+        if !valid {
+            self.errors.push(FieldError::new(field, message));
+        }
+        self
+    }
+
+    /// The value must contain something other than whitespace
+    pub fn not_blank(self, field: &str, value: &str) -> Self {
+        self.check(field, !value.trim().is_empty(), "must not be blank")
+    }
+
+    /// The value must be at most `max` characters long
+    pub fn max_length(self, field: &str, value: &str, max: usize) -> Self {
+        let message = format!("must be at most {} characters long", max);
+        self.check(field, value.chars().count() <= max, &message)
+    }
+
+    /// The value must be at least `min` characters long
+    pub fn min_length(self, field: &str, value: &str, min: usize) -> Self {
+        let message = format!("must be at least {} characters long", min);
+        self.check(field, value.chars().count() >= min, &message)
+    }
+
+    /// The value must be a finite number no smaller than `min`
+    pub fn min_value(self, field: &str, value: f32, min: f32) -> Self {
+        let message = format!("must be a number no smaller than {}", min);
+        self.check(field, value.is_finite() && value >= min, &message)
+    }
+
+    /// The value must be a positive ID
+    pub fn id(self, field: &str, value: i32) -> Self {
+        self.check(field, value > 0, "must be a positive ID")
+    }
+
+    /// The optional ID must be present and positive
+    pub fn required_id(self, field: &str, value: Option<i32>) -> Self {
+        match value {
+            Some(value) => self.id(field, value),
+            None => self.check(field, false, "is required"),
+        }
+    }
+
+    /// The value must be an absolute http(s) URL or a root-relative path
+    pub fn url(self, field: &str, value: &str) -> Self {
+        self.check(field, is_url(value), "must be an http(s) URL or a path starting with /")
+    }
+
+    /// The value must look like an email address
+    pub fn email(self, field: &str, value: &str) -> Self {
+        self.check(field, is_email(value), "must be a valid email address")
+    }
+
+    /// Validates a nested value, prefixing its field names with `field`
+    pub fn nested<T: Validate>(mut self, field: &str, value: &T) -> Self {
+        for error in value.validate() {
+            self.errors.push(FieldError::new(
+                format!("{}.{}", field, error.field),
+                error.message,
+            ));
+        }
+        self
+    }
+
+    /// Validates every element of a list, prefixing field names with `field[index]`
+    pub fn each<T: Validate>(self, field: &str, values: &[T]) -> Self {
+        values
+            .iter()
+            .enumerate()
+            .fold(self, |validator, (index, value)| {
+                validator.nested(&format!("{}[{}]", field, index), value)
+            })
+    }
+
+    pub fn finish(self) -> Vec<FieldError> {
+        self.errors
+    }
+}
+
+fn is_url(value: &str) -> bool {
+    if value.chars().any(char::is_whitespace) {
+        return false;
+    }
+    if value.starts_with('/') && !value.starts_with("//") {
+        return true;
+    }
+    ["http://", "https://"].iter().any(|scheme| {
+        value
+            .strip_prefix(scheme)
+            .and_then(|rest| rest.split('/').next())
+            .map(|host| !host.is_empty())
+            .unwrap_or(false)
+    })
+}
+
+fn is_email(value: &str) -> bool {
+    if value.chars().any(char::is_whitespace) {
+        return false;
+    }
+    match value.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && !domain.contains('@')
+                && domain.contains('.')
+                && !domain.starts_with('.')
+                && !domain.ends_with('.')
+        }
+        None => false,
+    }
+}
+
+/// Field errors of a rejected request body, kept in the request's local
+/// cache so the error catcher can include them in the response
+#[derive(Default)]
+pub struct BodyErrors(pub Vec<FieldError>);
+
+/// The raw request body read by [`Validated`], kept in the request's local
+/// cache for the lifetime of the request
+#[derive(Default)]
+pub struct RawBody(pub String);
+
+/// A JSON request body that has passed validation
+///
+/// Dereferences to the inner value, so handlers can use it like `Json<T>`.
+pub struct Validated<T>(pub T);
+
+impl<T> Deref for Validated<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+#[rocket::async_trait]
+impl<'r, T> FromData<'r> for Validated<T>
+where
+    T: Deserialize<'r> + Validate,
+{
+    type Error = Vec<FieldError>;
+
+    async fn from_data(req: &'r Request<'_>, data: Data<'r>) -> data::Outcome<'r, Self> {
+        let limit = req.limits().get("json").unwrap_or(Limits::JSON);
+        let body = match data.open(limit).into_string().await {
+            Ok(body) if body.is_complete() => body.into_inner(),
+            Ok(_) => return body_error(req, Status::PayloadTooLarge, "data limit exceeded"),
+            Err(error) => return body_error(req, Status::BadRequest, error.to_string()),
+        };
+        let RawBody(body) = req.local_cache(|| RawBody(body));
+
+        match json::from_str::<T>(body) {
+            Ok(value) => {
+                let errors = value.validate();
+                if errors.is_empty() {
+                    Outcome::Success(Validated(value))
+                } else {
+                    req.local_cache(|| BodyErrors(errors.clone()));
+                    Outcome::Error((Status::UnprocessableEntity, errors))
+                }
+            }
+            Err(error) if error.classify() == Category::Data => {
+                body_error(req, Status::UnprocessableEntity, error.to_string())
+            }
+            Err(error) => body_error(req, Status::BadRequest, error.to_string()),
+        }
+    }
+}
+
+/// Rejects a body that could not be read or parsed as JSON
+fn body_error<'r, T>(
+    req: &'r Request<'_>,
+    status: Status,
+    message: impl Into<String>,
+) -> data::Outcome<'r, T, Vec<FieldError>> {
+    let errors = vec![FieldError::new("body", message)];
+    req.local_cache(|| BodyErrors(errors.clone()));
+    Outcome::Error((status, errors))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Item {
+        name: String,
+    }
+
+    impl Validate for Item {
+        fn validate(&self) -> Vec<FieldError> {
+            Validator::new().not_blank("name", &self.name).finish()
+        }
+    }
+
+    fn item(name: &str) -> Item {
+        Item {
+            name: name.to_string(),
+        }
+    }
+
+    fn fields(errors: Vec<FieldError>) -> Vec<String> {
+        errors.into_iter().map(|error| error.field).collect()
+    }
+
+    #[test]
+    fn valid_values_have_no_errors() {
+        let errors = Validator::new()
+            .not_blank("name", "rust")
+            .min_length("name", "rust", 4)
+            .max_length("name", "rust", 4)
+            .min_value("price", 0.0, 0.0)
+            .id("id", 1)
+            .required_id("blog_id", Some(2))
+            .url("link", "https://example.com/a.png")
+            .email("email", "a@example.com")
+            .finish();
+
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn every_broken_rule_is_reported() {
+        let errors = Validator::new()
+            .not_blank("name", " \t")
+            .max_length("text", "abcdef", 5)
+            .id("id", 0)
+            .required_id("blog_id", None)
+            .finish();
+
+        assert_eq!(fields(errors), ["name", "text", "id", "blog_id"]);
+    }
+
+    #[test]
+    fn lengths_count_characters_not_bytes() {
+        assert!(Validator::new()
+            .max_length("name", "ÄÖÜ", 3)
+            .finish()
+            .is_empty());
+        assert_eq!(
+            Validator::new().min_length("name", "ÄÖ", 3).finish().len(),
+            1
+        );
+    }
+
+    #[test]
+    fn min_value_rejects_non_finite_numbers() {
+        assert_eq!(
+            Validator::new()
+                .min_value("price", -0.5, 0.0)
+                .finish()
+                .len(),
+            1
+        );
+        assert_eq!(
+            Validator::new()
+                .min_value("price", f32::NAN, 0.0)
+                .finish()
+                .len(),
+            1
+        );
+        assert_eq!(
+            Validator::new()
+                .min_value("price", f32::INFINITY, 0.0)
+                .finish()
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn urls_must_be_http_or_root_relative() {
+        assert!(is_url("http://example.com"));
+        assert!(is_url("https://example.com/img.png"));
+        assert!(is_url("/static/img.png"));
+
+        assert!(!is_url("//example.com/img.png"));
+        assert!(!is_url("https://"));
+        assert!(!is_url("ftp://example.com"));
+        assert!(!is_url("javascript:alert(1)"));
+        assert!(!is_url("img.png"));
+        assert!(!is_url("https://example.com/a b.png"));
+    }
+
+    #[test]
+    fn emails_need_a_local_part_and_a_dotted_domain() {
+        assert!(is_email("a@example.com"));
+        assert!(is_email("first.last+tag@mail.example.org"));
+
+        assert!(!is_email("example.com"));
+        assert!(!is_email("@example.com"));
+        assert!(!is_email("a@localhost"));
+        assert!(!is_email("a@.example.com"));
+        assert!(!is_email("a@example.com."));
+        assert!(!is_email("a@b@example.com"));
+        assert!(!is_email("a @example.com"));
+    }
+
+    #[test]
+    fn nested_errors_are_prefixed_with_their_path() {
+        let errors = Validator::new()
+            .nested("author", &item(""))
+            .each("tags", &[item("rust"), item(" ")])
+            .finish();
+
+        assert_eq!(fields(errors), ["author.name", "tags[1].name"]);
+    }
+
+    #[test]
+    fn lists_report_errors_by_index() {
+        let errors = vec![item(""), item("rust"), item("")].validate();
+
+        assert_eq!(fields(errors), ["[0].name", "[2].name"]);
+    }
+}
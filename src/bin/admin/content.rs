@@ -0,0 +1,495 @@
+//! Seeding, exporting and importing content
+//!
+//! An export is an [`Archive`] of every category, tag, blog, project and shop
+//! item that is not deleted, written as JSON. Items refer to their tags and
+//! tags to their categories by name, so an archive can be imported into
+//! another database, where they get new IDs. Imports create everything in a
+//! single transaction, and reuse the categories and tags that already exist.
+//! Seeding imports a small built-in archive of demo content.
+
+use either::{Either, Left, Right};
+use projects_rust_server::api::validation::Validate;
+use projects_rust_server::db::audit;
+use projects_rust_server::db::blog_item::{BlogItem, Content, ContentType};
+use projects_rust_server::db::pagination::{Page, PageParams, MAX_LIMIT};
+use projects_rust_server::db::project_item::{DescItem, ProjectItem};
+use projects_rust_server::db::shop_item::{ShopImage, ShopItem, ShopItemDesc};
+use projects_rust_server::db::stamps::Stamps;
+use projects_rust_server::db::tag::{BlogToTag, ProjectToTechTag, ShopItemToTag, Tag};
+use projects_rust_server::db::tag_category::TagCategory;
+use rocket::serde::json;
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::future::Future;
+
+use crate::{check, CliResult};
+
+/// All content of a database, as written by `export` and read by `import`
+#[derive(Serialize, Deserialize)]
+struct Archive {
+    categories: Vec<TagCategory>,
+    tags: Vec<ArchivedTag>,
+    blogs: Vec<Tagged<BlogItem>>,
+    projects: Vec<Tagged<ProjectItem>>,
+    shop_items: Vec<Tagged<ArchivedShopItem>>,
+}
+
+/// A tag and the names of its categories
+#[derive(Serialize, Deserialize)]
+struct ArchivedTag {
+    text: String,
+    categories: Vec<String>,
+}
+
+/// An item and the text of its tags
+#[derive(Serialize, Deserialize)]
+struct Tagged<T> {
+    #[serde(flatten)]
+    item: T,
+    tags: Vec<String>,
+}
+
+/// A shop item with its images and descriptions, which are stored apart from it
+#[derive(Serialize, Deserialize)]
+struct ArchivedShopItem {
+    #[serde(flatten)]
+    item: ShopItem,
+    images: Vec<ShopImage>,
+    descriptions: Vec<ShopItemDesc>,
+}
+
+pub async fn export(pool: &PgPool, path: &str) -> CliResult {
+    let archive = read_archive(pool).await?;
+    fs::write(path, json::to_pretty_string(&archive)?)?;
+
+    println!("Exported {} to {path}", summary(&archive));
+    Ok(())
+}
+
+pub async fn import(pool: &PgPool, path: &str) -> CliResult {
+    let archive: Archive = json::from_str(&fs::read_to_string(path)?)?;
+    create(pool, &archive).await?;
+
+    println!("Imported {} from {path}", summary(&archive));
+    Ok(())
+}
+
+pub async fn seed(pool: &PgPool) -> CliResult {
+    let archive = demo_archive();
+    create(pool, &archive).await?;
+
+    println!("Seeded {}", summary(&archive));
+    Ok(())
+}
+
+fn summary(archive: &Archive) -> String {
+    format!(
+        "{} categories, {} tags, {} blogs, {} projects and {} shop items",
+        archive.categories.len(),
+        archive.tags.len(),
+        archive.blogs.len(),
+        archive.projects.len(),
+        archive.shop_items.len(),
+    )
+}
+
+/// Loads every item that is not deleted, with its children and tags
+async fn read_archive(pool: &PgPool) -> CliResult<Archive> {
+    let mut conn = pool.acquire().await?;
+    let categories = TagCategory::get_all(&mut conn).await?;
+
+    let tags = fetch_all(|params| async move {
+        let mut conn = pool.acquire().await.map_err(Left)?;
+        Tag::get_all(&mut conn, &params, None).await
+    })
+    .await?;
+    let blogs = fetch_all(|params| async move {
+        let mut conn = pool.acquire().await.map_err(Left)?;
+        BlogItem::get_all(&mut conn, &params, None).await
+    })
+    .await?;
+    let projects = fetch_all(|params| async move {
+        let mut conn = pool.acquire().await.map_err(Left)?;
+        ProjectItem::get_all(&mut conn, &params, None).await
+    })
+    .await?;
+    let shop_items = fetch_all(|params| async move {
+        let mut conn = pool.acquire().await.map_err(Left)?;
+        ShopItem::get_all(&mut conn, &params, None, None, None).await
+    })
+    .await?;
+
+    let blog_ids: Vec<i32> = blogs.iter().filter_map(|blog| blog.id).collect();
+    let mut contents = group_by(
+        Content::get_all_from_blogs(pool, &blog_ids).await?,
+        |content| content.blog_id,
+    );
+    let mut blog_tags = tag_texts(Tag::get_tags_by_blogs(pool, &blog_ids).await?);
+
+    let project_ids: Vec<i32> = projects.iter().filter_map(|project| project.id).collect();
+    let mut descs = group_by(
+        DescItem::get_all_from_projects(pool, &project_ids).await?,
+        |desc| desc.project_id,
+    );
+    let mut project_tags = tag_texts(Tag::get_tags_by_projects(pool, &project_ids).await?);
+
+    let shop_item_ids: Vec<i32> = shop_items
+        .iter()
+        .filter_map(|shop_item| shop_item.id)
+        .collect();
+    let mut images = group_by(
+        ShopImage::get_all_from_shop_items(pool, &shop_item_ids).await?,
+        |image| image.shop_item_id,
+    );
+    let mut descriptions = group_by(
+        ShopItemDesc::get_all_from_shop_items(pool, &shop_item_ids).await?,
+        |description| description.shop_item_id,
+    );
+    let mut shop_item_tags = tag_texts(Tag::get_tags_by_shop_items(pool, &shop_item_ids).await?);
+
+    Ok(Archive {
+        categories,
+        tags: tags
+            .into_iter()
+            .map(|tag| ArchivedTag {
+                text: tag.text,
+                categories: tag.categories,
+            })
+            .collect(),
+        blogs: blogs
+            .into_iter()
+            .map(|mut blog| {
+                let id = blog.id.unwrap_or_default();
+                blog.content = contents.remove(&id).unwrap_or_default();
+                Tagged {
+                    item: blog,
+                    tags: blog_tags.remove(&id).unwrap_or_default(),
+                }
+            })
+            .collect(),
+        projects: projects
+            .into_iter()
+            .map(|mut project| {
+                let id = project.id.unwrap_or_default();
+                project.desc = descs.remove(&id).unwrap_or_default();
+                Tagged {
+                    item: project,
+                    tags: project_tags.remove(&id).unwrap_or_default(),
+                }
+            })
+            .collect(),
+        shop_items: shop_items
+            .into_iter()
+            .map(|shop_item| {
+                let id = shop_item.id.unwrap_or_default();
+                Tagged {
+                    item: ArchivedShopItem {
+                        item: shop_item,
+                        images: images.remove(&id).unwrap_or_default(),
+                        descriptions: descriptions.remove(&id).unwrap_or_default(),
+                    },
+                    tags: shop_item_tags.remove(&id).unwrap_or_default(),
+                }
+            })
+            .collect(),
+    })
+}
+
+/// Fetches every page of a list, following the cursor of each page
+async fn fetch_all<T, F, Fut>(fetch_page: F) -> CliResult<Vec<T>>
+where
+    F: Fn(PageParams) -> Fut,
+    Fut: Future<Output = Result<Page<T>, Either<sqlx::Error, ()>>>,
+{
+    let mut items = Vec::new();
+    let mut cursor = None;
+    loop {
+        let params = PageParams {
+            limit: Some(MAX_LIMIT),
+            cursor,
+            ..PageParams::default()
+        };
+        let page = fetch_page(params).await.map_err(|error| match error {
+            Left(error) => Box::<dyn Error>::from(error),
+            Right(_) => "Invalid sort field".into(),
+        })?;
+        items.extend(page.items);
+
+        match page.info.next_cursor {
+            Some(next_cursor) => cursor = Some(next_cursor),
+            None => return Ok(items),
+        }
+    }
+}
+
+/// Groups rows by the ID of the item they belong to
+fn group_by<T>(rows: Vec<T>, item_id: impl Fn(&T) -> Option<i32>) -> HashMap<i32, Vec<T>> {
+    let mut groups: HashMap<i32, Vec<T>> = HashMap::new();
+    for row in rows {
+        if let Some(id) = item_id(&row) {
+            groups.entry(id).or_default().push(row);
+        }
+    }
+    groups
+}
+
+/// Groups the text of tags by the ID of the item they are attached to
+fn tag_texts(rows: Vec<(i32, Tag)>) -> HashMap<i32, Vec<String>> {
+    let mut groups: HashMap<i32, Vec<String>> = HashMap::new();
+    for (item_id, tag) in rows {
+        groups.entry(item_id).or_default().push(tag.text);
+    }
+    groups
+}
+
+/// Creates the content of an archive in a single transaction
+async fn create(pool: &PgPool, archive: &Archive) -> CliResult {
+    let mut conn = pool.acquire().await?;
+    let mut tx = audit::begin(&mut conn).await?;
+
+    for category in &archive.categories {
+        check(&format!("category {}", category.name), category.validate())?;
+        if TagCategory::get_by_name(&mut tx, &category.name)
+            .await?
+            .is_none()
+        {
+            category.add(&mut tx).await?;
+        }
+    }
+
+    for archived in &archive.tags {
+        let tag = new_tag(&archived.text);
+        check(&format!("tag {}", archived.text), tag.validate())?;
+        tag.add_or_get(&mut tx).await?;
+        for name in &archived.categories {
+            let category = TagCategory::get_by_name(&mut tx, name)
+                .await?
+                .ok_or_else(|| format!("Unknown category {name} of tag {}", archived.text))?;
+            tag.add_category(&mut tx, &category).await?;
+        }
+    }
+
+    for blog in &archive.blogs {
+        check(
+            &format!("blog {}", blog.item.blog_title),
+            blog.item.validate(),
+        )?;
+        let blog_id = blog.item.add_tx(&mut tx).await?.id.unwrap_or_default();
+        for text in &blog.tags {
+            let tag_id = find_or_create_tag(&mut tx, text).await?;
+            BlogToTag {
+                id: None,
+                blog_id,
+                tag_id,
+            }
+            .add_tx(&mut tx)
+            .await?;
+        }
+    }
+
+    for project in &archive.projects {
+        check(
+            &format!("project {}", project.item.title),
+            project.item.validate(),
+        )?;
+        let project_id = project.item.add_tx(&mut tx).await?.id.unwrap_or_default();
+        for text in &project.tags {
+            let tag_id = find_or_create_tag(&mut tx, text).await?;
+            ProjectToTechTag {
+                id: None,
+                project_id,
+                tag_id,
+            }
+            .add_tx(&mut tx)
+            .await?;
+        }
+    }
+
+    for shop_item in &archive.shop_items {
+        let archived = &shop_item.item;
+        check(
+            &format!("shop item {}", archived.item.iname),
+            archived.item.validate(),
+        )?;
+        let shop_item_id = archived.item.add_tx(&mut tx).await?.id.unwrap_or_default();
+
+        for image in &archived.images {
+            let image = ShopImage {
+                id: None,
+                shop_item_id: Some(shop_item_id),
+                tooltip: image.tooltip.clone(),
+                img_link: image.img_link.clone(),
+                stamps: Stamps::default(),
+            };
+            check(
+                &format!("image of shop item {}", archived.item.iname),
+                image.validate(),
+            )?;
+            image.add_tx(&mut tx).await.map_err(insert_error)?;
+        }
+        for description in &archived.descriptions {
+            let description = ShopItemDesc {
+                id: None,
+                shop_item_id: Some(shop_item_id),
+                content: description.content.clone(),
+                stamps: Stamps::default(),
+            };
+            check(
+                &format!("description of shop item {}", archived.item.iname),
+                description.validate(),
+            )?;
+            description.add_tx(&mut tx).await.map_err(insert_error)?;
+        }
+        for text in &shop_item.tags {
+            let tag_id = find_or_create_tag(&mut tx, text).await?;
+            ShopItemToTag {
+                id: None,
+                shop_item_id,
+                tag_id,
+            }
+            .add_tx(&mut tx)
+            .await?;
+        }
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+fn new_tag(text: &str) -> Tag {
+    Tag {
+        id: None,
+        text: text.to_string(),
+        categories: Vec::new(),
+        stamps: Stamps::default(),
+    }
+}
+
+/// Looks up the ID of a tag by its text, creating the tag if it does not exist
+async fn find_or_create_tag(tx: &mut Transaction<'_, Postgres>, text: &str) -> CliResult<i32> {
+    let tag = new_tag(text);
+    check(&format!("tag {text}"), tag.validate())?;
+    Ok(tag.add_or_get(tx).await?.id.unwrap_or_default())
+}
+
+/// Maps the error of an insert that needs a parent ID onto a command error
+fn insert_error(error: Either<sqlx::Error, ()>) -> Box<dyn Error> {
+    match error {
+        Left(error) => error.into(),
+        Right(_) => "Missing parent ID".into(),
+    }
+}
+
+/// A handful of categories, tags and items to try the site with
+fn demo_archive() -> Archive {
+    let tag = |text: &str, category: &str| ArchivedTag {
+        text: text.to_string(),
+        categories: vec![category.to_string()],
+    };
+    let category = |name: &str| TagCategory {
+        id: None,
+        name: name.to_string(),
+        stamps: Stamps::default(),
+    };
+    let texts =
+        |texts: &[&str]| -> Vec<String> { texts.iter().map(|text| text.to_string()).collect() };
+
+    Archive {
+        categories: vec![category("language"), category("framework")],
+        tags: vec![
+            tag("Rust", "language"),
+            tag("TypeScript", "language"),
+            tag("Rocket", "framework"),
+            tag("SolidJS", "framework"),
+        ],
+        blogs: vec![Tagged {
+            item: BlogItem {
+                id: None,
+                blog_title: "Hello, world".to_string(),
+                header_img: "https://placehold.co/1200x400?text=Hello".to_string(),
+                version: None,
+                stamps: Stamps::default(),
+                content: vec![
+                    Content {
+                        id: None,
+                        blog_id: None,
+                        ctype: ContentType::Header,
+                        content: "Welcome".to_string(),
+                        stamps: Stamps::default(),
+                    },
+                    Content {
+                        id: None,
+                        blog_id: None,
+                        ctype: ContentType::Body,
+                        content: "This blog was created by the demo seed.".to_string(),
+                        stamps: Stamps::default(),
+                    },
+                ],
+            },
+            tags: texts(&["Rust", "Rocket"]),
+        }],
+        projects: vec![
+            Tagged {
+                item: ProjectItem {
+                    id: None,
+                    title: "Projects server".to_string(),
+                    thumbnail_img_link: "https://placehold.co/600x400?text=Server".to_string(),
+                    version: None,
+                    stamps: Stamps::default(),
+                    desc: vec![DescItem {
+                        id: None,
+                        project_id: None,
+                        content: "The API behind this site.".to_string(),
+                        stamps: Stamps::default(),
+                    }],
+                },
+                tags: texts(&["Rust", "Rocket"]),
+            },
+            Tagged {
+                item: ProjectItem {
+                    id: None,
+                    title: "Online shop".to_string(),
+                    thumbnail_img_link: "https://placehold.co/600x400?text=Shop".to_string(),
+                    version: None,
+                    stamps: Stamps::default(),
+                    desc: vec![DescItem {
+                        id: None,
+                        project_id: None,
+                        content: "The front end of this site.".to_string(),
+                        stamps: Stamps::default(),
+                    }],
+                },
+                tags: texts(&["TypeScript", "SolidJS"]),
+            },
+        ],
+        shop_items: vec![Tagged {
+            item: ArchivedShopItem {
+                item: ShopItem {
+                    id: None,
+                    iname: "Sticker pack".to_string(),
+                    img_link: "https://placehold.co/600x600?text=Stickers".to_string(),
+                    price: 4.99,
+                    version: None,
+                    stamps: Stamps::default(),
+                },
+                images: vec![ShopImage {
+                    id: None,
+                    shop_item_id: None,
+                    tooltip: "Front".to_string(),
+                    img_link: "https://placehold.co/600x600?text=Front".to_string(),
+                    stamps: Stamps::default(),
+                }],
+                descriptions: vec![ShopItemDesc {
+                    id: None,
+                    shop_item_id: None,
+                    content: "Five vinyl stickers.".to_string(),
+                    stamps: Stamps::default(),
+                }],
+            },
+            tags: texts(&["Rust"]),
+        }],
+    }
+}
@@ -0,0 +1,174 @@
+//! Admin command line tool
+//!
+//! Maintains the database without running the server: it applies and lists
+//! migrations, manages users, seeds demo content, and exports or imports
+//! content. It also generates the shared admin key. It connects to the database the server is configured with,
+//! `databases.sqlx.url` in `Rocket.toml` or `ROCKET_DATABASES`, and its
+//! changes are recorded in the audit log with `admin-cli` as their actor.
+//!
+//! Users have no roles: the admin routes are guarded by the one key
+//! configured as `admin_api_key`, which `key generate` creates, so
+//! `user create` registers the same kind of account as `POST /api/v2/users`.
+
+mod content;
+mod users;
+
+use projects_rust_server::api::FieldError;
+use projects_rust_server::db::audit::{self, AuditContext};
+use projects_rust_server::db::health::{self, MIGRATOR};
+use sqlx::PgPool;
+use std::error::Error;
+use std::process::ExitCode;
+use uuid::Uuid;
+
+/// Result of a command, whose error is shown to the user
+type CliResult<T = ()> = Result<T, Box<dyn Error>>;
+
+/// Actor recorded in the audit log for changes made by the tool
+const ACTOR: &str = "admin-cli";
+
+const USAGE: &str = "\
+Usage: admin <command>
+
+Commands:
+  migrate up                      Apply the pending migrations
+  migrate status                  List the migrations and whether they are applied
+  user create <username> <email>  Create a user, reading the password from stdin
+  user reset-password <username>  Replace a user's password, reading it from stdin
+  seed                            Create demo categories, tags and content
+  export <file>                   Write all content to a JSON file
+  import <file>                   Create the content of a JSON file written by export
+  key generate                    Print a new random key to set as admin_api_key";
+
+/// A command given on the command line
+enum Command<'a> {
+    MigrateUp,
+    MigrateStatus,
+    CreateUser { username: &'a str, email: &'a str },
+    ResetPassword { username: &'a str },
+    Seed,
+    Export { path: &'a str },
+    Import { path: &'a str },
+    GenerateKey,
+}
+
+impl<'a> Command<'a> {
+    /// Parses the arguments, returning `None` if they are not a known command
+    fn parse(args: &[&'a str]) -> Option<Self> {
+        match *args {
+            ["migrate", "up"] => Some(Command::MigrateUp),
+            ["migrate", "status"] => Some(Command::MigrateStatus),
+            ["user", "create", username, email] => Some(Command::CreateUser { username, email }),
+            ["user", "reset-password", username] => Some(Command::ResetPassword { username }),
+            ["seed"] => Some(Command::Seed),
+            ["export", path] => Some(Command::Export { path }),
+            ["import", path] => Some(Command::Import { path }),
+            ["key", "generate"] => Some(Command::GenerateKey),
+            _ => None,
+        }
+    }
+
+    async fn run(&self, pool: &PgPool) -> CliResult {
+        match self {
+            Command::MigrateUp => migrate_up(pool).await,
+            Command::MigrateStatus => migrate_status(pool).await,
+            Command::CreateUser { username, email } => users::create(pool, username, email).await,
+            Command::ResetPassword { username } => users::reset_password(pool, username).await,
+            Command::Seed => content::seed(pool).await,
+            Command::Export { path } => content::export(pool, path).await,
+            Command::Import { path } => content::import(pool, path).await,
+            Command::GenerateKey => unreachable!("runs without a database"),
+        }
+    }
+}
+
+#[rocket::main]
+async fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    if matches!(args.as_slice(), [] | ["help"] | ["-h"] | ["--help"]) {
+        println!("{USAGE}");
+        return ExitCode::SUCCESS;
+    }
+    let Some(command) = Command::parse(&args) else {
+        eprintln!("Unknown command: {}\n\n{USAGE}", args.join(" "));
+        return ExitCode::FAILURE;
+    };
+    if let Command::GenerateKey = command {
+        println!("{}", generate_key());
+        return ExitCode::SUCCESS;
+    }
+
+    let context = AuditContext {
+        actor: Some(ACTOR.to_string()),
+        request_id: None,
+    };
+    let result = audit::scope(context, async {
+        let pool = connect().await?;
+        command.run(&pool).await
+    })
+    .await;
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("Error: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// A random admin key, from two version 4 UUIDs for 244 random bits
+fn generate_key() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+/// Connects to the database the server is configured with
+async fn connect() -> CliResult<PgPool> {
+    let url: String = rocket::Config::figment()
+        .extract_inner("databases.sqlx.url")
+        .map_err(|error| format!("No database configured: {error}"))?;
+    Ok(PgPool::connect(&url).await?)
+}
+
+async fn migrate_up(pool: &PgPool) -> CliResult {
+    let pending = health::pending_migrations(pool).await?;
+    MIGRATOR.run(pool).await?;
+
+    match pending.len() {
+        0 => println!("The database is up to date"),
+        applied => println!("Applied {applied} migrations"),
+    }
+    Ok(())
+}
+
+async fn migrate_status(pool: &PgPool) -> CliResult {
+    let pending = health::pending_migrations(pool).await?;
+    for migration in MIGRATOR
+        .iter()
+        .filter(|migration| !migration.migration_type.is_down_migration())
+    {
+        let state = if pending.contains(&migration.version) {
+            "pending"
+        } else {
+            "applied"
+        };
+        println!(
+            "{:>4}  {:<7}  {}",
+            migration.version, state, migration.description
+        );
+    }
+    Ok(())
+}
+
+/// Fails with the validation errors of `what`, if there are any
+fn check(what: &str, errors: Vec<FieldError>) -> CliResult {
+    if errors.is_empty() {
+        return Ok(());
+    }
+    let details: Vec<String> = errors
+        .iter()
+        .map(|error| format!("{} {}", error.field, error.message))
+        .collect();
+    Err(format!("Invalid {what}: {}", details.join(", ")).into())
+}
@@ -0,0 +1,61 @@
+//! Creating users and resetting their passwords
+//!
+//! Passwords are read from standard input rather than taken as arguments, so
+//! they do not end up in the shell history.
+
+use projects_rust_server::api::validation::{Validate, Validator};
+use projects_rust_server::db::user::User;
+use sqlx::PgPool;
+use std::io::{self, BufRead, IsTerminal, Write};
+
+use crate::{check, CliResult};
+
+pub async fn create(pool: &PgPool, username: &str, email: &str) -> CliResult {
+    let user = User {
+        id: None,
+        username: username.to_string(),
+        upassword: read_password()?,
+        email: email.to_string(),
+    };
+    check("user", user.validate())?;
+
+    let mut conn = pool.acquire().await?;
+    let created = user.add(&mut conn).await?;
+    println!(
+        "Created user {} with ID {}",
+        created.username,
+        created.id.unwrap_or_default()
+    );
+    Ok(())
+}
+
+/// Replaces the password of a user, which also unlocks their account
+pub async fn reset_password(pool: &PgPool, username: &str) -> CliResult {
+    let password = read_password()?;
+    let errors = Validator::new()
+        .min_length("password", &password, 8)
+        .max_length("password", &password, 128)
+        .finish();
+    check("password", errors)?;
+
+    let mut conn = pool.acquire().await?;
+    if !User::set_password(&mut conn, username, &password).await? {
+        return Err(format!("No user named {username}").into());
+    }
+    println!("Reset the password of {username}");
+    Ok(())
+}
+
+/// Reads a password from the first line of standard input, prompting for it
+/// when the input is a terminal
+fn read_password() -> CliResult<String> {
+    let stdin = io::stdin();
+    if stdin.is_terminal() {
+        eprint!("Password: ");
+        io::stderr().flush()?;
+    }
+
+    let mut password = String::new();
+    stdin.lock().read_line(&mut password)?;
+    Ok(password.trim_end_matches(['\r', '\n']).to_string())
+}
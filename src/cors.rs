@@ -0,0 +1,163 @@
+//! Cross-origin resource sharing
+//!
+//! The policy is read from the `cors` table of the configuration, e.g.
+//!
+//! ```toml
+//! [release.cors]
+//! allowed_origins = ["https://shop.example.com"]
+//! allowed_origin_patterns = ['^https://[a-z0-9-]+\.example\.com$']
+//! allow_credentials = true
+//! ```
+//!
+//! or from `ROCKET_CORS`, and every field left out falls back to the
+//! defaults of the active profile: local origins in debug, none in release.
+//! Requests from origins outside the policy are rejected by `rocket_cors`
+//! and logged by [`CorsRejections`].
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::figment::providers::Serialized;
+use rocket::figment::{Figment, Profile};
+use rocket::request::Request;
+use rocket::response::Response;
+use rocket_cors::{AllowedHeaders, AllowedMethods, AllowedOrigins, Cors, CorsOptions, Method};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use tracing::warn;
+
+use crate::telemetry::request_span;
+
+/// Path `rocket_cors` rewrites rejected requests to
+const REJECTED_PATH_PREFIX: &str = "/cors/";
+
+/// The CORS policy as it is configured
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CorsConfig {
+    /// Origins allowed as written, e.g. `https://example.com`
+    pub allowed_origins: Vec<String>,
+    /// Regular expressions an origin may match instead
+    pub allowed_origin_patterns: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    /// Request headers clients may send, or `["*"]` for any
+    pub allowed_headers: Vec<String>,
+    /// Response headers scripts may read
+    pub expose_headers: Vec<String>,
+    pub allow_credentials: bool,
+    /// How long preflight results may be cached, in seconds
+    pub max_age: Option<usize>,
+}
+
+impl CorsConfig {
+    /// The policy used for whatever the configuration leaves out
+    ///
+    /// Debug builds accept any port on localhost so the frontend dev server
+    /// works out of the box. Every other profile accepts no origin until one
+    /// is configured.
+    pub fn defaults(profile: &Profile) -> Self {
+        let debug = profile == "debug";
+        let strings = |values: &[&str]| -> Vec<String> {
+            values.iter().map(|value| value.to_string()).collect()
+        };
+
+        Self {
+            allowed_origins: Vec::new(),
+            allowed_origin_patterns: if debug {
+                strings(&[r"^https?://(localhost|127\.0\.0\.1)(:\d+)?$"])
+            } else {
+                Vec::new()
+            },
+            allowed_methods: strings(&["GET", "POST", "PATCH", "DELETE"]),
+            allowed_headers: strings(&[
+                "Accept",
+                "Authorization",
+                "Content-Type",
+                "If-Match",
+                "If-Modified-Since",
+                "If-None-Match",
+                "Idempotency-Key",
+                "X-Request-Id",
+            ]),
+            expose_headers: strings(&[
+                "ETag",
+                "Link",
+                "Deprecation",
+                "Sunset",
+                "Idempotent-Replayed",
+                "X-Request-Id",
+            ]),
+            allow_credentials: debug,
+            max_age: Some(3600),
+        }
+    }
+
+    /// Reads the policy from the `cors` table, filling in the profile's defaults
+    pub fn from_figment(figment: &Figment) -> Result<Self, Box<rocket::figment::Error>> {
+        figment
+            .clone()
+            .join(Serialized::default("cors", Self::defaults(figment.profile())))
+            .extract_inner("cors")
+            .map_err(Box::new)
+    }
+
+    /// Builds the `rocket_cors` fairing for this policy
+    ///
+    /// # Returns
+    /// * `String` - If a method name or origin pattern is invalid
+    pub fn to_cors(&self) -> Result<Cors, String> {
+        let methods: AllowedMethods = self
+            .allowed_methods
+            .iter()
+            .map(|method| {
+                Method::from_str(method).map_err(|_| format!("Unknown method '{}'", method))
+            })
+            .collect::<Result<_, _>>()?;
+        let headers = if self.allowed_headers.iter().any(|header| header == "*") {
+            AllowedHeaders::all()
+        } else {
+            let headers: Vec<&str> = self.allowed_headers.iter().map(String::as_str).collect();
+            AllowedHeaders::some(&headers)
+        };
+
+        CorsOptions::default()
+            .allowed_origins(AllowedOrigins::some(
+                &self.allowed_origins,
+                &self.allowed_origin_patterns,
+            ))
+            .allowed_methods(methods)
+            .allowed_headers(headers)
+            .expose_headers(self.expose_headers.iter().cloned().collect())
+            .allow_credentials(self.allow_credentials)
+            .max_age(self.max_age)
+            .to_cors()
+            .map_err(|error| error.to_string())
+    }
+}
+
+/// Fairing that logs the requests `rocket_cors` rejected
+///
+/// The rejected request has already been rewritten to the CORS error route,
+/// but its span still carries the path the client asked for.
+pub struct CorsRejections;
+
+#[rocket::async_trait]
+impl Fairing for CorsRejections {
+    fn info(&self) -> Info {
+        Info {
+            name: "CORS rejection log",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        if !req.uri().path().starts_with(REJECTED_PATH_PREFIX) {
+            return;
+        }
+
+        let origin = req.headers().get_one("Origin").unwrap_or_default();
+        warn!(
+            parent: &request_span(req),
+            origin,
+            status = res.status().code,
+            "Rejected cross-origin request"
+        );
+    }
+}
@@ -4,10 +4,22 @@
 //! - Data models for different entities (blog, project, shop, user)
 //! - Database operations and queries
 //! - Relationship mappings between entities
+//! - The audit log of changes made to them
+//! - Soft deletion, restoring and purging of rows
+//! - When and by whom rows were created and last updated
 
+pub mod audit;
 pub mod blog_item;
+pub mod health;
+pub mod idempotency;
+pub mod pagination;
 pub mod project_item;
+pub mod rate_limit;
 pub mod shop_item;
+pub mod soft_delete;
+pub mod stamps;
 pub mod tag;
+pub mod tag_category;
 pub mod tag_category_join;
 pub mod user;
+pub mod versioning;
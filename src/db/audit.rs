@@ -0,0 +1,151 @@
+//! Audit log of every change to the content and user tables
+//!
+//! Rows are recorded by the `audit_row` trigger, so no write can bypass the
+//! log. The trigger takes the actor and request ID from settings local to the
+//! transaction, which [`begin`] fills in from the [`AuditContext`] the
+//! current request runs in. Writes should therefore always go through a
+//! transaction started with [`begin`]; changes made outside of one are still
+//! recorded, but without an actor or request ID.
+
+use either::Either;
+use serde::Serialize;
+use sqlx::types::JsonValue;
+use sqlx::{Acquire, PgConnection, Postgres, Transaction};
+use std::future::Future;
+use time::OffsetDateTime;
+use utoipa::ToSchema;
+
+use super::pagination::{fetch_page, Identifiable, Page, PageParams};
+
+tokio::task_local! {
+    static CONTEXT: AuditContext;
+}
+
+/// Who is making changes, and in which request
+#[derive(Debug, Clone, Default)]
+pub struct AuditContext {
+    pub actor: Option<String>,
+    pub request_id: Option<String>,
+}
+
+/// Runs `future` with `context` as the audit context of its writes
+pub async fn scope<F: Future>(context: AuditContext, future: F) -> F::Output {
+    CONTEXT.scope(context, future).await
+}
+
+/// Starts a transaction whose changes are attributed to the current audit context
+pub async fn begin(conn: &mut PgConnection) -> Result<Transaction<'_, Postgres>, sqlx::Error> {
+    let context = CONTEXT.try_with(Clone::clone).unwrap_or_default();
+    let mut tx = conn.begin().await?;
+
+    sqlx::query!(
+        "
+            SELECT
+                set_config('audit.actor', $1, TRUE) AS actor,
+                set_config('audit.request_id', $2, TRUE) AS request_id
+        ",
+        context.actor.unwrap_or_default(),
+        context.request_id.unwrap_or_default(),
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    Ok(tx)
+}
+
+/// A recorded change to a single row
+#[derive(Serialize, ToSchema, sqlx::FromRow)]
+pub struct AuditEntry {
+    pub id: i32,
+    /// Who made the change, if it was made through the API
+    pub actor: Option<String>,
+    /// `insert`, `update` or `delete`
+    pub action: String,
+    /// Table of the changed row
+    pub entity_type: String,
+    pub entity_id: Option<i32>,
+    /// The row before the change, missing for inserts
+    #[schema(value_type = Option<Object>)]
+    pub before: Option<JsonValue>,
+    /// The row after the change, missing for deletes
+    #[schema(value_type = Option<Object>)]
+    pub after: Option<JsonValue>,
+    pub request_id: Option<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    #[schema(value_type = String, format = DateTime)]
+    pub created_at: OffsetDateTime,
+}
+
+impl Identifiable for AuditEntry {
+    fn row_id(&self) -> Option<i32> {
+        Some(self.id)
+    }
+}
+
+/// Conditions audit entries must all meet to be listed
+pub struct AuditFilter<'a> {
+    pub actor: Option<&'a str>,
+    pub action: Option<&'a str>,
+    pub entity_type: Option<&'a str>,
+    pub entity_id: Option<i32>,
+    pub request_id: Option<&'a str>,
+    /// Only entries recorded at or after this time
+    pub since: Option<OffsetDateTime>,
+    /// Only entries recorded before this time
+    pub until: Option<OffsetDateTime>,
+}
+
+impl AuditEntry {
+    /// Fields that audit entries may be sorted by, the first being the default
+    pub const SORT_FIELDS: &'static [(&'static str, &'static str)] =
+        &[("id", "id"), ("created_at", "created_at")];
+
+    /// Lists audit entries one page at a time
+    pub async fn get_all(
+        db: &mut PgConnection,
+        params: &PageParams,
+        filter: &AuditFilter<'_>,
+    ) -> Result<Page<AuditEntry>, Either<sqlx::Error, ()>> {
+        fetch_page(
+            db,
+            "
+                SELECT id, actor, action, entity_type, entity_id, before, after, request_id, created_at
+                FROM audit_log
+            ",
+            "audit_log",
+            Self::SORT_FIELDS,
+            params,
+            |query| {
+                if let Some(actor) = filter.actor {
+                    query.push(" AND audit_log.actor = ");
+                    query.push_bind(actor.to_string());
+                }
+                if let Some(action) = filter.action {
+                    query.push(" AND audit_log.action = ");
+                    query.push_bind(action.to_lowercase());
+                }
+                if let Some(entity_type) = filter.entity_type {
+                    query.push(" AND audit_log.entity_type = ");
+                    query.push_bind(entity_type.to_string());
+                }
+                if let Some(entity_id) = filter.entity_id {
+                    query.push(" AND audit_log.entity_id = ");
+                    query.push_bind(entity_id);
+                }
+                if let Some(request_id) = filter.request_id {
+                    query.push(" AND audit_log.request_id = ");
+                    query.push_bind(request_id.to_string());
+                }
+                if let Some(since) = filter.since {
+                    query.push(" AND audit_log.created_at >= ");
+                    query.push_bind(since);
+                }
+                if let Some(until) = filter.until {
+                    query.push(" AND audit_log.created_at < ");
+                    query.push_bind(until);
+                }
+            },
+        )
+        .await
+    }
+}
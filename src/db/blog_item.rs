@@ -1,12 +1,23 @@
+use async_graphql::{Enum, SimpleObject};
 use either::*;
 use futures::stream::TryStreamExt;
 use rocket_db_pools::Connection;
 use serde::{Deserialize, Serialize};
-use sqlx::{Acquire, Postgres, Transaction};
+use utoipa::ToSchema;
+use sqlx::{PgConnection, PgPool, Postgres, Transaction};
+use tracing::{debug, info};
 
+use super::audit;
+use super::pagination::{escape_like, fetch_page, Identifiable, Page, PageParams};
+use super::soft_delete::{self, Entity};
+use super::stamps::Stamps;
+use super::versioning::explain_mismatch;
+use crate::api::validation::{Validate, Validator};
+use crate::api::FieldError;
+use crate::metrics::METRICS;
 use crate::Db;
 
-#[derive(Serialize, Deserialize, Clone, sqlx::Type)]
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, PartialEq, Eq, Enum, sqlx::Type)]
 #[serde(rename_all = "lowercase")]
 #[sqlx(type_name = "content_type", rename_all = "lowercase")]
 pub enum ContentType {
@@ -16,36 +27,75 @@ pub enum ContentType {
     Body,
 }
 
-#[derive(Serialize, Deserialize, sqlx::FromRow)]
+#[derive(Serialize, Deserialize, ToSchema, SimpleObject, sqlx::FromRow)]
 #[sqlx(type_name = "blog_item")]
+#[graphql(complex, name = "Blog")]
 pub struct BlogItem {
     #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
     pub id: Option<i32>,
     pub blog_title: String,
     pub header_img: String,
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub version: Option<i32>,
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    #[graphql(flatten)]
+    pub stamps: Stamps,
     #[sqlx(skip)]
+    #[graphql(skip)]
     pub content: Vec<Content>,
 }
 
-#[derive(Serialize, Deserialize, Clone, sqlx::FromRow)]
+/// Partial update of a blog; omitted fields keep their value
+#[derive(Deserialize, ToSchema)]
+pub struct BlogItemPatch {
+    pub blog_title: Option<String>,
+    pub header_img: Option<String>,
+    /// Version the client last saw, required unless an `If-Match` header is sent
+    pub version: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, SimpleObject, sqlx::FromRow)]
 #[sqlx(type_name = "content")]
+#[graphql(name = "BlogContent")]
 pub struct Content {
     #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
     pub id: Option<i32>,
     pub blog_id: Option<i32>,
     pub ctype: ContentType,
     pub content: String,
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    #[graphql(flatten)]
+    pub stamps: Stamps,
 }
 
 impl BlogItem {
     pub async fn add(&self, mut db: Connection<Db>) -> Result<BlogItem, sqlx::Error> {
-        let mut tx = (*db).begin().await?;
+        let mut tx = audit::begin(&mut db).await?;
+        let result = self.add_tx(&mut tx).await?;
+        tx.commit().await?;
+        METRICS.blogs_created.inc();
+
+        Ok(result)
+    }
+
+    /// Inserts the blog and its content blocks inside an existing transaction
+    ///
+    /// Callers count the blog in [`METRICS`] once the transaction is committed.
+    pub async fn add_tx<'a>(
+        &self,
+        tx: &mut Transaction<'a, Postgres>,
+    ) -> Result<BlogItem, sqlx::Error> {
         let result = sqlx::query!(
-            "INSERT INTO blog_item (blog_title, header_img) VALUES ($1, $2) RETURNING id",
+            "
+                INSERT INTO blog_item (blog_title, header_img) VALUES ($1, $2)
+                    RETURNING id, version, created_at, updated_at, created_by, updated_by
+            ",
             &self.blog_title,
             &self.header_img,
         )
-        .fetch(&mut *tx)
+        .fetch(&mut **tx)
         .try_collect::<Vec<_>>()
         .await;
 
@@ -53,17 +103,18 @@ impl BlogItem {
 
         match result {
             Ok(result) => {
-                println!("Successfully added new  {}", &self.blog_title);
-                let id_returned = result.first().expect("returning result").id;
+                let returned = result.first().expect("returning result");
+                let id_returned = returned.id;
 
                 for content in &self.content {
                     let content_copy = Content {
                         id: None,
                         blog_id: Some(id_returned),
-                        ctype: content.ctype.clone(),
+                        ctype: content.ctype,
                         content: content.content.clone(),
+                        stamps: Stamps::default(),
                     };
-                    let result = content_copy.add_tx(&mut tx).await;
+                    let result = content_copy.add_tx(tx).await;
 
                     match result {
                         Ok(resulting_content) => {
@@ -81,34 +132,137 @@ impl BlogItem {
                     };
                 }
 
-                tx.commit().await?;
-
-                for content_item in &pushed_content {
-                    println!("Inserted content {}", content_item.blog_id.unwrap_or(-1));
-                }
+                info!(
+                    id = id_returned,
+                    title = %self.blog_title,
+                    contents = pushed_content.len(),
+                    "Created blog item"
+                );
 
                 Ok(BlogItem {
                     id: Some(id_returned),
                     blog_title: self.blog_title.clone(),
                     header_img: self.header_img.clone(),
+                    version: Some(returned.version),
+                    stamps: Stamps {
+                        created_at: Some(returned.created_at),
+                        updated_at: Some(returned.updated_at),
+                        created_by: returned.created_by.clone(),
+                        updated_by: returned.updated_by.clone(),
+                    },
                     content: pushed_content,
                 })
             }
             Err(error) => {
-                println!(
-                    "Error when creating new blog item with: [ {} ]",
-                    &self.blog_title
-                );
+                debug!(%error, title = %self.blog_title, "Failed to create blog item");
                 Err(error)
             }
         }
     }
 
-    pub async fn get_all(mut db: Connection<Db>) -> Result<Vec<BlogItem>, sqlx::Error> {
-        sqlx::query_as("SELECT id, blog_title, header_img FROM blog_item")
-            .fetch_all(&mut **db)
-            .await
-        // TODO: Add custom completion prints
+    /// Applies a partial update, provided the blog is still at `expected_version`,
+    /// or is at any version if that is `None`
+    ///
+    /// The blog's content blocks are not loaded into the returned item.
+    ///
+    /// # Returns
+    /// * `Right(version)` with the current version if the blog was changed in the meantime
+    pub async fn update(
+        mut db: Connection<Db>,
+        id: i32,
+        patch: &BlogItemPatch,
+        expected_version: Option<i32>,
+    ) -> Result<BlogItem, Either<sqlx::Error, i32>> {
+        let mut tx = audit::begin(&mut db).await.map_err(Left)?;
+        let result = sqlx::query_as(
+            "
+                UPDATE blog_item SET
+                    blog_title = COALESCE($2, blog_title),
+                    header_img = COALESCE($3, header_img),
+                    version = version + 1
+                WHERE id = $1 AND ($4::INT4 IS NULL OR version = $4) AND deleted_at IS NULL
+                RETURNING id, blog_title, header_img, version, created_at, updated_at, created_by, updated_by
+            ",
+        )
+        .bind(id)
+        .bind(patch.blog_title.as_deref())
+        .bind(patch.header_img.as_deref())
+        .bind(expected_version)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(Left)?;
+
+        match result {
+            Some(blog_item) => {
+                tx.commit().await.map_err(Left)?;
+                Ok(blog_item)
+            }
+            None => Err(explain_mismatch(&mut tx, "blog_item", id).await),
+        }
+    }
+
+    /// Soft deletes the blog, returning whether it existed and was not deleted yet
+    pub async fn delete(mut db: Connection<Db>, id: i32) -> Result<bool, sqlx::Error> {
+        let mut tx = audit::begin(&mut db).await?;
+        let deleted = soft_delete::delete(&mut tx, Entity::Blog, id).await?;
+        tx.commit().await?;
+
+        if deleted {
+            info!(id, "Deleted blog item");
+        }
+        Ok(deleted)
+    }
+
+    /// Fields that blog lists may be sorted by, the first being the default
+    pub const SORT_FIELDS: &'static [(&'static str, &'static str)] = &[
+        ("id", "id"),
+        ("title", "blog_title"),
+        ("created_at", "created_at"),
+        ("updated_at", "updated_at"),
+    ];
+
+    /// Lists blogs one page at a time, optionally filtered by a title search
+    pub async fn get_all(
+        db: &mut PgConnection,
+        params: &PageParams,
+        search: Option<&str>,
+    ) -> Result<Page<BlogItem>, Either<sqlx::Error, ()>> {
+        fetch_page(
+            db,
+            "
+                SELECT id, blog_title, header_img, version, created_at, updated_at, created_by, updated_by
+                FROM blog_item
+            ",
+            "blog_item",
+            Self::SORT_FIELDS,
+            params,
+            |query| {
+                query.push(" AND blog_item.deleted_at IS NULL");
+                if let Some(search) = search {
+                    query.push(" AND blog_item.blog_title ILIKE '%' || ");
+                    query.push_bind(escape_like(search));
+                    query.push(" || '%'");
+                }
+            },
+        )
+        .await
+    }
+
+    pub async fn get_blogs_by_tag(
+        mut db: Connection<Db>,
+        tag_id: i32,
+    ) -> Result<Vec<BlogItem>, sqlx::Error> {
+        sqlx::query_as(
+            "
+                SELECT blog_item.id, blog_item.blog_title, blog_item.header_img, blog_item.version,
+                    blog_item.created_at, blog_item.updated_at, blog_item.created_by, blog_item.updated_by FROM blog_item
+                    INNER JOIN blog_tag ON blog_item.id=blog_tag.blog_id
+                    WHERE blog_tag.tag_id = $1 AND blog_item.deleted_at IS NULL
+            ",
+        )
+        .bind(tag_id)
+        .fetch_all(&mut **db)
+        .await
     }
 
     pub async fn query_contents(
@@ -131,38 +285,53 @@ impl BlogItem {
     }
 }
 
+impl Identifiable for BlogItem {
+    fn row_id(&self) -> Option<i32> {
+        self.id
+    }
+}
+
+impl Validate for BlogItem {
+    fn validate(&self) -> Vec<FieldError> {
+        Validator::new()
+            .not_blank("blog_title", &self.blog_title)
+            .max_length("blog_title", &self.blog_title, 200)
+            .url("header_img", &self.header_img)
+            .each("content", &self.content)
+            .finish()
+    }
+}
+
+impl Validate for BlogItemPatch {
+    fn validate(&self) -> Vec<FieldError> {
+        let mut validator = Validator::new();
+        if let Some(blog_title) = &self.blog_title {
+            validator = validator
+                .not_blank("blog_title", blog_title)
+                .max_length("blog_title", blog_title, 200);
+        }
+        if let Some(header_img) = &self.header_img {
+            validator = validator.url("header_img", header_img);
+        }
+        validator.finish()
+    }
+}
+
+impl Validate for Content {
+    fn validate(&self) -> Vec<FieldError> {
+        Validator::new()
+            .not_blank("content", &self.content)
+            .finish()
+    }
+}
+
 impl Content {
     pub async fn add(&self, db: &mut Connection<Db>) -> Result<Content, Either<sqlx::Error, ()>> {
-        match &self.blog_id {
-            Some(blog_id) => {
-                let result = sqlx::query!(
-                    "INSERT INTO content (blog_id, ctype, content) VALUES ($1, $2, $3) RETURNING id",
-                    blog_id,
-                    &self.ctype as &ContentType,
-                    &self.content,
-                )
-                    .fetch_one(&mut ***db)
-                .await;
+        let mut tx = audit::begin(db).await.map_err(Left)?;
+        let result = self.add_tx(&mut tx).await?;
+        tx.commit().await.map_err(Left)?;
 
-                match result {
-                    Ok(record) => {
-                        println!("Successfully added new content");
-                        let id_returned = record.id;
-                        Ok(Content {
-                            id: Some(id_returned),
-                            blog_id: self.blog_id,
-                            ctype: self.ctype.clone(),
-                            content: self.content.clone(),
-                        })
-                    }
-                    Err(error) => {
-                        println!("Error when creating new content");
-                        Err(Left(error))
-                    }
-                }
-            }
-            None => Err(Right(())),
-        }
+        Ok(result)
     }
 
     pub async fn add_tx<'a>(
@@ -172,27 +341,36 @@ impl Content {
         match &self.blog_id {
             Some(blog_id) => {
                 let result = sqlx::query!(
-                    "INSERT INTO content (blog_id, ctype, content) VALUES ($1, $2, $3) RETURNING id",
+                    "
+                        INSERT INTO content (blog_id, ctype, content) VALUES ($1, $2, $3)
+                            RETURNING id, created_at, updated_at, created_by, updated_by
+                    ",
                     blog_id,
                     &self.ctype as &ContentType,
                     &self.content,
                 )
-                    .fetch_one(&mut **db)
+                .fetch_one(&mut **db)
                 .await;
 
                 match result {
                     Ok(record) => {
-                        println!("Successfully added new content");
+                        debug!(id = record.id, blog_id, "Created blog content");
                         let id_returned = record.id;
                         Ok(Content {
                             id: Some(id_returned),
                             blog_id: self.blog_id,
-                            ctype: self.ctype.clone(),
+                            ctype: self.ctype,
                             content: self.content.clone(),
+                            stamps: Stamps {
+                                created_at: Some(record.created_at),
+                                updated_at: Some(record.updated_at),
+                                created_by: record.created_by,
+                                updated_by: record.updated_by,
+                            },
                         })
                     }
                     Err(error) => {
-                        println!("Error when creating new content");
+                        debug!(%error, blog_id, "Failed to create blog content");
                         Err(Left(error))
                     }
                 }
@@ -207,11 +385,33 @@ impl Content {
     ) -> Result<Vec<Content>, sqlx::Error> {
         sqlx::query_as!(
             Content,
-            r#"SELECT id, blog_id, ctype as "ctype: ContentType", content FROM content WHERE blog_id=$1"#,
-            blog_id
+            r#"
+                SELECT id, blog_id, ctype AS "ctype: ContentType", content,
+                    ROW(created_at, updated_at, created_by, updated_by) AS "stamps!: Stamps"
+                FROM content WHERE blog_id=$1
+            "#,
+            blog_id,
         )
         .fetch_all(&mut **db)
         .await
         // TODO: Add custom completion prints
     }
+
+    /// Loads the content blocks of several blogs at once
+    pub async fn get_all_from_blogs(
+        pool: &PgPool,
+        blog_ids: &[i32],
+    ) -> Result<Vec<Content>, sqlx::Error> {
+        sqlx::query_as!(
+            Content,
+            r#"
+                SELECT id, blog_id, ctype AS "ctype: ContentType", content,
+                    ROW(created_at, updated_at, created_by, updated_by) AS "stamps!: Stamps"
+                FROM content WHERE blog_id = ANY($1) ORDER BY id
+            "#,
+            blog_ids,
+        )
+        .fetch_all(pool)
+        .await
+    }
 }
@@ -0,0 +1,41 @@
+//! Database health checks
+//!
+//! The migrations are embedded in the binary through [`MIGRATOR`], which both
+//! runs them at startup and tells which of them the database is still missing.
+
+use sqlx::migrate::Migrator;
+use sqlx::PgPool;
+
+/// The migrations in `src/db/migrations`
+pub static MIGRATOR: Migrator = sqlx::migrate!("src/db/migrations");
+
+/// Checks that the database answers queries
+pub async fn ping(pool: &PgPool) -> Result<(), sqlx::Error> {
+    sqlx::query("SELECT 1").execute(pool).await?;
+    Ok(())
+}
+
+/// Lists the versions of the embedded migrations that have not been applied
+///
+/// A migration that failed halfway counts as not applied, and every migration
+/// is pending in a database that was never migrated.
+pub async fn pending_migrations(pool: &PgPool) -> Result<Vec<i64>, sqlx::Error> {
+    let migrated: bool =
+        sqlx::query_scalar("SELECT to_regclass('_sqlx_migrations') IS NOT NULL")
+            .fetch_one(pool)
+            .await?;
+    let applied: Vec<i64> = if migrated {
+        sqlx::query_scalar("SELECT version FROM _sqlx_migrations WHERE success")
+            .fetch_all(pool)
+            .await?
+    } else {
+        Vec::new()
+    };
+
+    Ok(MIGRATOR
+        .iter()
+        .filter(|migration| !migration.migration_type.is_down_migration())
+        .map(|migration| migration.version)
+        .filter(|version| !applied.contains(version))
+        .collect())
+}
@@ -0,0 +1,126 @@
+//! Stored responses for requests sent with an `Idempotency-Key`
+//!
+//! The first request with a key reserves it, and its response is stored once
+//! it succeeded. Keys older than the configured TTL are deleted the next time
+//! any key is reserved.
+
+use sqlx::PgPool;
+
+/// An idempotency key together with what it is scoped to
+///
+/// The same key sent by another user, or to another endpoint, is a
+/// different key.
+#[derive(Debug, Clone)]
+pub struct ScopedKey {
+    /// The authenticated user who sent the key, empty for anonymous requests,
+    /// see [`crate::api::admin::identity`]
+    pub identity: String,
+    pub method: String,
+    pub path: String,
+    pub key: String,
+}
+
+/// A previous use of an idempotency key
+#[derive(Clone)]
+pub struct IdempotencyRecord {
+    /// FNV-1a hash of the request body, reinterpreted as signed
+    pub request_hash: Option<i64>,
+    /// `None` while the first request with the key is still being processed
+    pub response_status: Option<i16>,
+    pub response_content_type: Option<String>,
+    pub response_body: Option<Vec<u8>>,
+}
+
+impl IdempotencyRecord {
+    /// Reserves `key` for the request that sent it
+    ///
+    /// # Returns
+    /// * `Ok(None)` if the key was free and is now reserved
+    /// * `Ok(Some(record))` if the key was used within the last `ttl_seconds`
+    pub async fn reserve(
+        pool: &PgPool,
+        key: &ScopedKey,
+        ttl_seconds: u64,
+    ) -> Result<Option<IdempotencyRecord>, sqlx::Error> {
+        sqlx::query!(
+            "DELETE FROM idempotency_key WHERE created_at < now() - make_interval(secs => $1)",
+            ttl_seconds as f64,
+        )
+        .execute(pool)
+        .await?;
+
+        let reserved = sqlx::query!(
+            "INSERT INTO idempotency_key (identity, request_method, request_path, key)
+             VALUES ($1, $2, $3, $4)
+             ON CONFLICT (identity, request_method, request_path, key) DO NOTHING
+             RETURNING key",
+            key.identity,
+            key.method,
+            key.path,
+            key.key,
+        )
+        .fetch_optional(pool)
+        .await?;
+        if reserved.is_some() {
+            return Ok(None);
+        }
+
+        sqlx::query_as!(
+            IdempotencyRecord,
+            "SELECT request_hash, response_status, response_content_type, response_body
+             FROM idempotency_key
+             WHERE identity = $1 AND request_method = $2 AND request_path = $3 AND key = $4",
+            key.identity,
+            key.method,
+            key.path,
+            key.key,
+        )
+        .fetch_optional(pool)
+        .await
+    }
+
+    /// Stores the response to the request that reserved `key`
+    pub async fn complete(
+        pool: &PgPool,
+        key: &ScopedKey,
+        request_hash: i64,
+        status: i16,
+        content_type: Option<String>,
+        body: &[u8],
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "UPDATE idempotency_key
+             SET request_hash = $5, response_status = $6, response_content_type = $7, response_body = $8
+             WHERE identity = $1 AND request_method = $2 AND request_path = $3 AND key = $4",
+            key.identity,
+            key.method,
+            key.path,
+            key.key,
+            request_hash,
+            status,
+            content_type,
+            body,
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Frees `key` after the request that reserved it failed, so it can be retried
+    pub async fn release(pool: &PgPool, key: &ScopedKey) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "DELETE FROM idempotency_key
+             WHERE identity = $1 AND request_method = $2 AND request_path = $3 AND key = $4
+                AND response_status IS NULL",
+            key.identity,
+            key.method,
+            key.path,
+            key.key,
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+}
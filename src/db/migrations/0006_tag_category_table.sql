@@ -0,0 +1,22 @@
+CREATE TABLE IF NOT EXISTS category (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR UNIQUE NOT NULL
+);
+
+INSERT INTO category (name)
+VALUES ('language'), ('framework'), ('database')
+ON CONFLICT (name) DO NOTHING;
+
+ALTER TABLE tag_category_join ADD COLUMN category_id INT;
+
+UPDATE tag_category_join SET category_id = category.id
+FROM category
+WHERE category.name = tag_category_join.category::TEXT;
+
+ALTER TABLE tag_category_join DROP COLUMN category;
+ALTER TABLE tag_category_join ALTER COLUMN category_id SET NOT NULL;
+ALTER TABLE tag_category_join ADD CONSTRAINT fk_category FOREIGN KEY (
+    category_id
+) REFERENCES category (id);
+
+DROP TYPE IF EXISTS tag_category;
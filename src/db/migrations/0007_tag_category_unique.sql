@@ -0,0 +1,9 @@
+DELETE FROM tag_category_join AS duplicate
+USING tag_category_join AS original
+WHERE duplicate.id > original.id
+    AND duplicate.tag_id = original.tag_id
+    AND duplicate.category_id = original.category_id;
+
+ALTER TABLE tag_category_join ADD CONSTRAINT uq_tag_category UNIQUE (
+    tag_id, category_id
+);
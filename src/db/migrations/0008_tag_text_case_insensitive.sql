@@ -0,0 +1,47 @@
+-- Fold tags that only differ by case or surrounding whitespace into the
+-- oldest tag of each group before enforcing case-insensitive uniqueness.
+CREATE TEMPORARY TABLE tag_merge AS
+SELECT tag.id AS source_id, keeper.id AS target_id
+FROM tag
+INNER JOIN (
+    SELECT
+        MIN(id) AS id,
+        LOWER(TRIM(text)) AS normalized
+    FROM tag GROUP BY LOWER(TRIM(text))
+) AS keeper ON LOWER(TRIM(tag.text)) = keeper.normalized
+WHERE tag.id <> keeper.id;
+
+INSERT INTO project_tech_tag (project_id, tag_id)
+SELECT DISTINCT
+    project_tech_tag.project_id,
+    tag_merge.target_id
+FROM project_tech_tag
+INNER JOIN tag_merge ON project_tech_tag.tag_id = tag_merge.source_id
+WHERE NOT EXISTS (
+    SELECT 1 FROM project_tech_tag AS existing
+    WHERE existing.tag_id = tag_merge.target_id
+        AND existing.project_id = project_tech_tag.project_id
+);
+
+DELETE FROM project_tech_tag USING tag_merge
+WHERE project_tech_tag.tag_id = tag_merge.source_id;
+
+INSERT INTO tag_category_join (tag_id, category_id)
+SELECT DISTINCT
+    tag_merge.target_id,
+    tag_category_join.category_id
+FROM tag_category_join
+INNER JOIN tag_merge ON tag_category_join.tag_id = tag_merge.source_id
+ON CONFLICT (tag_id, category_id) DO NOTHING;
+
+DELETE FROM tag_category_join USING tag_merge
+WHERE tag_category_join.tag_id = tag_merge.source_id;
+
+DELETE FROM tag USING tag_merge
+WHERE tag.id = tag_merge.source_id;
+
+DROP TABLE tag_merge;
+
+UPDATE tag SET text = TRIM(text) WHERE text <> TRIM(text);
+
+CREATE UNIQUE INDEX IF NOT EXISTS tag_text_lower_key ON tag (LOWER(text));
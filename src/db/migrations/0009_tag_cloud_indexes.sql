@@ -0,0 +1,7 @@
+CREATE INDEX IF NOT EXISTS tag_text_lower_prefix_idx ON tag (
+    LOWER(text) text_pattern_ops
+);
+
+CREATE INDEX IF NOT EXISTS project_tech_tag_tag_id_idx ON project_tech_tag (
+    tag_id
+);
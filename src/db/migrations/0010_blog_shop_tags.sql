@@ -0,0 +1,28 @@
+CREATE TABLE IF NOT EXISTS blog_tag (
+    id SERIAL PRIMARY KEY,
+    blog_id INT NOT NULL,
+    tag_id INT NOT NULL,
+    CONSTRAINT fk_blog FOREIGN KEY (
+        blog_id
+    ) REFERENCES blog_item (id),
+    CONSTRAINT fk_tag FOREIGN KEY (
+        tag_id
+    ) REFERENCES tag (id),
+    CONSTRAINT uq_blog_tag UNIQUE (blog_id, tag_id)
+);
+
+CREATE TABLE IF NOT EXISTS shop_item_tag (
+    id SERIAL PRIMARY KEY,
+    shop_item_id INT NOT NULL,
+    tag_id INT NOT NULL,
+    CONSTRAINT fk_shop_item FOREIGN KEY (
+        shop_item_id
+    ) REFERENCES shop_item (id),
+    CONSTRAINT fk_tag FOREIGN KEY (
+        tag_id
+    ) REFERENCES tag (id),
+    CONSTRAINT uq_shop_item_tag UNIQUE (shop_item_id, tag_id)
+);
+
+CREATE INDEX IF NOT EXISTS blog_tag_tag_id_idx ON blog_tag (tag_id);
+CREATE INDEX IF NOT EXISTS shop_item_tag_tag_id_idx ON shop_item_tag (tag_id);
@@ -0,0 +1,4 @@
+-- Row versions for optimistic concurrency on updates
+ALTER TABLE blog_item ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE project_item ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE shop_item ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
@@ -0,0 +1,13 @@
+-- Responses to POST requests, replayed when a client retries with the same Idempotency-Key
+CREATE TABLE idempotency_key (
+    key VARCHAR(255) PRIMARY KEY,
+    request_path TEXT NOT NULL,
+    -- The columns below stay NULL while the first request is being processed
+    request_hash BIGINT,
+    response_status SMALLINT,
+    response_content_type TEXT,
+    response_body BYTEA,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX idempotency_key_created_at_idx ON idempotency_key (created_at);
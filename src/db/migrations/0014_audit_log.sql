@@ -0,0 +1,78 @@
+-- Every change to the content and user tables, written by the audit_row trigger
+CREATE TABLE audit_log (
+    id SERIAL PRIMARY KEY,
+    actor VARCHAR(255),
+    action VARCHAR(16) NOT NULL,
+    entity_type VARCHAR(64) NOT NULL,
+    entity_id INT,
+    before JSONB,
+    after JSONB,
+    request_id VARCHAR(128),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX audit_log_entity_idx ON audit_log (entity_type, entity_id);
+CREATE INDEX audit_log_actor_idx ON audit_log (actor);
+CREATE INDEX audit_log_request_id_idx ON audit_log (request_id);
+CREATE INDEX audit_log_created_at_idx ON audit_log (created_at);
+
+-- Records the changed row together with the actor and request ID the
+-- application set for the transaction. Passwords are left out, and upserts
+-- that rewrite an existing row unchanged are not recorded.
+CREATE OR REPLACE FUNCTION audit_row() RETURNS TRIGGER AS $$
+DECLARE
+    old_row JSONB;
+    new_row JSONB;
+BEGIN
+    IF TG_OP <> 'INSERT' THEN
+        old_row := to_jsonb(OLD) - 'upassword';
+    END IF;
+    IF TG_OP <> 'DELETE' THEN
+        new_row := to_jsonb(NEW) - 'upassword';
+    END IF;
+    IF old_row IS NOT DISTINCT FROM new_row THEN
+        RETURN NULL;
+    END IF;
+
+    INSERT INTO audit_log (actor, action, entity_type, entity_id, before, after, request_id)
+    VALUES (
+        NULLIF(current_setting('audit.actor', TRUE), ''),
+        lower(TG_OP),
+        TG_TABLE_NAME,
+        COALESCE(new_row ->> 'id', old_row ->> 'id')::INT,
+        old_row,
+        new_row,
+        NULLIF(current_setting('audit.request_id', TRUE), '')
+    );
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_app_user AFTER INSERT OR UPDATE OR DELETE ON app_user
+    FOR EACH ROW EXECUTE FUNCTION audit_row();
+CREATE TRIGGER audit_shop_item AFTER INSERT OR UPDATE OR DELETE ON shop_item
+    FOR EACH ROW EXECUTE FUNCTION audit_row();
+CREATE TRIGGER audit_shop_image AFTER INSERT OR UPDATE OR DELETE ON shop_image
+    FOR EACH ROW EXECUTE FUNCTION audit_row();
+CREATE TRIGGER audit_shop_item_desc AFTER INSERT OR UPDATE OR DELETE ON shop_item_desc
+    FOR EACH ROW EXECUTE FUNCTION audit_row();
+CREATE TRIGGER audit_shop_item_tag AFTER INSERT OR UPDATE OR DELETE ON shop_item_tag
+    FOR EACH ROW EXECUTE FUNCTION audit_row();
+CREATE TRIGGER audit_blog_item AFTER INSERT OR UPDATE OR DELETE ON blog_item
+    FOR EACH ROW EXECUTE FUNCTION audit_row();
+CREATE TRIGGER audit_content AFTER INSERT OR UPDATE OR DELETE ON content
+    FOR EACH ROW EXECUTE FUNCTION audit_row();
+CREATE TRIGGER audit_blog_tag AFTER INSERT OR UPDATE OR DELETE ON blog_tag
+    FOR EACH ROW EXECUTE FUNCTION audit_row();
+CREATE TRIGGER audit_project_item AFTER INSERT OR UPDATE OR DELETE ON project_item
+    FOR EACH ROW EXECUTE FUNCTION audit_row();
+CREATE TRIGGER audit_project_desc_item AFTER INSERT OR UPDATE OR DELETE ON project_desc_item
+    FOR EACH ROW EXECUTE FUNCTION audit_row();
+CREATE TRIGGER audit_project_tech_tag AFTER INSERT OR UPDATE OR DELETE ON project_tech_tag
+    FOR EACH ROW EXECUTE FUNCTION audit_row();
+CREATE TRIGGER audit_tag AFTER INSERT OR UPDATE OR DELETE ON tag
+    FOR EACH ROW EXECUTE FUNCTION audit_row();
+CREATE TRIGGER audit_category AFTER INSERT OR UPDATE OR DELETE ON category
+    FOR EACH ROW EXECUTE FUNCTION audit_row();
+CREATE TRIGGER audit_tag_category_join AFTER INSERT OR UPDATE OR DELETE ON tag_category_join
+    FOR EACH ROW EXECUTE FUNCTION audit_row();
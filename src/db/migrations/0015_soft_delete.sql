@@ -0,0 +1,13 @@
+-- Soft deletion: rows with deleted_at set are hidden until restored or purged
+ALTER TABLE blog_item ADD COLUMN deleted_at TIMESTAMPTZ;
+ALTER TABLE project_item ADD COLUMN deleted_at TIMESTAMPTZ;
+ALTER TABLE shop_item ADD COLUMN deleted_at TIMESTAMPTZ;
+ALTER TABLE tag ADD COLUMN deleted_at TIMESTAMPTZ;
+ALTER TABLE app_user ADD COLUMN deleted_at TIMESTAMPTZ;
+
+-- Used by the retention job to find rows due for purging
+CREATE INDEX blog_item_deleted_at_idx ON blog_item (deleted_at) WHERE deleted_at IS NOT NULL;
+CREATE INDEX project_item_deleted_at_idx ON project_item (deleted_at) WHERE deleted_at IS NOT NULL;
+CREATE INDEX shop_item_deleted_at_idx ON shop_item (deleted_at) WHERE deleted_at IS NOT NULL;
+CREATE INDEX tag_deleted_at_idx ON tag (deleted_at) WHERE deleted_at IS NOT NULL;
+CREATE INDEX app_user_deleted_at_idx ON app_user (deleted_at) WHERE deleted_at IS NOT NULL;
@@ -0,0 +1,101 @@
+-- When and by whom content rows were created and last updated, maintained
+-- by the stamp_row trigger. Rows that predate this migration count as
+-- created when it ran, by an unknown actor.
+ALTER TABLE blog_item
+    ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    ADD COLUMN created_by VARCHAR(255),
+    ADD COLUMN updated_by VARCHAR(255);
+ALTER TABLE content
+    ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    ADD COLUMN created_by VARCHAR(255),
+    ADD COLUMN updated_by VARCHAR(255);
+ALTER TABLE project_item
+    ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    ADD COLUMN created_by VARCHAR(255),
+    ADD COLUMN updated_by VARCHAR(255);
+ALTER TABLE project_desc_item
+    ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    ADD COLUMN created_by VARCHAR(255),
+    ADD COLUMN updated_by VARCHAR(255);
+ALTER TABLE shop_item
+    ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    ADD COLUMN created_by VARCHAR(255),
+    ADD COLUMN updated_by VARCHAR(255);
+ALTER TABLE shop_image
+    ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    ADD COLUMN created_by VARCHAR(255),
+    ADD COLUMN updated_by VARCHAR(255);
+ALTER TABLE shop_item_desc
+    ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    ADD COLUMN created_by VARCHAR(255),
+    ADD COLUMN updated_by VARCHAR(255);
+ALTER TABLE tag
+    ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    ADD COLUMN created_by VARCHAR(255),
+    ADD COLUMN updated_by VARCHAR(255);
+ALTER TABLE category
+    ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    ADD COLUMN created_by VARCHAR(255),
+    ADD COLUMN updated_by VARCHAR(255);
+
+CREATE INDEX blog_item_created_at_idx ON blog_item (created_at);
+CREATE INDEX blog_item_updated_at_idx ON blog_item (updated_at);
+CREATE INDEX project_item_created_at_idx ON project_item (created_at);
+CREATE INDEX project_item_updated_at_idx ON project_item (updated_at);
+CREATE INDEX shop_item_created_at_idx ON shop_item (created_at);
+CREATE INDEX shop_item_updated_at_idx ON shop_item (updated_at);
+
+-- Stamps inserts and changing updates with the time and the actor the
+-- application set for the transaction, see audit_row. The creation stamps
+-- cannot be overwritten, and updates that change nothing keep the row as it
+-- was, so they are not recorded by audit_row either.
+CREATE OR REPLACE FUNCTION stamp_row() RETURNS TRIGGER AS $$
+DECLARE
+    actor VARCHAR := NULLIF(current_setting('audit.actor', TRUE), '');
+BEGIN
+    IF TG_OP = 'INSERT' THEN
+        NEW.created_at := now();
+        NEW.created_by := actor;
+    ELSE
+        NEW.created_at := OLD.created_at;
+        NEW.created_by := OLD.created_by;
+        NEW.updated_at := OLD.updated_at;
+        NEW.updated_by := OLD.updated_by;
+        IF NEW IS NOT DISTINCT FROM OLD THEN
+            RETURN NEW;
+        END IF;
+    END IF;
+
+    NEW.updated_at := now();
+    NEW.updated_by := actor;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER stamp_blog_item BEFORE INSERT OR UPDATE ON blog_item
+    FOR EACH ROW EXECUTE FUNCTION stamp_row();
+CREATE TRIGGER stamp_content BEFORE INSERT OR UPDATE ON content
+    FOR EACH ROW EXECUTE FUNCTION stamp_row();
+CREATE TRIGGER stamp_project_item BEFORE INSERT OR UPDATE ON project_item
+    FOR EACH ROW EXECUTE FUNCTION stamp_row();
+CREATE TRIGGER stamp_project_desc_item BEFORE INSERT OR UPDATE ON project_desc_item
+    FOR EACH ROW EXECUTE FUNCTION stamp_row();
+CREATE TRIGGER stamp_shop_item BEFORE INSERT OR UPDATE ON shop_item
+    FOR EACH ROW EXECUTE FUNCTION stamp_row();
+CREATE TRIGGER stamp_shop_image BEFORE INSERT OR UPDATE ON shop_image
+    FOR EACH ROW EXECUTE FUNCTION stamp_row();
+CREATE TRIGGER stamp_shop_item_desc BEFORE INSERT OR UPDATE ON shop_item_desc
+    FOR EACH ROW EXECUTE FUNCTION stamp_row();
+CREATE TRIGGER stamp_tag BEFORE INSERT OR UPDATE ON tag
+    FOR EACH ROW EXECUTE FUNCTION stamp_row();
+CREATE TRIGGER stamp_category BEFORE INSERT OR UPDATE ON category
+    FOR EACH ROW EXECUTE FUNCTION stamp_row();
@@ -0,0 +1,12 @@
+-- Scope idempotency keys to the user and the endpoint they were sent to, so
+-- users choosing the same key do not see each other's responses. Anonymous
+-- requests have the empty identity, whatever IP they come from.
+ALTER TABLE idempotency_key DROP CONSTRAINT idempotency_key_pkey;
+
+ALTER TABLE idempotency_key
+    ADD COLUMN identity TEXT NOT NULL DEFAULT '',
+    ADD COLUMN request_method TEXT NOT NULL DEFAULT 'POST';
+
+ALTER TABLE idempotency_key ALTER COLUMN request_method DROP DEFAULT;
+
+ALTER TABLE idempotency_key ADD PRIMARY KEY (identity, request_method, request_path, key);
@@ -0,0 +1,285 @@
+//! Shared pagination, sorting and filtering for list queries
+//!
+//! List endpoints accept the common `limit`, `offset`, `cursor`, `sort` and
+//! `order` query parameters through [`PageParams`]. Offset pagination skips a
+//! number of rows, while cursor pagination continues after the row whose ID
+//! was returned as `next_cursor` in the previous page. When both are given,
+//! the cursor wins.
+//!
+//! Paging is opt-in: a request without `limit`, `offset` or `cursor` gets the
+//! whole list, as the lists did before they were paginated. Lists that grow
+//! without bound, such as the audit log, are [always paged](PageParams::always_paged).
+
+use either::{Either, Left, Right};
+use rocket::{FromForm, FromFormField};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use sqlx::postgres::PgRow;
+use sqlx::{FromRow, PgConnection, Postgres, QueryBuilder};
+
+/// Number of rows in a page when paging without a `limit`
+pub const DEFAULT_LIMIT: i64 = 50;
+/// Largest `limit` a client may request
+pub const MAX_LIMIT: i64 = 200;
+
+/// Direction of a sorted list
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, FromFormField, ToSchema)]
+#[schema(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// Common query parameters accepted by every list endpoint
+#[derive(Debug, Default, FromForm, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PageParams {
+    /// Maximum number of rows to return
+    pub limit: Option<i64>,
+    /// Number of rows to skip, ignored when `cursor` is set
+    pub offset: Option<i64>,
+    /// ID of the last row of the previous page
+    pub cursor: Option<i32>,
+    /// Name of the field to sort by, checked against each model's whitelist
+    pub sort: Option<String>,
+    /// Sort direction, defaults to ascending
+    pub order: Option<SortOrder>,
+}
+
+/// Pagination metadata returned alongside a page of results
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PageInfo {
+    /// Number of rows matching the filters, across all pages
+    pub total: i64,
+    /// Maximum number of rows in this page, absent if the whole list was returned
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<i64>,
+    /// Offset used for this page, if offset pagination was used
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<i64>,
+    /// Cursor to pass to fetch the next page, if there is one
+    pub next_cursor: Option<i32>,
+}
+
+/// A single page of rows together with its pagination metadata
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub info: PageInfo,
+}
+
+/// Models that can be paginated with a cursor expose their row ID
+pub trait Identifiable {
+    fn row_id(&self) -> Option<i32>;
+}
+
+impl PageParams {
+    /// Maximum number of rows to return, `None` if the request does not page
+    pub fn limit(&self) -> Option<i64> {
+        if self.limit.is_none() && self.offset.is_none() && self.cursor.is_none() {
+            return None;
+        }
+        Some(self.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT))
+    }
+
+    /// Pages the list even if the request does not ask for it
+    pub fn always_paged(self) -> Self {
+        Self {
+            limit: Some(self.limit.unwrap_or(DEFAULT_LIMIT)),
+            ..self
+        }
+    }
+
+    /// Resolves the requested sort field to a column name
+    ///
+    /// The first entry of `allowed` is the default. Returns `None` if the
+    /// requested field is not whitelisted.
+    pub fn sort_column(&self, allowed: &[(&str, &'static str)]) -> Option<&'static str> {
+        match &self.sort {
+            None => allowed.first().map(|(_, column)| *column),
+            Some(sort) => allowed
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(sort.trim()))
+                .map(|(_, column)| *column),
+        }
+    }
+}
+
+/// Escapes `LIKE` wildcards so user input only ever matches literally
+pub fn escape_like(text: &str) -> String {
+    text.trim()
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// Runs a paginated, sorted and filtered query against a single table
+///
+/// # Arguments
+/// * `select` - The `SELECT ... FROM <table>` part of the query, without a `WHERE` clause
+/// * `table` - The table being listed, which must have an `id` column
+/// * `sort_fields` - Whitelist of `(field, column)` pairs clients may sort by,
+///   whose columns must be `NOT NULL` since the cursor comparison never
+///   matches a NULL sort value
+/// * `params` - The pagination parameters from the request
+/// * `push_filters` - Appends `AND ...` conditions for model-specific filters
+///
+/// # Returns
+/// * `Right(())` if the requested sort field is not whitelisted
+pub async fn fetch_page<T, F>(
+    conn: &mut PgConnection,
+    select: &str,
+    table: &'static str,
+    sort_fields: &[(&str, &'static str)],
+    params: &PageParams,
+    push_filters: F,
+) -> Result<Page<T>, Either<sqlx::Error, ()>>
+where
+    T: for<'r> FromRow<'r, PgRow> + Identifiable + Send + Unpin,
+    F: Fn(&mut QueryBuilder<'_, Postgres>),
+{
+    let sort_column = params.sort_column(sort_fields).ok_or(Right(()))?;
+    let descending = params.order.unwrap_or_default() == SortOrder::Desc;
+    let limit = params.limit();
+
+    let mut count_query = QueryBuilder::new(format!("SELECT COUNT(*) FROM {} WHERE TRUE", table));
+    push_filters(&mut count_query);
+    let total: i64 = count_query
+        .build_query_scalar()
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(Left)?;
+
+    let mut query = QueryBuilder::new(select);
+    query.push(" WHERE TRUE");
+    push_filters(&mut query);
+
+    if let Some(cursor) = params.cursor {
+        query.push(format!(
+            " AND ({table}.{sort_column}, {table}.id) {} (SELECT cursor_row.{sort_column}, cursor_row.id FROM {table} AS cursor_row WHERE cursor_row.id = ",
+            if descending { "<" } else { ">" },
+        ));
+        query.push_bind(cursor);
+        query.push(")");
+    }
+
+    let direction = if descending { "DESC" } else { "ASC" };
+    query.push(format!(
+        " ORDER BY {table}.{sort_column} {direction}, {table}.id {direction}"
+    ));
+    if let Some(limit) = limit {
+        // Fetch one extra row to find out whether there is a next page
+        query.push(" LIMIT ");
+        query.push_bind(limit + 1);
+    }
+
+    let offset = match params.cursor {
+        Some(_) => None,
+        None => params.offset.map(|offset| offset.max(0)),
+    };
+    if let Some(offset) = offset {
+        query.push(" OFFSET ");
+        query.push_bind(offset);
+    }
+
+    let mut items: Vec<T> = query
+        .build_query_as()
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(Left)?;
+
+    let next_cursor = match limit {
+        Some(limit) if items.len() as i64 > limit => {
+            items.truncate(limit as usize);
+            items.last().and_then(Identifiable::row_id)
+        }
+        _ => None,
+    };
+
+    Ok(Page {
+        items,
+        info: PageInfo {
+            total,
+            limit,
+            offset,
+            next_cursor,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIELDS: &[(&str, &str)] = &[("id", "id"), ("title", "blog_title")];
+
+    fn sorted_by(sort: &str) -> PageParams {
+        PageParams {
+            sort: Some(sort.to_string()),
+            ..PageParams::default()
+        }
+    }
+
+    #[test]
+    fn sort_column_defaults_to_the_first_field() {
+        assert_eq!(PageParams::default().sort_column(FIELDS), Some("id"));
+        assert_eq!(PageParams::default().sort_column(&[]), None);
+    }
+
+    #[test]
+    fn sort_column_maps_fields_to_columns_ignoring_case() {
+        assert_eq!(sorted_by("title").sort_column(FIELDS), Some("blog_title"));
+        assert_eq!(sorted_by(" Title ").sort_column(FIELDS), Some("blog_title"));
+    }
+
+    #[test]
+    fn sort_column_rejects_fields_outside_the_whitelist() {
+        assert_eq!(sorted_by("blog_title").sort_column(FIELDS), None);
+        assert_eq!(sorted_by("id; DROP TABLE tag").sort_column(FIELDS), None);
+        assert_eq!(sorted_by("").sort_column(FIELDS), None);
+    }
+
+    #[test]
+    fn limit_is_clamped() {
+        let limited = |limit| PageParams {
+            limit,
+            ..PageParams::default()
+        };
+
+        assert_eq!(limited(Some(0)).limit(), Some(1));
+        assert_eq!(limited(Some(-5)).limit(), Some(1));
+        assert_eq!(limited(Some(MAX_LIMIT + 1)).limit(), Some(MAX_LIMIT));
+    }
+
+    #[test]
+    fn paging_is_opt_in() {
+        assert_eq!(PageParams::default().limit(), None);
+        assert_eq!(sorted_by("title").limit(), None);
+
+        let from_offset = PageParams {
+            offset: Some(100),
+            ..PageParams::default()
+        };
+        let from_cursor = PageParams {
+            cursor: Some(7),
+            ..PageParams::default()
+        };
+        assert_eq!(from_offset.limit(), Some(DEFAULT_LIMIT));
+        assert_eq!(from_cursor.limit(), Some(DEFAULT_LIMIT));
+        assert_eq!(PageParams::default().always_paged().limit(), Some(DEFAULT_LIMIT));
+    }
+
+    #[test]
+    fn escape_like_escapes_wildcards_and_backslashes() {
+        assert_eq!(escape_like("rust"), "rust");
+        assert_eq!(escape_like("100%"), "100\\%");
+        assert_eq!(escape_like("snake_case"), "snake\\_case");
+        assert_eq!(escape_like("C:\\dir"), "C:\\\\dir");
+        assert_eq!(escape_like("\\%"), "\\\\\\%");
+    }
+
+    #[test]
+    fn escape_like_trims_whitespace() {
+        assert_eq!(escape_like("  rust \n"), "rust");
+    }
+}
@@ -1,33 +1,65 @@
+use async_graphql::SimpleObject;
 use either::{Either, Left, Right};
 use futures::stream::TryStreamExt;
 use rocket_db_pools::Connection;
 use serde::{Deserialize, Serialize};
-use sqlx::{Acquire, Postgres, Transaction};
+use utoipa::ToSchema;
+use sqlx::{PgConnection, PgPool, Postgres, Transaction};
+use tracing::{debug, info};
 
+use super::audit;
+use super::pagination::{escape_like, fetch_page, Identifiable, Page, PageParams};
+use super::soft_delete::{self, Entity};
+use super::stamps::Stamps;
 use super::tag::Tag;
+use super::versioning::explain_mismatch;
+use crate::api::validation::{Validate, Validator};
+use crate::api::FieldError;
 use crate::Db;
 
-#[derive(Serialize, Deserialize, sqlx::FromRow)]
+#[derive(Serialize, Deserialize, ToSchema, SimpleObject, sqlx::FromRow)]
 #[sqlx(type_name = "project_item")]
+#[graphql(complex, name = "Project")]
 pub struct ProjectItem {
     #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
     pub id: Option<i32>,
     pub title: String,
     pub thumbnail_img_link: String,
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub version: Option<i32>,
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    #[graphql(flatten)]
+    pub stamps: Stamps,
     #[sqlx(skip)]
+    #[graphql(skip)]
     pub desc: Vec<DescItem>,
 }
 
-#[derive(Serialize, Deserialize, Clone, sqlx::FromRow)]
+/// Partial update of a project; omitted fields keep their value
+#[derive(Deserialize, ToSchema)]
+pub struct ProjectItemPatch {
+    pub title: Option<String>,
+    pub thumbnail_img_link: Option<String>,
+    /// Version the client last saw, required unless an `If-Match` header is sent
+    pub version: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, SimpleObject, sqlx::FromRow)]
 #[sqlx(type_name = "project_desc_item")]
+#[graphql(name = "ProjectDescription")]
 pub struct DescItem {
     #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
     pub id: Option<i32>,
     pub project_id: Option<i32>,
     pub content: String,
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    #[graphql(flatten)]
+    pub stamps: Stamps,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct DescItemMany {
     pub id: Option<i32>,
     pub project_id: Option<i32>,
@@ -36,13 +68,27 @@ pub struct DescItemMany {
 
 impl ProjectItem {
     pub async fn add(&self, mut db: Connection<Db>) -> Result<ProjectItem, sqlx::Error> {
-        let mut tx = (*db).begin().await?;
+        let mut tx = audit::begin(&mut db).await?;
+        let result = self.add_tx(&mut tx).await?;
+        tx.commit().await?;
+
+        Ok(result)
+    }
+
+    /// Inserts the project and its descriptions inside an existing transaction
+    pub async fn add_tx<'a>(
+        &self,
+        tx: &mut Transaction<'a, Postgres>,
+    ) -> Result<ProjectItem, sqlx::Error> {
         let result = sqlx::query!(
-            "INSERT INTO project_item (title, thumbnail_img_link) VALUES ($1, $2) RETURNING id",
+            "
+                INSERT INTO project_item (title, thumbnail_img_link) VALUES ($1, $2)
+                    RETURNING id, version, created_at, updated_at, created_by, updated_by
+            ",
             &self.title,
             &self.thumbnail_img_link,
         )
-        .fetch(&mut *tx)
+        .fetch(&mut **tx)
         .try_collect::<Vec<_>>()
         .await;
 
@@ -50,8 +96,8 @@ impl ProjectItem {
 
         match result {
             Ok(result) => {
-                println!("Successfully added new  {}", &self.title);
-                let id_returned = result.first().expect("returning result").id;
+                let returned = result.first().expect("returning result");
+                let id_returned = returned.id;
 
                 for content in &self.desc {
                     let content_copy = DescItem {
@@ -59,8 +105,9 @@ impl ProjectItem {
                         project_id: content.project_id,
 
                         content: content.content.clone(),
+                        stamps: Stamps::default(),
                     };
-                    let result = content_copy.add_tx(&mut tx).await;
+                    let result = content_copy.add_tx(tx).await;
 
                     match result {
                         Ok(resulting_content) => {
@@ -78,24 +125,29 @@ impl ProjectItem {
                     };
                 }
 
-                tx.commit().await?;
-
-                for content_item in &pushed_desc {
-                    println!("Inserted content {}", content_item.project_id.unwrap_or(-1));
-                }
+                info!(
+                    id = id_returned,
+                    title = %self.title,
+                    descriptions = pushed_desc.len(),
+                    "Created project"
+                );
 
                 Ok(ProjectItem {
                     id: Some(id_returned),
                     title: self.title.clone(),
                     thumbnail_img_link: self.thumbnail_img_link.clone(),
+                    version: Some(returned.version),
+                    stamps: Stamps {
+                        created_at: Some(returned.created_at),
+                        updated_at: Some(returned.updated_at),
+                        created_by: returned.created_by.clone(),
+                        updated_by: returned.updated_by.clone(),
+                    },
                     desc: pushed_desc,
                 })
             }
             Err(error) => {
-                println!(
-                    "Error when creating new blog item with: [ {} ]",
-                    &self.title
-                );
+                debug!(%error, title = %self.title, "Failed to create project");
                 Err(error)
             }
         }
@@ -106,7 +158,7 @@ impl ProjectItem {
         mut db: Connection<Db>,
         tags: Vec<&Tag>,
     ) -> Result<(), sqlx::Error> {
-        let mut tx = (*db).begin().await?;
+        let mut tx = audit::begin(&mut db).await?;
 
         for tag in tags {
             let result = sqlx::query!(
@@ -120,14 +172,97 @@ impl ProjectItem {
 
             result?;
         }
+        tx.commit().await?;
+
         Ok(())
     }
 
-    pub async fn get_all(mut db: Connection<Db>) -> Result<Vec<ProjectItem>, sqlx::Error> {
-        sqlx::query_as("SELECT id, title, thumbnail_img_link FROM project_item")
-            .fetch_all(&mut **db)
-            .await
-        // TODO: Add custom completion prints
+    /// Applies a partial update, provided the project is still at `expected_version`,
+    /// or is at any version if that is `None`
+    ///
+    /// The project's descriptions are not loaded into the returned item.
+    ///
+    /// # Returns
+    /// * `Right(version)` with the current version if the project was changed in the meantime
+    pub async fn update(
+        mut db: Connection<Db>,
+        id: i32,
+        patch: &ProjectItemPatch,
+        expected_version: Option<i32>,
+    ) -> Result<ProjectItem, Either<sqlx::Error, i32>> {
+        let mut tx = audit::begin(&mut db).await.map_err(Left)?;
+        let result = sqlx::query_as(
+            "
+                UPDATE project_item SET
+                    title = COALESCE($2, title),
+                    thumbnail_img_link = COALESCE($3, thumbnail_img_link),
+                    version = version + 1
+                WHERE id = $1 AND ($4::INT4 IS NULL OR version = $4) AND deleted_at IS NULL
+                RETURNING id, title, thumbnail_img_link, version, created_at, updated_at, created_by, updated_by
+            ",
+        )
+        .bind(id)
+        .bind(patch.title.as_deref())
+        .bind(patch.thumbnail_img_link.as_deref())
+        .bind(expected_version)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(Left)?;
+
+        match result {
+            Some(project_item) => {
+                tx.commit().await.map_err(Left)?;
+                Ok(project_item)
+            }
+            None => Err(explain_mismatch(&mut tx, "project_item", id).await),
+        }
+    }
+
+    /// Soft deletes the project, returning whether it existed and was not deleted yet
+    pub async fn delete(mut db: Connection<Db>, id: i32) -> Result<bool, sqlx::Error> {
+        let mut tx = audit::begin(&mut db).await?;
+        let deleted = soft_delete::delete(&mut tx, Entity::Project, id).await?;
+        tx.commit().await?;
+
+        if deleted {
+            info!(id, "Deleted project");
+        }
+        Ok(deleted)
+    }
+
+    /// Fields that project lists may be sorted by, the first being the default
+    pub const SORT_FIELDS: &'static [(&'static str, &'static str)] = &[
+        ("id", "id"),
+        ("title", "title"),
+        ("created_at", "created_at"),
+        ("updated_at", "updated_at"),
+    ];
+
+    /// Lists projects one page at a time, optionally filtered by a title search
+    pub async fn get_all(
+        db: &mut PgConnection,
+        params: &PageParams,
+        search: Option<&str>,
+    ) -> Result<Page<ProjectItem>, Either<sqlx::Error, ()>> {
+        fetch_page(
+            db,
+            "
+                SELECT id, title, thumbnail_img_link, version, created_at, updated_at, created_by, updated_by
+                FROM project_item
+            ",
+            "project_item",
+            Self::SORT_FIELDS,
+            params,
+            |query| {
+                query.push(" AND project_item.deleted_at IS NULL");
+                if let Some(search) = search {
+                    query.push(" AND project_item.title ILIKE '%' || ");
+                    query.push_bind(escape_like(search));
+                    query.push(" || '%'");
+                }
+            },
+        )
+        .await
     }
 
     pub async fn get_projects_by_tag(
@@ -136,9 +271,10 @@ impl ProjectItem {
     ) -> Result<Vec<ProjectItem>, sqlx::Error> {
         sqlx::query_as(
             "
-                SELECT project_item.id, project_item.thumbnail_img_link FROM project_item 
+                SELECT project_item.id, project_item.title, project_item.thumbnail_img_link, project_item.version,
+                    project_item.created_at, project_item.updated_at, project_item.created_by, project_item.updated_by FROM project_item
                     INNER JOIN project_tech_tag ON project_item.id=project_tech_tag.project_id
-                    WHERE project_tech_tag.tag_id = $1
+                    WHERE project_tech_tag.tag_id = $1 AND project_item.deleted_at IS NULL
             ",
         )
         .bind(tag_id)
@@ -148,31 +284,55 @@ impl ProjectItem {
     }
 }
 
+impl Identifiable for ProjectItem {
+    fn row_id(&self) -> Option<i32> {
+        self.id
+    }
+}
+
+impl Validate for ProjectItem {
+    fn validate(&self) -> Vec<FieldError> {
+        Validator::new()
+            .not_blank("title", &self.title)
+            .max_length("title", &self.title, 200)
+            .url("thumbnail_img_link", &self.thumbnail_img_link)
+            .each("desc", &self.desc)
+            .finish()
+    }
+}
+
+impl Validate for ProjectItemPatch {
+    fn validate(&self) -> Vec<FieldError> {
+        let mut validator = Validator::new();
+        if let Some(title) = &self.title {
+            validator = validator
+                .not_blank("title", title)
+                .max_length("title", title, 200);
+        }
+        if let Some(thumbnail_img_link) = &self.thumbnail_img_link {
+            validator = validator.url("thumbnail_img_link", thumbnail_img_link);
+        }
+        validator.finish()
+    }
+}
+
+impl Validate for DescItem {
+    fn validate(&self) -> Vec<FieldError> {
+        // project_id is left to the routes, since it is filled in by the
+        // server when descriptions are created along with their project
+        Validator::new()
+            .not_blank("content", &self.content)
+            .finish()
+    }
+}
+
 impl DescItem {
     pub async fn add(&self, mut db: Connection<Db>) -> Result<DescItem, Either<sqlx::Error, ()>> {
-        match &self.project_id {
-            Some(project_id) => {
-                let result = sqlx::query_as!(DescItem,
-                    "INSERT INTO project_desc_item (project_id, content) VALUES ($1, $2) RETURNING id, project_id, content",
-                    project_id,
-                    &self.content,
-                )
-                    .fetch_one(&mut **db)
-                .await;
+        let mut tx = audit::begin(&mut db).await.map_err(Left)?;
+        let result = self.add_tx(&mut tx).await?;
+        tx.commit().await.map_err(Left)?;
 
-                match result {
-                    Ok(record) => {
-                        println!("Successfully added new project description");
-                        Ok(record)
-                    }
-                    Err(error) => {
-                        println!("Error when creating new project description");
-                        Err(Left(error))
-                    }
-                }
-            }
-            None => Err(Right(())),
-        }
+        Ok(result)
     }
 
     pub async fn add_tx<'a>(
@@ -181,8 +341,12 @@ impl DescItem {
     ) -> Result<DescItem, Either<sqlx::Error, ()>> {
         match &self.project_id {
             Some(project_id) => {
-                let result = sqlx::query_as!(DescItem,
-                    "INSERT INTO project_desc_item (project_id, content) VALUES ($1, $2) RETURNING id, project_id, content",
+                let result = sqlx::query_as!(
+                    DescItem,
+                    "
+                        INSERT INTO project_desc_item (project_id, content) VALUES ($1, $2)
+                            RETURNING id, project_id, content, ROW(created_at, updated_at, created_by, updated_by) AS \"stamps!: Stamps\"
+                    ",
                     project_id,
                     &self.content,
                 )
@@ -191,11 +355,11 @@ impl DescItem {
 
                 match result {
                     Ok(record) => {
-                        println!("Successfully added new content");
+                        debug!(project_id, "Created project description");
                         Ok(record)
                     }
                     Err(error) => {
-                        println!("Error when creating new content");
+                        debug!(%error, project_id, "Failed to create project description");
                         Err(Left(error))
                     }
                 }
@@ -210,11 +374,31 @@ impl DescItem {
     ) -> Result<Vec<DescItem>, sqlx::Error> {
         sqlx::query_as!(
             DescItem,
-            r#"SELECT id, project_id, content FROM project_desc_item WHERE project_id=$1"#,
-            project_id
+            "
+                SELECT id, project_id, content, ROW(created_at, updated_at, created_by, updated_by) AS \"stamps!: Stamps\"
+                FROM project_desc_item WHERE project_id=$1
+            ",
+            project_id,
         )
         .fetch_all(&mut **db)
         .await
         // TODO: Add custom completion prints
     }
+
+    /// Loads the descriptions of several projects at once
+    pub async fn get_all_from_projects(
+        pool: &PgPool,
+        project_ids: &[i32],
+    ) -> Result<Vec<DescItem>, sqlx::Error> {
+        sqlx::query_as!(
+            DescItem,
+            "
+                SELECT id, project_id, content, ROW(created_at, updated_at, created_by, updated_by) AS \"stamps!: Stamps\"
+                FROM project_desc_item WHERE project_id = ANY($1) ORDER BY id
+            ",
+            project_ids,
+        )
+        .fetch_all(pool)
+        .await
+    }
 }
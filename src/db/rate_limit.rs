@@ -0,0 +1,60 @@
+//! Token buckets stored in Postgres
+//!
+//! Used instead of the in-memory buckets when several instances serve the
+//! same clients. Each take refills the bucket for the time since its last
+//! update and removes a token if one is available, in a single statement.
+//! Buckets that have been idle for a day are deleted from time to time.
+
+use sqlx::PgPool;
+
+/// Outcome of taking a token from a stored bucket
+pub struct BucketState {
+    pub allowed: bool,
+    /// Tokens left after the take, possibly a fraction
+    pub tokens: f64,
+}
+
+/// Takes a token from the bucket `key`, creating it full if it does not exist
+///
+/// # Arguments
+/// * `capacity` - Most tokens the bucket holds
+/// * `refill_per_second` - Tokens added per second
+pub async fn take(
+    pool: &PgPool,
+    key: &str,
+    capacity: f64,
+    refill_per_second: f64,
+) -> Result<BucketState, sqlx::Error> {
+    sqlx::query_as!(
+        BucketState,
+        "
+            INSERT INTO rate_limit_bucket AS bucket (key, tokens, allowed)
+                VALUES ($1, $2::FLOAT8 - 1, TRUE)
+            ON CONFLICT (key) DO UPDATE SET
+                allowed = LEAST($2::FLOAT8, bucket.tokens + EXTRACT(EPOCH FROM now() - bucket.updated_at)::FLOAT8 * $3::FLOAT8) >= 1,
+                tokens = LEAST($2::FLOAT8, bucket.tokens + EXTRACT(EPOCH FROM now() - bucket.updated_at)::FLOAT8 * $3::FLOAT8)
+                    - CASE
+                        WHEN LEAST($2::FLOAT8, bucket.tokens + EXTRACT(EPOCH FROM now() - bucket.updated_at)::FLOAT8 * $3::FLOAT8) >= 1
+                        THEN 1
+                        ELSE 0
+                    END,
+                updated_at = now()
+            RETURNING allowed, tokens
+        ",
+        key,
+        capacity,
+        refill_per_second,
+    )
+    .fetch_one(pool)
+    .await
+}
+
+/// Deletes the buckets that have not been used for a day, which are full again
+pub async fn prune(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let result =
+        sqlx::query!("DELETE FROM rate_limit_bucket WHERE updated_at < now() - INTERVAL '1 day'")
+            .execute(pool)
+            .await?;
+
+    Ok(result.rows_affected())
+}
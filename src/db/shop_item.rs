@@ -1,39 +1,77 @@
+use async_graphql::SimpleObject;
 use futures::stream::TryStreamExt;
 use rocket_db_pools::Connection;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use sqlx::Either::{self};
+use sqlx::{PgConnection, PgPool};
+use tracing::{debug, info};
 
+use super::audit;
+use super::pagination::{escape_like, fetch_page, Identifiable, Page, PageParams};
+use super::soft_delete::{self, Entity};
+use super::stamps::Stamps;
+use super::versioning::explain_mismatch;
+use crate::api::validation::{Validate, Validator};
+use crate::api::FieldError;
 use crate::Db;
 
-#[derive(Serialize, Deserialize, sqlx::FromRow)]
+#[derive(Serialize, Deserialize, ToSchema, SimpleObject, sqlx::FromRow)]
 #[sqlx(type_name = "shop_item")]
+#[graphql(complex)]
 pub struct ShopItem {
     #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
     pub id: Option<i32>,
     pub iname: String,
     pub img_link: String,
     pub price: f32,
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub version: Option<i32>,
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    #[graphql(flatten)]
+    pub stamps: Stamps,
+}
+
+/// Partial update of a shop item; omitted fields keep their value
+#[derive(Deserialize, ToSchema)]
+pub struct ShopItemPatch {
+    pub iname: Option<String>,
+    pub img_link: Option<String>,
+    pub price: Option<f32>,
+    /// Version the client last saw, required unless an `If-Match` header is sent
+    pub version: Option<i32>,
 }
 
-#[derive(Serialize, Deserialize, sqlx::FromRow)]
+#[derive(Serialize, Deserialize, ToSchema, Clone, SimpleObject, sqlx::FromRow)]
 #[sqlx(type_name = "shop_image")]
+#[graphql(name = "ShopItemImage")]
 pub struct ShopImage {
     pub id: Option<i32>,
     pub shop_item_id: Option<i32>,
     pub tooltip: String,
     pub img_link: String,
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    #[graphql(flatten)]
+    pub stamps: Stamps,
 }
 
-#[derive(Serialize, Deserialize, sqlx::FromRow)]
+#[derive(Serialize, Deserialize, ToSchema, Clone, SimpleObject, sqlx::FromRow)]
 #[sqlx(type_name = "shop_item_desc")]
+#[graphql(name = "ShopItemDescription")]
 pub struct ShopItemDesc {
     pub id: Option<i32>,
     pub shop_item_id: Option<i32>,
     pub content: String,
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    #[graphql(flatten)]
+    pub stamps: Stamps,
 }
 
 // NOTE: Not a database model
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct ShopItemDescMany {
     pub id: Option<i32>,
     pub shop_item_id: Option<i32>,
@@ -42,32 +80,48 @@ pub struct ShopItemDescMany {
 
 impl ShopItem {
     pub async fn add(&self, mut db: Connection<Db>) -> Result<ShopItem, sqlx::Error> {
+        let mut tx = audit::begin(&mut db).await?;
+        let result = self.add_tx(&mut tx).await?;
+        tx.commit().await?;
+
+        Ok(result)
+    }
+
+    /// Inserts the shop item on the given connection, which may be inside a transaction
+    pub async fn add_tx(&self, db: &mut PgConnection) -> Result<ShopItem, sqlx::Error> {
         let result = sqlx::query!(
-            "INSERT INTO shop_item (iname, img_link, price) VALUES ($1, $2, $3) RETURNING id",
+            "
+                INSERT INTO shop_item (iname, img_link, price) VALUES ($1, $2, $3)
+                    RETURNING id, version, created_at, updated_at, created_by, updated_by
+            ",
             &self.iname,
             &self.img_link,
             &self.price
         )
-        .fetch(&mut **db)
+        .fetch(&mut *db)
         .try_collect::<Vec<_>>()
         .await;
 
         match result {
             Ok(result) => {
-                println!("Successfully added new  {}", &self.iname);
-                let id_returned = result.first().expect("returning result").id;
+                info!(name = %self.iname, "Created shop item");
+                let returned = result.first().expect("returning result");
                 Ok(ShopItem {
-                    id: Some(id_returned),
+                    id: Some(returned.id),
                     iname: self.iname.clone(),
                     img_link: self.img_link.clone(),
                     price: self.price,
+                    version: Some(returned.version),
+                    stamps: Stamps {
+                        created_at: Some(returned.created_at),
+                        updated_at: Some(returned.updated_at),
+                        created_by: returned.created_by.clone(),
+                        updated_by: returned.updated_by.clone(),
+                    },
                 })
             }
             Err(error) => {
-                println!(
-                    "Error when creating new shop item with: [ {} ]",
-                    &self.iname
-                );
+                debug!(%error, name = %self.iname, "Failed to create shop item");
                 Err(error)
             }
         }
@@ -76,7 +130,10 @@ impl ShopItem {
     pub async fn get_by_id(mut db: Connection<Db>, id: i32) -> Result<ShopItem, sqlx::Error> {
         sqlx::query_as!(
             ShopItem,
-            "SELECT id, iname, img_link, price FROM shop_item WHERE id=$1",
+            "
+                SELECT id, iname, img_link, price, version, ROW(created_at, updated_at, created_by, updated_by) AS \"stamps!: Stamps\"
+                FROM shop_item WHERE id=$1 AND deleted_at IS NULL
+            ",
             id,
         )
         .fetch_one(&mut **db)
@@ -84,37 +141,240 @@ impl ShopItem {
         // TODO: Add custom completion prints
     }
 
-    pub async fn get_all(mut db: Connection<Db>) -> Result<Vec<ShopItem>, sqlx::Error> {
-        sqlx::query_as!(ShopItem, "SELECT id, iname, img_link, price FROM shop_item",)
-            .fetch_all(&mut **db)
-            .await
-        // TODO: Add custom completion prints
+    /// Applies a partial update, provided the item is still at `expected_version`,
+    /// or is at any version if that is `None`
+    ///
+    /// # Returns
+    /// * `Right(version)` with the current version if the item was changed in the meantime
+    pub async fn update(
+        mut db: Connection<Db>,
+        id: i32,
+        patch: &ShopItemPatch,
+        expected_version: Option<i32>,
+    ) -> Result<ShopItem, Either<sqlx::Error, i32>> {
+        let mut tx = audit::begin(&mut db).await.map_err(Either::Left)?;
+        let result = sqlx::query_as!(
+            ShopItem,
+            "
+                UPDATE shop_item SET
+                    iname = COALESCE($2, iname),
+                    img_link = COALESCE($3, img_link),
+                    price = COALESCE($4, price),
+                    version = version + 1
+                WHERE id = $1 AND ($5::INT4 IS NULL OR version = $5) AND deleted_at IS NULL
+                RETURNING id, iname, img_link, price, version, ROW(created_at, updated_at, created_by, updated_by) AS \"stamps!: Stamps\"
+            ",
+            id,
+            patch.iname.as_deref(),
+            patch.img_link.as_deref(),
+            patch.price,
+            expected_version,
+        )
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(Either::Left)?;
+
+        match result {
+            Some(shop_item) => {
+                tx.commit().await.map_err(Either::Left)?;
+                Ok(shop_item)
+            }
+            None => Err(explain_mismatch(&mut tx, "shop_item", id).await),
+        }
+    }
+
+    /// Soft deletes the shop item, returning whether it existed and was not deleted yet
+    pub async fn delete(mut db: Connection<Db>, id: i32) -> Result<bool, sqlx::Error> {
+        let mut tx = audit::begin(&mut db).await?;
+        let deleted = soft_delete::delete(&mut tx, Entity::ShopItem, id).await?;
+        tx.commit().await?;
+
+        if deleted {
+            info!(id, "Deleted shop item");
+        }
+        Ok(deleted)
+    }
+
+    /// Fields that shop item lists may be sorted by, the first being the default
+    pub const SORT_FIELDS: &'static [(&'static str, &'static str)] = &[
+        ("id", "id"),
+        ("name", "iname"),
+        ("price", "price"),
+        ("created_at", "created_at"),
+        ("updated_at", "updated_at"),
+    ];
+
+    /// Lists shop items one page at a time
+    ///
+    /// # Arguments
+    /// * `search` - Only include items whose name contains this text, ignoring case
+    /// * `min_price` - Only include items costing at least this much
+    /// * `max_price` - Only include items costing at most this much
+    pub async fn get_all(
+        db: &mut PgConnection,
+        params: &PageParams,
+        search: Option<&str>,
+        min_price: Option<f32>,
+        max_price: Option<f32>,
+    ) -> Result<Page<ShopItem>, Either<sqlx::Error, ()>> {
+        fetch_page(
+            db,
+            "
+                SELECT id, iname, img_link, price, version, created_at, updated_at, created_by, updated_by
+                FROM shop_item
+            ",
+            "shop_item",
+            Self::SORT_FIELDS,
+            params,
+            |query| {
+                query.push(" AND shop_item.deleted_at IS NULL");
+                if let Some(search) = search {
+                    query.push(" AND shop_item.iname ILIKE '%' || ");
+                    query.push_bind(escape_like(search));
+                    query.push(" || '%'");
+                }
+                if let Some(min_price) = min_price {
+                    query.push(" AND shop_item.price >= ");
+                    query.push_bind(min_price);
+                }
+                if let Some(max_price) = max_price {
+                    query.push(" AND shop_item.price <= ");
+                    query.push_bind(max_price);
+                }
+            },
+        )
+        .await
+    }
+
+    pub async fn get_shop_items_by_tag(
+        mut db: Connection<Db>,
+        tag_id: i32,
+    ) -> Result<Vec<ShopItem>, sqlx::Error> {
+        sqlx::query_as!(
+            ShopItem,
+            "
+                SELECT shop_item.id, shop_item.iname, shop_item.img_link, shop_item.price, shop_item.version,
+                    ROW(shop_item.created_at, shop_item.updated_at, shop_item.created_by, shop_item.updated_by) AS \"stamps!: Stamps\" FROM shop_item
+                    INNER JOIN shop_item_tag ON shop_item.id=shop_item_tag.shop_item_id
+                    WHERE shop_item_tag.tag_id = $1 AND shop_item.deleted_at IS NULL
+            ",
+            tag_id,
+        )
+        .fetch_all(&mut **db)
+        .await
+    }
+}
+
+impl Identifiable for ShopItem {
+    fn row_id(&self) -> Option<i32> {
+        self.id
+    }
+}
+
+impl Validate for ShopItem {
+    fn validate(&self) -> Vec<FieldError> {
+        Validator::new()
+            .not_blank("iname", &self.iname)
+            .max_length("iname", &self.iname, 200)
+            .url("img_link", &self.img_link)
+            .min_value("price", self.price, 0.0)
+            .finish()
+    }
+}
+
+impl Validate for ShopItemPatch {
+    fn validate(&self) -> Vec<FieldError> {
+        let mut validator = Validator::new();
+        if let Some(iname) = &self.iname {
+            validator = validator
+                .not_blank("iname", iname)
+                .max_length("iname", iname, 200);
+        }
+        if let Some(img_link) = &self.img_link {
+            validator = validator.url("img_link", img_link);
+        }
+        if let Some(price) = self.price {
+            validator = validator.min_value("price", price, 0.0);
+        }
+        validator.finish()
+    }
+}
+
+impl Validate for ShopImage {
+    fn validate(&self) -> Vec<FieldError> {
+        Validator::new()
+            .required_id("shop_item_id", self.shop_item_id)
+            .max_length("tooltip", &self.tooltip, 200)
+            .url("img_link", &self.img_link)
+            .finish()
+    }
+}
+
+impl Validate for ShopItemDesc {
+    fn validate(&self) -> Vec<FieldError> {
+        Validator::new()
+            .required_id("shop_item_id", self.shop_item_id)
+            .not_blank("content", &self.content)
+            .finish()
+    }
+}
+
+impl Validate for ShopItemDescMany {
+    fn validate(&self) -> Vec<FieldError> {
+        let validator = Validator::new()
+            .required_id("shop_item_id", self.shop_item_id)
+            .check("contents", !self.contents.is_empty(), "must not be empty");
+        self.contents
+            .iter()
+            .enumerate()
+            .fold(validator, |validator, (index, content)| {
+                validator.not_blank(&format!("contents[{}]", index), content)
+            })
+            .finish()
     }
 }
 
 impl ShopImage {
     pub async fn add(&self, mut db: Connection<Db>) -> Result<ShopImage, Either<sqlx::Error, ()>> {
+        let mut tx = audit::begin(&mut db).await.map_err(Either::Left)?;
+        let result = self.add_tx(&mut tx).await?;
+        tx.commit().await.map_err(Either::Left)?;
+
+        Ok(result)
+    }
+
+    /// Inserts the image on the given connection, which may be inside a transaction
+    pub async fn add_tx(
+        &self,
+        db: &mut PgConnection,
+    ) -> Result<ShopImage, Either<sqlx::Error, ()>> {
         match &self.shop_item_id {
             Some(shop_item_id) => {
                 // TODO: Copy this implementation of query_as to the other insert functions
-                let result = sqlx::query_as!(ShopImage,
-                    "INSERT INTO shop_image (shop_item_id, tooltip, img_link) VALUES ($1, $2, $3) RETURNING id, shop_item_id, tooltip, img_link"
-                    , shop_item_id, &self.tooltip, &self.img_link
-                ).fetch_one(&mut **db).await;
+                let result = sqlx::query_as!(
+                    ShopImage,
+                    "
+                        INSERT INTO shop_image (shop_item_id, tooltip, img_link) VALUES ($1, $2, $3)
+                            RETURNING id, shop_item_id, tooltip, img_link, ROW(created_at, updated_at, created_by, updated_by) AS \"stamps!: Stamps\"
+                    ",
+                    shop_item_id,
+                    &self.tooltip,
+                    &self.img_link,
+                )
+                .fetch_one(&mut *db)
+                .await;
 
                 match result {
                     Ok(resulting_shop_image) => {
-                        println!(
-                            "Successfully added new shop item image {}",
-                            resulting_shop_image.tooltip
+                        info!(
+                            shop_item_id,
+                            tooltip = %resulting_shop_image.tooltip,
+                            "Created shop item image"
                         );
                         Ok(resulting_shop_image)
                     }
                     Err(error) => {
-                        println!(
-                            "Error when creating new shop item image with tooltip: {}",
-                            &self.tooltip
-                        );
+                        debug!(%error, shop_item_id, tooltip = %self.tooltip, "Failed to create shop item image");
                         Err(Either::Left(error))
                     }
                 }
@@ -129,34 +389,73 @@ impl ShopImage {
     ) -> Result<Vec<ShopImage>, sqlx::Error> {
         sqlx::query_as!(
             ShopImage,
-            "SELECT id, shop_item_id, tooltip, img_link FROM shop_image WHERE shop_item_id=$1",
-            id
+            "
+                SELECT id, shop_item_id, tooltip, img_link, ROW(created_at, updated_at, created_by, updated_by) AS \"stamps!: Stamps\"
+                FROM shop_image WHERE shop_item_id=$1
+            ",
+            id,
         )
         .fetch_all(&mut **db)
         .await
     }
+
+    /// Loads the images of several shop items at once
+    pub async fn get_all_from_shop_items(
+        pool: &PgPool,
+        shop_item_ids: &[i32],
+    ) -> Result<Vec<ShopImage>, sqlx::Error> {
+        sqlx::query_as!(
+            ShopImage,
+            "
+                SELECT id, shop_item_id, tooltip, img_link, ROW(created_at, updated_at, created_by, updated_by) AS \"stamps!: Stamps\"
+                FROM shop_image WHERE shop_item_id = ANY($1) ORDER BY id
+            ",
+            shop_item_ids,
+        )
+        .fetch_all(pool)
+        .await
+    }
 }
 
 impl ShopItemDesc {
     pub async fn add(
         &self,
         mut db: Connection<Db>,
+    ) -> Result<ShopItemDesc, Either<sqlx::Error, ()>> {
+        let mut tx = audit::begin(&mut db).await.map_err(Either::Left)?;
+        let result = self.add_tx(&mut tx).await?;
+        tx.commit().await.map_err(Either::Left)?;
+
+        Ok(result)
+    }
+
+    /// Inserts the description on the given connection, which may be inside a transaction
+    pub async fn add_tx(
+        &self,
+        db: &mut PgConnection,
     ) -> Result<ShopItemDesc, Either<sqlx::Error, ()>> {
         match &self.shop_item_id {
             Some(shop_item_id) => {
                 // TODO: Copy this implementation of query_as to the other insert functions
-                let result = sqlx::query_as!(ShopItemDesc,
-                    "INSERT INTO shop_item_desc (shop_item_id, content) VALUES ($1, $2) RETURNING id, shop_item_id, content"
-                    , shop_item_id, &self.content
-                ).fetch_one(&mut **db).await;
+                let result = sqlx::query_as!(
+                    ShopItemDesc,
+                    "
+                        INSERT INTO shop_item_desc (shop_item_id, content) VALUES ($1, $2)
+                            RETURNING id, shop_item_id, content, ROW(created_at, updated_at, created_by, updated_by) AS \"stamps!: Stamps\"
+                    ",
+                    shop_item_id,
+                    &self.content,
+                )
+                .fetch_one(&mut *db)
+                .await;
 
                 match result {
                     Ok(resulting_shop_image) => {
-                        println!("Successfully added new shop item desc");
+                        info!(shop_item_id, "Created shop item description");
                         Ok(resulting_shop_image)
                     }
                     Err(error) => {
-                        println!("Error when creating new shop item");
+                        debug!(%error, shop_item_id, "Failed to create shop item description");
                         Err(Either::Left(error))
                     }
                 }
@@ -171,10 +470,30 @@ impl ShopItemDesc {
     ) -> Result<Vec<ShopItemDesc>, sqlx::Error> {
         sqlx::query_as!(
             ShopItemDesc,
-            "SELECT id, shop_item_id, content FROM shop_item_desc WHERE shop_item_id=$1",
-            id
+            "
+                SELECT id, shop_item_id, content, ROW(created_at, updated_at, created_by, updated_by) AS \"stamps!: Stamps\"
+                FROM shop_item_desc WHERE shop_item_id=$1
+            ",
+            id,
         )
         .fetch_all(&mut **db)
         .await
     }
+
+    /// Loads the descriptions of several shop items at once
+    pub async fn get_all_from_shop_items(
+        pool: &PgPool,
+        shop_item_ids: &[i32],
+    ) -> Result<Vec<ShopItemDesc>, sqlx::Error> {
+        sqlx::query_as!(
+            ShopItemDesc,
+            "
+                SELECT id, shop_item_id, content, ROW(created_at, updated_at, created_by, updated_by) AS \"stamps!: Stamps\"
+                FROM shop_item_desc WHERE shop_item_id = ANY($1) ORDER BY id
+            ",
+            shop_item_ids,
+        )
+        .fetch_all(pool)
+        .await
+    }
 }
@@ -0,0 +1,172 @@
+//! Soft deletion of content and users
+//!
+//! Deleting a blog, project, shop item, tag or user only sets its
+//! `deleted_at`, and every list and lookup query skips rows where it is set.
+//! Links to other rows are kept, so a restored row comes back exactly as it
+//! was. Purging deletes a soft-deleted row for good, along with the rows that
+//! belong to it; [`purge_expired`] does so for every row deleted longer ago
+//! than the retention period.
+
+use rocket::request::FromParam;
+use serde::Serialize;
+use sqlx::PgConnection;
+use utoipa::ToSchema;
+
+/// Kinds of rows that are soft deleted, named like their API paths
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+pub enum Entity {
+    #[serde(rename = "blogs")]
+    Blog,
+    #[serde(rename = "projects")]
+    Project,
+    #[serde(rename = "shop-items")]
+    ShopItem,
+    #[serde(rename = "tags")]
+    Tag,
+    #[serde(rename = "users")]
+    User,
+}
+
+impl Entity {
+    pub const ALL: [Entity; 5] = [
+        Entity::Blog,
+        Entity::Project,
+        Entity::ShopItem,
+        Entity::Tag,
+        Entity::User,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Entity::Blog => "blogs",
+            Entity::Project => "projects",
+            Entity::ShopItem => "shop-items",
+            Entity::Tag => "tags",
+            Entity::User => "users",
+        }
+    }
+
+    fn table(self) -> &'static str {
+        match self {
+            Entity::Blog => "blog_item",
+            Entity::Project => "project_item",
+            Entity::ShopItem => "shop_item",
+            Entity::Tag => "tag",
+            Entity::User => "app_user",
+        }
+    }
+
+    /// Tables with rows that belong to a row of this table, and their referencing column
+    fn dependents(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Entity::Blog => &[("content", "blog_id"), ("blog_tag", "blog_id")],
+            Entity::Project => &[
+                ("project_desc_item", "project_id"),
+                ("project_tech_tag", "project_id"),
+            ],
+            Entity::ShopItem => &[
+                ("shop_image", "shop_item_id"),
+                ("shop_item_desc", "shop_item_id"),
+                ("shop_item_tag", "shop_item_id"),
+            ],
+            Entity::Tag => &[
+                ("project_tech_tag", "tag_id"),
+                ("blog_tag", "tag_id"),
+                ("shop_item_tag", "tag_id"),
+                ("tag_category_join", "tag_id"),
+            ],
+            Entity::User => &[],
+        }
+    }
+}
+
+impl<'a> FromParam<'a> for Entity {
+    type Error = &'a str;
+
+    fn from_param(param: &'a str) -> Result<Self, Self::Error> {
+        Entity::ALL
+            .into_iter()
+            .find(|entity| entity.name() == param)
+            .ok_or(param)
+    }
+}
+
+/// Marks a row as deleted
+///
+/// Returns whether the row existed and was not deleted yet.
+pub async fn delete(conn: &mut PgConnection, entity: Entity, id: i32) -> Result<bool, sqlx::Error> {
+    let query = format!(
+        "UPDATE {} SET deleted_at = now() WHERE id = $1 AND deleted_at IS NULL",
+        entity.table()
+    );
+    let result = sqlx::query(&query).bind(id).execute(conn).await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Brings back a soft-deleted row
+///
+/// Returns whether the row existed and was deleted.
+pub async fn restore(conn: &mut PgConnection, entity: Entity, id: i32) -> Result<bool, sqlx::Error> {
+    let query = format!(
+        "UPDATE {} SET deleted_at = NULL WHERE id = $1 AND deleted_at IS NOT NULL",
+        entity.table()
+    );
+    let result = sqlx::query(&query).bind(id).execute(conn).await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Deletes soft-deleted rows for good, along with the rows that belong to them
+///
+/// # Arguments
+/// * `condition` - Which soft-deleted rows to purge, with `value` bound to `$1`
+///
+/// # Returns
+/// * The number of rows of `entity`'s table purged
+async fn purge_where(
+    conn: &mut PgConnection,
+    entity: Entity,
+    condition: &str,
+    value: i32,
+) -> Result<u64, sqlx::Error> {
+    let table = entity.table();
+    let purged = format!(
+        "SELECT id FROM {} WHERE deleted_at IS NOT NULL AND {}",
+        table, condition
+    );
+
+    for (dependent, column) in entity.dependents() {
+        let query = format!("DELETE FROM {} WHERE {} IN ({})", dependent, column, purged);
+        sqlx::query(&query).bind(value).execute(&mut *conn).await?;
+    }
+    let query = format!("DELETE FROM {} WHERE id IN ({})", table, purged);
+    let result = sqlx::query(&query).bind(value).execute(&mut *conn).await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Deletes a soft-deleted row for good
+///
+/// Rows that are not soft deleted are left alone. Returns whether the row was purged.
+pub async fn purge(conn: &mut PgConnection, entity: Entity, id: i32) -> Result<bool, sqlx::Error> {
+    Ok(purge_where(conn, entity, "id = $1", id).await? > 0)
+}
+
+/// Purges every row that was soft deleted more than `retention_days` days ago
+///
+/// Returns the number of rows purged, not counting the rows that belonged to them.
+pub async fn purge_expired(conn: &mut PgConnection, retention_days: u32) -> Result<u64, sqlx::Error> {
+    let mut purged = 0;
+    for entity in Entity::ALL {
+        purged += purge_where(
+            conn,
+            entity,
+            "deleted_at < now() - make_interval(days => $1)",
+            retention_days as i32,
+        )
+        .await?;
+    }
+
+    Ok(purged)
+}
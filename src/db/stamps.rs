@@ -0,0 +1,73 @@
+//! When and by whom content rows were created and last updated
+//!
+//! The `stamp_row` trigger keeps these columns up to date on every insert and
+//! update, taking the actor from the same transaction setting as the audit
+//! log, so writes only need to go through [`super::audit::begin`]. Models
+//! embed [`Stamps`] flattened, so runtime queries loading them select
+//! `created_at, updated_at, created_by, updated_by` along with their own
+//! columns. `query_as!` fills struct fields from columns one to one, so the
+//! checked queries select the four columns as a single record instead:
+//! `ROW(created_at, updated_at, created_by, updated_by) AS "stamps!: Stamps"`.
+
+use async_graphql::SimpleObject;
+use serde::{Deserialize, Serialize};
+use sqlx::error::BoxDynError;
+use sqlx::postgres::types::PgRecordDecoder;
+use sqlx::postgres::{PgTypeInfo, PgValueRef};
+use sqlx::{Decode, Postgres, Type};
+use std::time::SystemTime;
+use time::OffsetDateTime;
+use utoipa::ToSchema;
+
+/// Creation and last update of a row, never read from request bodies
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema, SimpleObject, sqlx::FromRow)]
+pub struct Stamps {
+    #[serde(
+        skip_deserializing,
+        skip_serializing_if = "Option::is_none",
+        with = "time::serde::rfc3339::option"
+    )]
+    #[schema(value_type = Option<String>, format = DateTime)]
+    pub created_at: Option<OffsetDateTime>,
+    #[serde(
+        skip_deserializing,
+        skip_serializing_if = "Option::is_none",
+        with = "time::serde::rfc3339::option"
+    )]
+    #[schema(value_type = Option<String>, format = DateTime)]
+    pub updated_at: Option<OffsetDateTime>,
+    /// Who created the row, `admin` or a client IP, if known
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub created_by: Option<String>,
+    /// Who last updated the row, if known
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub updated_by: Option<String>,
+}
+
+impl Stamps {
+    /// When the row last changed
+    pub fn last_modified(&self) -> Option<SystemTime> {
+        self.updated_at.map(SystemTime::from)
+    }
+}
+
+/// Anonymous record, as built by `ROW(...)`
+impl Type<Postgres> for Stamps {
+    fn type_info() -> PgTypeInfo {
+        PgTypeInfo::with_name("record")
+    }
+}
+
+/// Decodes `ROW(created_at, updated_at, created_by, updated_by)`
+impl<'r> Decode<'r, Postgres> for Stamps {
+    fn decode(value: PgValueRef<'r>) -> Result<Self, BoxDynError> {
+        let mut decoder = PgRecordDecoder::new(value)?;
+
+        Ok(Stamps {
+            created_at: decoder.try_decode()?,
+            updated_at: decoder.try_decode()?,
+            created_by: decoder.try_decode()?,
+            updated_by: decoder.try_decode()?,
+        })
+    }
+}
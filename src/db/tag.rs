@@ -1,18 +1,54 @@
 use crate::Db;
+use async_graphql::SimpleObject;
+use either::{Either, Left, Right};
 use rocket_db_pools::Connection;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use sqlx::{PgConnection, PgPool};
+use tracing::{debug, info};
 
-use super::tag_category_join::{TagCategory, TagCategoryJoin};
+use super::audit;
+use super::blog_item::BlogItem;
+use super::pagination::{escape_like, fetch_page, Identifiable, Page, PageParams};
+use super::project_item::ProjectItem;
+use super::shop_item::ShopItem;
+use super::soft_delete::{self, Entity};
+use super::stamps::Stamps;
+use super::tag_category::TagCategory;
+use super::tag_category_join::TagCategoryJoin;
+use crate::api::validation::{Validate, Validator};
+use crate::api::FieldError;
 
-#[derive(Serialize, Deserialize, Clone, sqlx::FromRow)]
+#[derive(Serialize, Deserialize, ToSchema, Clone, SimpleObject, sqlx::FromRow)]
 #[sqlx(type_name = "tag")]
 pub struct Tag {
     #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
     pub id: Option<i32>,
     pub text: String,
+    /// Names of the categories this tag is assigned to
+    #[serde(skip_deserializing)]
+    pub categories: Vec<String>,
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    #[graphql(flatten)]
+    pub stamps: Stamps,
 }
 
-#[derive(Serialize, Deserialize, Clone, sqlx::FromRow)]
+/// A tag together with the number of items it is attached to
+// NOTE: Not a database model
+#[derive(Serialize, Deserialize, ToSchema, Clone, sqlx::FromRow)]
+pub struct TagUsage {
+    pub id: i32,
+    pub text: String,
+    pub categories: Vec<String>,
+    pub project_count: i64,
+    pub blog_count: i64,
+    pub shop_item_count: i64,
+    /// Sum of the project, blog and shop item counts
+    pub usage_count: i64,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, sqlx::FromRow)]
 #[sqlx(type_name = "project_tech_tag")]
 pub struct ProjectToTechTag {
     #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
@@ -21,60 +57,440 @@ pub struct ProjectToTechTag {
     pub tag_id: i32,
 }
 
+#[derive(Serialize, Deserialize, ToSchema, Clone, sqlx::FromRow)]
+#[sqlx(type_name = "blog_tag")]
+pub struct BlogToTag {
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub id: Option<i32>,
+    pub blog_id: i32,
+    pub tag_id: i32,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, sqlx::FromRow)]
+#[sqlx(type_name = "shop_item_tag")]
+pub struct ShopItemToTag {
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub id: Option<i32>,
+    pub shop_item_id: i32,
+    pub tag_id: i32,
+}
+
+/// Everything tagged with a single tag, across all taggable entities
+// NOTE: Not a database model
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct TaggedItems {
+    pub tag: Tag,
+    pub projects: Vec<ProjectItem>,
+    pub blogs: Vec<BlogItem>,
+    pub shop_items: Vec<ShopItem>,
+}
+
 impl Tag {
-    pub async fn add_or_get(&self, mut db: Connection<Db>) -> Result<Tag, sqlx::Error> {
+    /// Inserts the tag, or returns the existing one if a tag with the same
+    /// text already exists
+    ///
+    /// Surrounding whitespace is trimmed and the match is case-insensitive, so
+    /// "Rust" and " rust" resolve to the same tag. A soft-deleted tag with the
+    /// same text is restored, since its text is still taken.
+    pub async fn add_or_get(&self, db: &mut PgConnection) -> Result<Tag, sqlx::Error> {
+        let mut tx = audit::begin(db).await?;
+        sqlx::query!(
+            "UPDATE tag SET deleted_at = NULL WHERE LOWER(text) = LOWER($1) AND deleted_at IS NOT NULL",
+            self.text.trim(),
+        )
+        .execute(&mut *tx)
+        .await?;
         let result = sqlx::query_as!(
             Tag,
             "
                 WITH newrow AS (
                     INSERT INTO tag (text) 
                     SELECT $1::VARCHAR
-                    WHERE NOT EXISTS (SELECT * FROM tag WHERE text = $1)
-                    RETURNING id, text
+                    WHERE NOT EXISTS (SELECT * FROM tag WHERE LOWER(text) = LOWER($1))
+                    RETURNING id, text, created_at, updated_at, created_by, updated_by
                 )
-                    SELECT id, COALESCE(text, '') AS \"text!\" FROM newrow
+                    SELECT id, COALESCE(text, '') AS \"text!\",
+                        ROW(created_at, updated_at, created_by, updated_by) AS \"stamps!: Stamps\",
+                        ARRAY[]::VARCHAR[] AS \"categories!\" FROM newrow
                     UNION
-                    SELECT id, text FROM tag WHERE text = $1
+                    SELECT id, text, ROW(created_at, updated_at, created_by, updated_by), ARRAY(
+                        SELECT category.name FROM category
+                            INNER JOIN tag_category_join ON category.id=tag_category_join.category_id
+                            WHERE tag_category_join.tag_id = tag.id
+                            ORDER BY category.name
+                    ) FROM tag WHERE LOWER(text) = LOWER($1)
             ",
-            &self.text,
+            self.text.trim(),
         )
-        .fetch_one(&mut **db)
+        .fetch_one(&mut *tx)
         .await;
 
         match result {
             Ok(result) => {
-                println!("Successfully added new  {}", &self.text);
+                tx.commit().await?;
+                info!(id = result.id, text = %result.text, "Created tag");
 
                 Ok(result)
             }
             Err(error) => {
-                println!("Error when creating new blog item with: [ {} ]", &self.text);
+                debug!(%error, text = %self.text, "Failed to create tag");
                 Err(error)
             }
         }
     }
 
+    /// Assigns a category to this tag, looking the tag up by its text
+    ///
+    /// Assigning a category the tag already has is a no-op that returns the
+    /// existing join row. Returns `Ok(None)` when the tag does not exist.
     pub async fn add_category(
         &self,
-        mut db: Connection<Db>,
+        db: &mut PgConnection,
         tag_category: &TagCategory,
-    ) -> Result<TagCategoryJoin, sqlx::Error> {
-        sqlx::query_as!(
+    ) -> Result<Option<TagCategoryJoin>, sqlx::Error> {
+        let mut tx = audit::begin(db).await?;
+        let assigned = sqlx::query_as!(
             TagCategoryJoin,
             "
-                    SELECT id, tag_id, category AS \"category: _\"  FROM tag_category_join WHERE tag_id = $1 AND category = $2
+                    INSERT INTO tag_category_join (tag_id, category_id)
+                    SELECT tag.id, $2 FROM tag WHERE LOWER(tag.text) = LOWER($1) AND tag.deleted_at IS NULL
+                    ON CONFLICT (tag_id, category_id) DO UPDATE SET tag_id = EXCLUDED.tag_id
+                    RETURNING id, tag_id, category_id
             ",
-            &self.id.unwrap_or(-1), tag_category as &TagCategory
+            self.text.trim(), &tag_category.id.unwrap_or(-1)
         )
-        .fetch_one(&mut **db)
-        .await
+        .fetch_optional(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(assigned)
+    }
+
+    /// Removes a category from a tag
+    ///
+    /// Returns the number of assignments removed.
+    pub async fn remove_category(
+        mut db: Connection<Db>,
+        tag_id: i32,
+        category_id: i32,
+    ) -> Result<u64, sqlx::Error> {
+        let mut tx = audit::begin(&mut db).await?;
+        let result = sqlx::query!(
+            "DELETE FROM tag_category_join WHERE tag_id = $1 AND category_id = $2",
+            tag_id,
+            category_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(result.rows_affected())
+    }
+
+    pub async fn rename(mut db: Connection<Db>, id: i32, text: &str) -> Result<Tag, sqlx::Error> {
+        let mut tx = audit::begin(&mut db).await?;
+        let renamed = sqlx::query_as!(
+            Tag,
+            "
+                UPDATE tag SET text = $2 WHERE id = $1 AND deleted_at IS NULL
+                    RETURNING id, text, ROW(created_at, updated_at, created_by, updated_by) AS \"stamps!: Stamps\", ARRAY(
+                        SELECT category.name FROM category
+                            INNER JOIN tag_category_join ON category.id=tag_category_join.category_id
+                            WHERE tag_category_join.tag_id = tag.id
+                            ORDER BY category.name
+                    ) AS \"categories!\"
+            ",
+            id,
+            text.trim(),
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(renamed)
+    }
+
+    /// Merges the source tag into the target tag in a single transaction
+    ///
+    /// Every project, blog, shop item and category reference is moved from the source to the
+    /// target, skipping ones the target already has, and the source tag is
+    /// then deleted. Returns `sqlx::Error::RowNotFound` if either tag is missing.
+    pub async fn merge(
+        mut db: Connection<Db>,
+        source_id: i32,
+        target_id: i32,
+    ) -> Result<Tag, sqlx::Error> {
+        let mut tx = audit::begin(&mut db).await?;
+
+        let found = sqlx::query_scalar!(
+            "SELECT COUNT(*) AS \"count!\" FROM tag WHERE (id = $1 OR id = $2) AND deleted_at IS NULL",
+            source_id,
+            target_id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        if found != 2 {
+            return Err(sqlx::Error::RowNotFound);
+        }
+
+        sqlx::query!(
+            "
+                UPDATE project_tech_tag SET tag_id = $2
+                    WHERE tag_id = $1 AND project_id NOT IN (
+                        SELECT project_id FROM project_tech_tag WHERE tag_id = $2
+                    )
+            ",
+            source_id,
+            target_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!("DELETE FROM project_tech_tag WHERE tag_id = $1", source_id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query!(
+            "
+                UPDATE blog_tag SET tag_id = $2
+                    WHERE tag_id = $1 AND blog_id NOT IN (
+                        SELECT blog_id FROM blog_tag WHERE tag_id = $2
+                    )
+            ",
+            source_id,
+            target_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!("DELETE FROM blog_tag WHERE tag_id = $1", source_id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query!(
+            "
+                UPDATE shop_item_tag SET tag_id = $2
+                    WHERE tag_id = $1 AND shop_item_id NOT IN (
+                        SELECT shop_item_id FROM shop_item_tag WHERE tag_id = $2
+                    )
+            ",
+            source_id,
+            target_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!("DELETE FROM shop_item_tag WHERE tag_id = $1", source_id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query!(
+            "
+                UPDATE tag_category_join SET tag_id = $2
+                    WHERE tag_id = $1 AND category_id NOT IN (
+                        SELECT category_id FROM tag_category_join WHERE tag_id = $2
+                    )
+            ",
+            source_id,
+            target_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!("DELETE FROM tag_category_join WHERE tag_id = $1", source_id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query!("DELETE FROM tag WHERE id = $1", source_id)
+            .execute(&mut *tx)
+            .await?;
+
+        let merged = sqlx::query_as!(
+            Tag,
+            "
+                SELECT id, text, ROW(created_at, updated_at, created_by, updated_by) AS \"stamps!: Stamps\", ARRAY(
+                    SELECT category.name FROM category
+                        INNER JOIN tag_category_join ON category.id=tag_category_join.category_id
+                        WHERE tag_category_join.tag_id = tag.id
+                        ORDER BY category.name
+                ) AS \"categories!\" FROM tag WHERE id = $1
+            ",
+            target_id,
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        info!(source_id, target_id, text = %merged.text, "Merged tags");
+
+        Ok(merged)
     }
 
-    pub async fn get_all(mut db: Connection<Db>) -> Result<Vec<Tag>, sqlx::Error> {
-        sqlx::query_as("SELECT id, text FROM tag")
-            .fetch_all(&mut **db)
+    /// Soft deletes a tag
+    ///
+    /// Unless `force` is set, a tag that is still used by any project, blog or
+    /// shop item is left
+    /// untouched and `Right` carries the number of places it is used. Its
+    /// references and category assignments are kept, so restoring the tag
+    /// brings them back. On success, returns the number of tags deleted.
+    pub async fn delete(
+        mut db: Connection<Db>,
+        id: i32,
+        force: bool,
+    ) -> Result<u64, Either<sqlx::Error, i64>> {
+        let mut tx = audit::begin(&mut db).await.map_err(Left)?;
+
+        let usage = sqlx::query_scalar!(
+            "
+                SELECT
+                    (SELECT COUNT(*) FROM project_tech_tag
+                        INNER JOIN project_item ON project_item.id=project_tech_tag.project_id
+                        WHERE tag_id = $1 AND project_item.deleted_at IS NULL)
+                    + (SELECT COUNT(*) FROM blog_tag
+                        INNER JOIN blog_item ON blog_item.id=blog_tag.blog_id
+                        WHERE tag_id = $1 AND blog_item.deleted_at IS NULL)
+                    + (SELECT COUNT(*) FROM shop_item_tag
+                        INNER JOIN shop_item ON shop_item.id=shop_item_tag.shop_item_id
+                        WHERE tag_id = $1 AND shop_item.deleted_at IS NULL)
+                    AS \"count!\"
+            ",
+            id
+        )
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(Left)?;
+        if usage > 0 && !force {
+            return Err(Right(usage));
+        }
+
+        let deleted = soft_delete::delete(&mut tx, Entity::Tag, id)
             .await
-        // TODO: Add custom completion prints
+            .map_err(Left)?;
+
+        tx.commit().await.map_err(Left)?;
+
+        Ok(u64::from(deleted))
+    }
+
+    /// Fields that tag lists may be sorted by, the first being the default
+    pub const SORT_FIELDS: &'static [(&'static str, &'static str)] = &[
+        ("id", "id"),
+        ("text", "text"),
+        ("created_at", "created_at"),
+        ("updated_at", "updated_at"),
+    ];
+
+    /// Lists tags one page at a time, optionally filtered by a text search
+    pub async fn get_all(
+        db: &mut PgConnection,
+        params: &PageParams,
+        search: Option<&str>,
+    ) -> Result<Page<Tag>, Either<sqlx::Error, ()>> {
+        fetch_page(
+            db,
+            "
+                SELECT id, text, created_at, updated_at, created_by, updated_by, ARRAY(
+                    SELECT category.name FROM category
+                        INNER JOIN tag_category_join ON category.id=tag_category_join.category_id
+                        WHERE tag_category_join.tag_id = tag.id
+                        ORDER BY category.name
+                ) AS categories FROM tag
+            ",
+            "tag",
+            Self::SORT_FIELDS,
+            params,
+            |query| {
+                query.push(" AND tag.deleted_at IS NULL");
+                if let Some(search) = search {
+                    query.push(" AND tag.text ILIKE '%' || ");
+                    query.push_bind(escape_like(search));
+                    query.push(" || '%'");
+                }
+            },
+        )
+        .await
+    }
+
+    /// Lists every tag with the number of projects, blogs and shop items using
+    /// it, most used first
+    ///
+    /// Soft-deleted tags are left out, and so are soft-deleted items in the counts.
+    ///
+    /// # Arguments
+    /// * `category` - Only include tags assigned to this category, if set
+    /// * `min_count` - Only include tags used at least this many times
+    pub async fn get_usage_counts(
+        mut db: Connection<Db>,
+        category: Option<&str>,
+        min_count: i64,
+    ) -> Result<Vec<TagUsage>, sqlx::Error> {
+        sqlx::query_as(
+            "
+                SELECT
+                    id, text, categories, project_count, blog_count, shop_item_count,
+                    project_count + blog_count + shop_item_count AS usage_count
+                FROM (
+                    SELECT tag.id, tag.text, ARRAY(
+                        SELECT category.name FROM category
+                            INNER JOIN tag_category_join ON category.id=tag_category_join.category_id
+                            WHERE tag_category_join.tag_id = tag.id
+                            ORDER BY category.name
+                    ) AS categories, (
+                        SELECT COUNT(*) FROM project_tech_tag
+                            INNER JOIN project_item ON project_item.id=project_tech_tag.project_id
+                            WHERE project_tech_tag.tag_id = tag.id AND project_item.deleted_at IS NULL
+                    ) AS project_count, (
+                        SELECT COUNT(*) FROM blog_tag
+                            INNER JOIN blog_item ON blog_item.id=blog_tag.blog_id
+                            WHERE blog_tag.tag_id = tag.id AND blog_item.deleted_at IS NULL
+                    ) AS blog_count, (
+                        SELECT COUNT(*) FROM shop_item_tag
+                            INNER JOIN shop_item ON shop_item.id=shop_item_tag.shop_item_id
+                            WHERE shop_item_tag.tag_id = tag.id AND shop_item.deleted_at IS NULL
+                    ) AS shop_item_count FROM tag
+                        WHERE tag.deleted_at IS NULL AND ($1::VARCHAR IS NULL OR tag.id IN (
+                            SELECT tag_category_join.tag_id FROM tag_category_join
+                                INNER JOIN category ON category.id=tag_category_join.category_id
+                                WHERE category.name = LOWER($1)
+                        ))
+                ) AS cloud
+                    WHERE project_count + blog_count + shop_item_count >= $2
+                    ORDER BY usage_count DESC, text
+            ",
+        )
+        .bind(category.map(str::trim))
+        .bind(min_count)
+        .fetch_all(&mut **db)
+        .await
+    }
+
+    /// Finds tags whose text starts with the given prefix, ignoring case
+    ///
+    /// Backed by the `tag_text_lower_prefix_idx` index. The whole pattern is
+    /// built here and bound as one value, since the planner can only use the
+    /// index for a `LIKE` whose pattern is a constant with a fixed prefix.
+    pub async fn autocomplete(
+        mut db: Connection<Db>,
+        prefix: &str,
+        limit: i64,
+    ) -> Result<Vec<Tag>, sqlx::Error> {
+        let pattern = format!("{}%", escape_like(prefix).to_lowercase());
+
+        sqlx::query_as!(
+            Tag,
+            "
+                SELECT id, text, ROW(created_at, updated_at, created_by, updated_by) AS \"stamps!: Stamps\", ARRAY(
+                    SELECT category.name FROM category
+                        INNER JOIN tag_category_join ON category.id=tag_category_join.category_id
+                        WHERE tag_category_join.tag_id = tag.id
+                        ORDER BY category.name
+                ) AS \"categories!\" FROM tag
+                    WHERE LOWER(text) LIKE $1 AND deleted_at IS NULL
+                    ORDER BY text
+                    LIMIT $2
+            ",
+            pattern,
+            limit,
+        )
+        .fetch_all(&mut **db)
+        .await
     }
 
     pub async fn get_tags_by_project(
@@ -84,31 +500,212 @@ impl Tag {
         sqlx::query_as!(
             Tag,
             "
-                SELECT tag.id, tag.text FROM tag 
+                SELECT tag.id, tag.text, ROW(tag.created_at, tag.updated_at, tag.created_by, tag.updated_by) AS \"stamps!: Stamps\", ARRAY(
+                    SELECT category.name FROM category
+                        INNER JOIN tag_category_join ON category.id=tag_category_join.category_id
+                        WHERE tag_category_join.tag_id = tag.id
+                        ORDER BY category.name
+                ) AS \"categories!\" FROM tag 
                     INNER JOIN project_tech_tag ON tag.id=project_tech_tag.tag_id
-                    WHERE project_tech_tag.project_id = $1
+                    WHERE project_tech_tag.project_id = $1 AND tag.deleted_at IS NULL
             ",
-            project_item_id
+            project_item_id,
         )
         .fetch_all(&mut **db)
         .await
         // TODO: Add custom completion prints
     }
 
-    // NOTE: The type casting here is from
-    // https://github.com/launchbadge/sqlx/issues/1004#issuecomment-764964043
+    pub async fn get_tags_by_blog(
+        mut db: Connection<Db>,
+        blog_id: i32,
+    ) -> Result<Vec<Tag>, sqlx::Error> {
+        sqlx::query_as!(
+            Tag,
+            "
+                SELECT tag.id, tag.text, ROW(tag.created_at, tag.updated_at, tag.created_by, tag.updated_by) AS \"stamps!: Stamps\", ARRAY(
+                    SELECT category.name FROM category
+                        INNER JOIN tag_category_join ON category.id=tag_category_join.category_id
+                        WHERE tag_category_join.tag_id = tag.id
+                        ORDER BY category.name
+                ) AS \"categories!\" FROM tag 
+                    INNER JOIN blog_tag ON tag.id=blog_tag.tag_id
+                    WHERE blog_tag.blog_id = $1 AND tag.deleted_at IS NULL
+            ",
+            blog_id,
+        )
+        .fetch_all(&mut **db)
+        .await
+    }
+
+    pub async fn get_tags_by_shop_item(
+        mut db: Connection<Db>,
+        shop_item_id: i32,
+    ) -> Result<Vec<Tag>, sqlx::Error> {
+        sqlx::query_as!(
+            Tag,
+            "
+                SELECT tag.id, tag.text, ROW(tag.created_at, tag.updated_at, tag.created_by, tag.updated_by) AS \"stamps!: Stamps\", ARRAY(
+                    SELECT category.name FROM category
+                        INNER JOIN tag_category_join ON category.id=tag_category_join.category_id
+                        WHERE tag_category_join.tag_id = tag.id
+                        ORDER BY category.name
+                ) AS \"categories!\" FROM tag 
+                    INNER JOIN shop_item_tag ON tag.id=shop_item_tag.tag_id
+                    WHERE shop_item_tag.shop_item_id = $1 AND tag.deleted_at IS NULL
+            ",
+            shop_item_id,
+        )
+        .fetch_all(&mut **db)
+        .await
+    }
+
+    /// Loads the tags of several projects at once, each paired with the project ID
+    pub async fn get_tags_by_projects(
+        pool: &PgPool,
+        project_ids: &[i32],
+    ) -> Result<Vec<(i32, Tag)>, sqlx::Error> {
+        let rows = sqlx::query!(
+            "
+                SELECT project_tech_tag.project_id, tag.id, tag.text,
+                    tag.created_at AS \"created_at!\", tag.updated_at AS \"updated_at!\",
+                    tag.created_by AS \"created_by?\", tag.updated_by AS \"updated_by?\", ARRAY(
+                    SELECT category.name FROM category
+                        INNER JOIN tag_category_join ON category.id=tag_category_join.category_id
+                        WHERE tag_category_join.tag_id = tag.id
+                        ORDER BY category.name
+                ) AS \"categories!\" FROM tag
+                    INNER JOIN project_tech_tag ON tag.id=project_tech_tag.tag_id
+                    WHERE project_tech_tag.project_id = ANY($1) AND tag.deleted_at IS NULL
+                    ORDER BY tag.text
+            ",
+            project_ids
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let tag = Tag {
+                    id: Some(row.id),
+                    text: row.text,
+                    categories: row.categories,
+                    stamps: Stamps {
+                        created_at: Some(row.created_at),
+                        updated_at: Some(row.updated_at),
+                        created_by: row.created_by,
+                        updated_by: row.updated_by,
+                    },
+                };
+                (row.project_id, tag)
+            })
+            .collect())
+    }
+
+    /// Loads the tags of several blogs at once, each paired with the blog ID
+    pub async fn get_tags_by_blogs(
+        pool: &PgPool,
+        blog_ids: &[i32],
+    ) -> Result<Vec<(i32, Tag)>, sqlx::Error> {
+        let rows = sqlx::query!(
+            "
+                SELECT blog_tag.blog_id, tag.id, tag.text,
+                    tag.created_at AS \"created_at!\", tag.updated_at AS \"updated_at!\",
+                    tag.created_by AS \"created_by?\", tag.updated_by AS \"updated_by?\", ARRAY(
+                    SELECT category.name FROM category
+                        INNER JOIN tag_category_join ON category.id=tag_category_join.category_id
+                        WHERE tag_category_join.tag_id = tag.id
+                        ORDER BY category.name
+                ) AS \"categories!\" FROM tag
+                    INNER JOIN blog_tag ON tag.id=blog_tag.tag_id
+                    WHERE blog_tag.blog_id = ANY($1) AND tag.deleted_at IS NULL
+                    ORDER BY tag.text
+            ",
+            blog_ids
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let tag = Tag {
+                    id: Some(row.id),
+                    text: row.text,
+                    categories: row.categories,
+                    stamps: Stamps {
+                        created_at: Some(row.created_at),
+                        updated_at: Some(row.updated_at),
+                        created_by: row.created_by,
+                        updated_by: row.updated_by,
+                    },
+                };
+                (row.blog_id, tag)
+            })
+            .collect())
+    }
+
+    /// Loads the tags of several shop items at once, each paired with the shop item ID
+    pub async fn get_tags_by_shop_items(
+        pool: &PgPool,
+        shop_item_ids: &[i32],
+    ) -> Result<Vec<(i32, Tag)>, sqlx::Error> {
+        let rows = sqlx::query!(
+            "
+                SELECT shop_item_tag.shop_item_id, tag.id, tag.text,
+                    tag.created_at AS \"created_at!\", tag.updated_at AS \"updated_at!\",
+                    tag.created_by AS \"created_by?\", tag.updated_by AS \"updated_by?\", ARRAY(
+                    SELECT category.name FROM category
+                        INNER JOIN tag_category_join ON category.id=tag_category_join.category_id
+                        WHERE tag_category_join.tag_id = tag.id
+                        ORDER BY category.name
+                ) AS \"categories!\" FROM tag
+                    INNER JOIN shop_item_tag ON tag.id=shop_item_tag.tag_id
+                    WHERE shop_item_tag.shop_item_id = ANY($1) AND tag.deleted_at IS NULL
+                    ORDER BY tag.text
+            ",
+            shop_item_ids
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let tag = Tag {
+                    id: Some(row.id),
+                    text: row.text,
+                    categories: row.categories,
+                    stamps: Stamps {
+                        created_at: Some(row.created_at),
+                        updated_at: Some(row.updated_at),
+                        created_by: row.created_by,
+                        updated_by: row.updated_by,
+                    },
+                };
+                (row.shop_item_id, tag)
+            })
+            .collect())
+    }
+
     pub async fn get_tags_by_category(
         mut db: Connection<Db>,
-        category: TagCategory,
+        category_id: i32,
     ) -> Result<Vec<Tag>, sqlx::Error> {
         sqlx::query_as!(
             Tag,
             "
-                SELECT tag.id, tag.text FROM tag 
+                SELECT tag.id, tag.text, ROW(tag.created_at, tag.updated_at, tag.created_by, tag.updated_by) AS \"stamps!: Stamps\", ARRAY(
+                    SELECT category.name FROM category
+                        INNER JOIN tag_category_join AS tcj ON category.id=tcj.category_id
+                        WHERE tcj.tag_id = tag.id
+                        ORDER BY category.name
+                ) AS \"categories!\" FROM tag 
                     INNER JOIN tag_category_join ON tag.id=tag_category_join.tag_id
-                    WHERE tag_category_join.category = $1
+                    WHERE tag_category_join.category_id = $1 AND tag.deleted_at IS NULL
             ",
-            category as _
+            category_id,
         )
         .fetch_all(&mut **db)
         .await
@@ -116,30 +713,269 @@ impl Tag {
     }
 }
 
+impl Identifiable for Tag {
+    fn row_id(&self) -> Option<i32> {
+        self.id
+    }
+}
+
+impl Validate for Tag {
+    fn validate(&self) -> Vec<FieldError> {
+        Validator::new()
+            .not_blank("text", &self.text)
+            .max_length("text", self.text.trim(), 64)
+            .finish()
+    }
+}
+
+impl Validate for ProjectToTechTag {
+    fn validate(&self) -> Vec<FieldError> {
+        Validator::new()
+            .id("project_id", self.project_id)
+            .id("tag_id", self.tag_id)
+            .finish()
+    }
+}
+
+impl Validate for BlogToTag {
+    fn validate(&self) -> Vec<FieldError> {
+        Validator::new()
+            .id("blog_id", self.blog_id)
+            .id("tag_id", self.tag_id)
+            .finish()
+    }
+}
+
+impl Validate for ShopItemToTag {
+    fn validate(&self) -> Vec<FieldError> {
+        Validator::new()
+            .id("shop_item_id", self.shop_item_id)
+            .id("tag_id", self.tag_id)
+            .finish()
+    }
+}
+
 impl ProjectToTechTag {
     pub async fn add(&self, mut db: Connection<Db>) -> Result<ProjectToTechTag, sqlx::Error> {
+        let mut tx = audit::begin(&mut db).await?;
+        let result = self.add_tx(&mut tx).await?;
+        tx.commit().await?;
+
+        Ok(result)
+    }
+
+    /// Links the project and tag on the given connection, which may be inside a transaction
+    pub async fn add_tx(&self, db: &mut PgConnection) -> Result<ProjectToTechTag, sqlx::Error> {
         let result = sqlx::query_as!(
             ProjectToTechTag,
             "INSERT INTO project_tech_tag (project_id, tag_id) VALUES ($1, $2) RETURNING id, project_id, tag_id",
 &self.project_id, &self.tag_id
             ,
         )
-        .fetch_one(&mut **db)
+        .fetch_one(&mut *db)
+        .await;
+
+        match result {
+            Ok(result) => {
+                debug!(project_id = result.project_id, tag_id = result.tag_id, "Tagged project");
+
+                Ok(result)
+            }
+            Err(error) => {
+                debug!(%error, project_id = self.project_id, tag_id = self.tag_id, "Failed to tag project");
+                Err(error)
+            }
+        }
+    }
+}
+
+impl BlogToTag {
+    /// Attaches a tag to a blog, returning the existing link if it is already attached
+    pub async fn add(&self, mut db: Connection<Db>) -> Result<BlogToTag, sqlx::Error> {
+        let mut tx = audit::begin(&mut db).await?;
+        let result = self.add_tx(&mut tx).await?;
+        tx.commit().await?;
+
+        Ok(result)
+    }
+
+    /// Attaches the tag on the given connection, which may be inside a transaction
+    pub async fn add_tx(&self, db: &mut PgConnection) -> Result<BlogToTag, sqlx::Error> {
+        let result = sqlx::query_as!(
+            BlogToTag,
+            "
+                INSERT INTO blog_tag (blog_id, tag_id) VALUES ($1, $2)
+                    ON CONFLICT (blog_id, tag_id) DO UPDATE SET tag_id = EXCLUDED.tag_id
+                    RETURNING id, blog_id, tag_id
+            ",
+            &self.blog_id,
+            &self.tag_id,
+        )
+        .fetch_one(&mut *db)
+        .await;
+
+        match result {
+            Ok(result) => {
+                debug!(blog_id = result.blog_id, tag_id = result.tag_id, "Tagged blog");
+
+                Ok(result)
+            }
+            Err(error) => {
+                debug!(%error, blog_id = self.blog_id, tag_id = self.tag_id, "Failed to tag blog");
+                Err(error)
+            }
+        }
+    }
+
+    /// Detaches a tag from a blog, returning the number of links removed
+    pub async fn remove(mut db: Connection<Db>, blog_id: i32, tag_id: i32) -> Result<u64, sqlx::Error> {
+        let mut tx = audit::begin(&mut db).await?;
+        let result = sqlx::query!(
+            "DELETE FROM blog_tag WHERE blog_id = $1 AND tag_id = $2",
+            blog_id,
+            tag_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+impl ShopItemToTag {
+    /// Attaches a tag to a shop item, returning the existing link if it is already attached
+    pub async fn add(&self, mut db: Connection<Db>) -> Result<ShopItemToTag, sqlx::Error> {
+        let mut tx = audit::begin(&mut db).await?;
+        let result = self.add_tx(&mut tx).await?;
+        tx.commit().await?;
+
+        Ok(result)
+    }
+
+    /// Attaches the tag on the given connection, which may be inside a transaction
+    pub async fn add_tx(&self, db: &mut PgConnection) -> Result<ShopItemToTag, sqlx::Error> {
+        let result = sqlx::query_as!(
+            ShopItemToTag,
+            "
+                INSERT INTO shop_item_tag (shop_item_id, tag_id) VALUES ($1, $2)
+                    ON CONFLICT (shop_item_id, tag_id) DO UPDATE SET tag_id = EXCLUDED.tag_id
+                    RETURNING id, shop_item_id, tag_id
+            ",
+            &self.shop_item_id,
+            &self.tag_id,
+        )
+        .fetch_one(&mut *db)
         .await;
 
         match result {
             Ok(result) => {
-                println!("Successfully added new  {}", result.id.unwrap_or(-1));
+                debug!(shop_item_id = result.shop_item_id, tag_id = result.tag_id, "Tagged shop item");
 
                 Ok(result)
             }
             Err(error) => {
-                println!(
-                    "Error when joining project {} and tag {}",
-                    &self.project_id, &self.tag_id
+                debug!(
+                    %error,
+                    shop_item_id = self.shop_item_id,
+                    tag_id = self.tag_id,
+                    "Failed to tag shop item"
                 );
                 Err(error)
             }
         }
     }
+
+    /// Detaches a tag from a shop item, returning the number of links removed
+    pub async fn remove(
+        mut db: Connection<Db>,
+        shop_item_id: i32,
+        tag_id: i32,
+    ) -> Result<u64, sqlx::Error> {
+        let mut tx = audit::begin(&mut db).await?;
+        let result = sqlx::query!(
+            "DELETE FROM shop_item_tag WHERE shop_item_id = $1 AND tag_id = $2",
+            shop_item_id,
+            tag_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+impl TaggedItems {
+    /// Collects every project, blog and shop item tagged with the given tag text
+    ///
+    /// The tag is matched case-insensitively. Returns `Ok(None)` when no such tag exists.
+    pub async fn get_by_tag(
+        mut db: Connection<Db>,
+        text: &str,
+    ) -> Result<Option<TaggedItems>, sqlx::Error> {
+        let tag = sqlx::query_as!(
+            Tag,
+            "
+                SELECT id, text, ROW(created_at, updated_at, created_by, updated_by) AS \"stamps!: Stamps\", ARRAY(
+                    SELECT category.name FROM category
+                        INNER JOIN tag_category_join ON category.id=tag_category_join.category_id
+                        WHERE tag_category_join.tag_id = tag.id
+                        ORDER BY category.name
+                ) AS \"categories!\" FROM tag WHERE LOWER(text) = LOWER($1) AND deleted_at IS NULL
+            ",
+            text.trim(),
+        )
+        .fetch_optional(&mut **db)
+        .await?;
+
+        let tag = match tag {
+            Some(tag) => tag,
+            None => return Ok(None),
+        };
+        let tag_id = tag.id.unwrap_or(-1);
+
+        let projects = sqlx::query_as(
+            "
+                SELECT project_item.id, project_item.title, project_item.thumbnail_img_link, project_item.version,
+                    project_item.created_at, project_item.updated_at, project_item.created_by, project_item.updated_by FROM project_item
+                    INNER JOIN project_tech_tag ON project_item.id=project_tech_tag.project_id
+                    WHERE project_tech_tag.tag_id = $1 AND project_item.deleted_at IS NULL
+            ",
+        )
+        .bind(tag_id)
+        .fetch_all(&mut **db)
+        .await?;
+
+        let blogs = sqlx::query_as(
+            "
+                SELECT blog_item.id, blog_item.blog_title, blog_item.header_img, blog_item.version,
+                    blog_item.created_at, blog_item.updated_at, blog_item.created_by, blog_item.updated_by FROM blog_item
+                    INNER JOIN blog_tag ON blog_item.id=blog_tag.blog_id
+                    WHERE blog_tag.tag_id = $1 AND blog_item.deleted_at IS NULL
+            ",
+        )
+        .bind(tag_id)
+        .fetch_all(&mut **db)
+        .await?;
+
+        let shop_items = sqlx::query_as(
+            "
+                SELECT shop_item.id, shop_item.iname, shop_item.img_link, shop_item.price, shop_item.version,
+                    shop_item.created_at, shop_item.updated_at, shop_item.created_by, shop_item.updated_by FROM shop_item
+                    INNER JOIN shop_item_tag ON shop_item.id=shop_item_tag.shop_item_id
+                    WHERE shop_item_tag.tag_id = $1 AND shop_item.deleted_at IS NULL
+            ",
+        )
+        .bind(tag_id)
+        .fetch_all(&mut **db)
+        .await?;
+
+        Ok(Some(TaggedItems {
+            tag,
+            projects,
+            blogs,
+            shop_items,
+        }))
+    }
 }
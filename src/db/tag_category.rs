@@ -0,0 +1,126 @@
+use rocket_db_pools::Connection;
+use serde::{Deserialize, Serialize};
+use sqlx::PgConnection;
+use utoipa::ToSchema;
+use tracing::{debug, info};
+
+use super::audit;
+use super::stamps::Stamps;
+use crate::api::validation::{Validate, Validator};
+use crate::api::FieldError;
+use crate::Db;
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, sqlx::FromRow)]
+#[sqlx(type_name = "category")]
+pub struct TagCategory {
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub id: Option<i32>,
+    pub name: String,
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub stamps: Stamps,
+}
+
+impl TagCategory {
+    pub async fn add(&self, db: &mut PgConnection) -> Result<TagCategory, sqlx::Error> {
+        let mut tx = audit::begin(db).await?;
+        let result = sqlx::query_as!(
+            TagCategory,
+            "
+                INSERT INTO category (name) VALUES ($1)
+                    RETURNING id, name, ROW(created_at, updated_at, created_by, updated_by) AS \"stamps!: Stamps\"
+            ",
+            self.name.trim().to_lowercase(),
+        )
+        .fetch_one(&mut *tx)
+        .await;
+
+        match result {
+            Ok(result) => {
+                tx.commit().await?;
+                info!(id = result.id, name = %result.name, "Created category");
+
+                Ok(result)
+            }
+            Err(error) => {
+                debug!(%error, name = %self.name, "Failed to create category");
+                Err(error)
+            }
+        }
+    }
+
+    pub async fn get_all(db: &mut PgConnection) -> Result<Vec<TagCategory>, sqlx::Error> {
+        sqlx::query_as!(
+            TagCategory,
+            "SELECT id, name, ROW(created_at, updated_at, created_by, updated_by) AS \"stamps!: Stamps\" FROM category ORDER BY name",
+        )
+        .fetch_all(db)
+        .await
+    }
+
+    /// Looks up a category by its name, ignoring case
+    ///
+    /// Returns `Ok(None)` when no category with that name exists.
+    pub async fn get_by_name(
+        db: &mut PgConnection,
+        name: &str,
+    ) -> Result<Option<TagCategory>, sqlx::Error> {
+        sqlx::query_as!(
+            TagCategory,
+            "SELECT id, name, ROW(created_at, updated_at, created_by, updated_by) AS \"stamps!: Stamps\" FROM category WHERE name = $1",
+            name.trim().to_lowercase(),
+        )
+        .fetch_optional(db)
+        .await
+    }
+
+    pub async fn rename(
+        mut db: Connection<Db>,
+        id: i32,
+        name: &str,
+    ) -> Result<TagCategory, sqlx::Error> {
+        let mut tx = audit::begin(&mut db).await?;
+        let renamed = sqlx::query_as!(
+            TagCategory,
+            "
+                UPDATE category SET name = $2 WHERE id = $1
+                    RETURNING id, name, ROW(created_at, updated_at, created_by, updated_by) AS \"stamps!: Stamps\"
+            ",
+            id,
+            name.trim().to_lowercase(),
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(renamed)
+    }
+
+    /// Deletes a category along with every tag assignment that points to it
+    ///
+    /// Returns the number of categories removed, so callers can tell a
+    /// missing category apart from a successful delete.
+    pub async fn delete(mut db: Connection<Db>, id: i32) -> Result<u64, sqlx::Error> {
+        let mut tx = audit::begin(&mut db).await?;
+
+        sqlx::query!("DELETE FROM tag_category_join WHERE category_id = $1", id)
+            .execute(&mut *tx)
+            .await?;
+        let result = sqlx::query!("DELETE FROM category WHERE id = $1", id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+impl Validate for TagCategory {
+    fn validate(&self) -> Vec<FieldError> {
+        Validator::new()
+            .not_blank("name", &self.name)
+            .max_length("name", self.name.trim(), 32)
+            .finish()
+    }
+}
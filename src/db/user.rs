@@ -1,10 +1,20 @@
+use either::Either;
 use futures::stream::TryStreamExt;
 use rocket_db_pools::Connection;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use sqlx::PgConnection;
+use tracing::{debug, info, warn};
 
+use super::audit;
+use super::pagination::{escape_like, fetch_page, Identifiable, Page, PageParams};
+use super::soft_delete::{self, Entity};
+use crate::api::validation::{Validate, Validator};
+use crate::api::FieldError;
+use crate::metrics::METRICS;
 use crate::Db;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema, sqlx::FromRow)]
 pub struct User {
     #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
     pub id: Option<i32>,
@@ -13,23 +23,76 @@ pub struct User {
     pub email: String,
 }
 
+/// Username and password sent to log in
+#[derive(Deserialize, ToSchema)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+impl Validate for Credentials {
+    fn validate(&self) -> Vec<FieldError> {
+        Validator::new()
+            .not_blank("username", &self.username)
+            .max_length("username", &self.username, 32)
+            .not_blank("password", &self.password)
+            .max_length("password", &self.password, 128)
+            .finish()
+    }
+}
+
+/// How failed logins lock an account
+///
+/// Once `threshold` logins in a row have failed, every further failure locks
+/// the account for twice as long as the previous one, starting at
+/// `base_seconds` and capped at `max_seconds`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LockoutPolicy {
+    pub threshold: i32,
+    pub base_seconds: f64,
+    pub max_seconds: f64,
+}
+
+impl Default for LockoutPolicy {
+    fn default() -> Self {
+        Self {
+            threshold: 5,
+            base_seconds: 30.0,
+            max_seconds: 3600.0,
+        }
+    }
+}
+
+/// Result of a login attempt
+pub enum LoginOutcome {
+    LoggedIn(User),
+    /// The username is unknown or the password is wrong
+    InvalidCredentials,
+    /// The account is locked for another `retry_after` seconds
+    Locked { retry_after: u64 },
+}
+
 // TODO: Update/copy implementation from shop_items, which has simpler better handling.
 impl User {
-    pub async fn add(&self, mut db: Connection<Db>) -> Result<User, sqlx::Error> {
+    pub async fn add(&self, db: &mut PgConnection) -> Result<User, sqlx::Error> {
+        let mut tx = audit::begin(db).await?;
         let result = sqlx::query!(
             "INSERT INTO app_user (username, upassword, email) VALUES ($1, $2, $3 ) RETURNING id",
             &self.username,
             &self.upassword,
             &self.email
         )
-        .fetch(&mut **db)
+        .fetch(&mut *tx)
         .try_collect::<Vec<_>>()
         .await;
 
         match result {
             Ok(result) => {
-                println!("Successfully added new user {}", &self.username);
+                tx.commit().await?;
                 let id_returned = result.first().expect("returning result").id;
+                info!(id = id_returned, username = %self.username, "Registered user");
+                METRICS.users_registered.inc();
                 Ok(User {
                     id: Some(id_returned),
                     username: self.username.clone(),
@@ -38,21 +101,182 @@ impl User {
                 })
             }
             Err(error) => {
-                println!("Error when creating new user with: [ {} ]", &self.username);
+                debug!(%error, username = %self.username, "Failed to register user");
                 Err(error)
             }
         }
     }
 
-    pub async fn get_all_users(mut db: Connection<Db>) -> Result<Vec<User>, sqlx::Error> {
-        let results = sqlx::query_as!(User, "SELECT id, username, upassword, email FROM app_user")
-            .fetch_all(&mut **db)
-            .await;
+    /// Checks a login attempt, recording failures to lock the account
+    ///
+    /// A locked account is refused without looking at the password, so
+    /// guessing cannot go on while the lock lasts. A successful login resets
+    /// the failure count.
+    ///
+    /// No route calls this yet. The login route should first take a token
+    /// from the username's bucket in [`Group::Login`](crate::routes::rate_limit::Group::Login).
+    pub async fn login(
+        db: &mut PgConnection,
+        credentials: &Credentials,
+        policy: &LockoutPolicy,
+    ) -> Result<LoginOutcome, sqlx::Error> {
+        let mut tx = audit::begin(db).await?;
+        let found = sqlx::query!(
+            "
+                SELECT id, username, upassword, email,
+                    EXTRACT(EPOCH FROM locked_until - now())::FLOAT8 AS lock_remaining
+                FROM app_user WHERE username = $1 AND deleted_at IS NULL
+            ",
+            credentials.username.trim(),
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some(found) = found else {
+            return Ok(LoginOutcome::InvalidCredentials);
+        };
+        if let Some(remaining) = found.lock_remaining.filter(|remaining| *remaining > 0.0) {
+            return Ok(LoginOutcome::Locked {
+                retry_after: remaining.ceil() as u64,
+            });
+        }
+
+        if found.upassword != credentials.password {
+            let failed = sqlx::query!(
+                "
+                    UPDATE app_user SET
+                        failed_logins = failed_logins + 1,
+                        locked_until = CASE
+                            WHEN failed_logins + 1 >= $2 THEN now() + make_interval(
+                                secs => LEAST($3::FLOAT8 * power(2, failed_logins + 1 - $2), $4::FLOAT8)
+                            )
+                            ELSE locked_until
+                        END
+                    WHERE id = $1
+                    RETURNING failed_logins, COALESCE(locked_until > now(), FALSE) AS \"locked!\"
+                ",
+                found.id,
+                policy.threshold,
+                policy.base_seconds,
+                policy.max_seconds,
+            )
+            .fetch_one(&mut *tx)
+            .await?;
+            tx.commit().await?;
 
-        // TODO: Add custom completion prints
-        match results {
-            Ok(results_ok) => Ok(results_ok),
-            Err(error) => Err(error),
+            if failed.locked {
+                warn!(
+                    id = found.id,
+                    failed_logins = failed.failed_logins,
+                    "Locked account after failed logins"
+                );
+            }
+            return Ok(LoginOutcome::InvalidCredentials);
         }
+
+        sqlx::query!(
+            "UPDATE app_user SET failed_logins = 0, locked_until = NULL WHERE id = $1",
+            found.id,
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        info!(id = found.id, username = %found.username, "Logged in");
+        Ok(LoginOutcome::LoggedIn(User {
+            id: Some(found.id),
+            username: found.username,
+            upassword: found.upassword,
+            email: found.email,
+        }))
+    }
+
+    /// Replaces the password of a user, unlocking the account
+    ///
+    /// Returns whether a user with that username exists.
+    pub async fn set_password(
+        db: &mut PgConnection,
+        username: &str,
+        password: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let mut tx = audit::begin(db).await?;
+        let updated = sqlx::query!(
+            "
+                UPDATE app_user SET upassword = $2, failed_logins = 0, locked_until = NULL
+                WHERE username = $1 AND deleted_at IS NULL
+            ",
+            username.trim(),
+            password,
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        tx.commit().await?;
+
+        if updated > 0 {
+            info!(username = %username.trim(), "Reset password");
+        }
+        Ok(updated > 0)
+    }
+
+    /// Soft deletes the user, returning whether it existed and was not deleted yet
+    pub async fn delete(mut db: Connection<Db>, id: i32) -> Result<bool, sqlx::Error> {
+        let mut tx = audit::begin(&mut db).await?;
+        let deleted = soft_delete::delete(&mut tx, Entity::User, id).await?;
+        tx.commit().await?;
+
+        if deleted {
+            info!(id, "Deleted user");
+        }
+        Ok(deleted)
+    }
+
+    /// Fields that user lists may be sorted by, the first being the default
+    pub const SORT_FIELDS: &'static [(&'static str, &'static str)] =
+        &[("id", "id"), ("username", "username"), ("email", "email")];
+
+    /// Lists users one page at a time, optionally filtered by a username or email search
+    pub async fn get_all_users(
+        mut db: Connection<Db>,
+        params: &PageParams,
+        search: Option<&str>,
+    ) -> Result<Page<User>, Either<sqlx::Error, ()>> {
+        fetch_page(
+            &mut db,
+            "SELECT id, username, upassword, email FROM app_user",
+            "app_user",
+            Self::SORT_FIELDS,
+            params,
+            |query| {
+                query.push(" AND app_user.deleted_at IS NULL");
+                if let Some(search) = search {
+                    let pattern = escape_like(search);
+                    query.push(" AND (app_user.username ILIKE '%' || ");
+                    query.push_bind(pattern.clone());
+                    query.push(" || '%' OR app_user.email ILIKE '%' || ");
+                    query.push_bind(pattern);
+                    query.push(" || '%')");
+                }
+            },
+        )
+        .await
+    }
+}
+
+impl Identifiable for User {
+    fn row_id(&self) -> Option<i32> {
+        self.id
+    }
+}
+
+impl Validate for User {
+    fn validate(&self) -> Vec<FieldError> {
+        Validator::new()
+            .min_length("username", self.username.trim(), 3)
+            .max_length("username", &self.username, 32)
+            .min_length("upassword", &self.upassword, 8)
+            .max_length("upassword", &self.upassword, 128)
+            .email("email", &self.email)
+            .finish()
     }
 }
@@ -0,0 +1,36 @@
+//! Optimistic concurrency for versioned tables
+//!
+//! Tables with a `version` column are updated with
+//! `... SET version = version + 1 WHERE id = $1 AND version = $expected`, so
+//! an update only applies to the version the client last saw. When such an
+//! update matches no row, [`explain_mismatch`] tells a missing row apart from
+//! one that was changed in the meantime.
+
+use either::{Either, Left, Right};
+use sqlx::PgConnection;
+
+/// Finds out why a versioned update of `table` matched no row
+///
+/// # Returns
+/// * `Left(sqlx::Error::RowNotFound)` if the row does not exist or is soft deleted
+/// * `Right(version)` with the row's current version if it was changed by someone else
+pub async fn explain_mismatch(
+    conn: &mut PgConnection,
+    table: &'static str,
+    id: i32,
+) -> Either<sqlx::Error, i32> {
+    let query = format!(
+        "SELECT version FROM {} WHERE id = $1 AND deleted_at IS NULL",
+        table
+    );
+    let result: Result<Option<i32>, sqlx::Error> = sqlx::query_scalar(&query)
+        .bind(id)
+        .fetch_optional(conn)
+        .await;
+
+    match result {
+        Ok(Some(version)) => Right(version),
+        Ok(None) => Left(sqlx::Error::RowNotFound),
+        Err(error) => Left(error),
+    }
+}
@@ -0,0 +1,153 @@
+//! Batched loading of related records
+//!
+//! Every relation has its own key type, so a single [`DbLoader`] can serve
+//! all of them. The keys requested while resolving one level of a query are
+//! collected and loaded with a single `ANY($1)` query per relation.
+
+use async_graphql::dataloader::Loader;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Arc;
+
+use crate::db::blog_item::Content;
+use crate::db::project_item::DescItem;
+use crate::db::shop_item::{ShopImage, ShopItemDesc};
+use crate::db::tag::Tag;
+
+/// Loads related records from the database for the lifetime of one request
+pub struct DbLoader {
+    pool: PgPool,
+}
+
+impl DbLoader {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+/// Content blocks of the blog with this ID
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BlogContents(pub i32);
+
+/// Tags of the blog with this ID
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BlogTags(pub i32);
+
+/// Descriptions of the project with this ID
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ProjectDescriptions(pub i32);
+
+/// Tags of the project with this ID
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ProjectTags(pub i32);
+
+/// Images of the shop item with this ID
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ShopItemImages(pub i32);
+
+/// Descriptions of the shop item with this ID
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ShopItemDescriptions(pub i32);
+
+/// Tags of the shop item with this ID
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ShopItemTags(pub i32);
+
+/// Groups rows under the key of the item they belong to
+fn group<K: Hash + Eq, V>(rows: impl IntoIterator<Item = (K, V)>) -> HashMap<K, Vec<V>> {
+    let mut groups: HashMap<K, Vec<V>> = HashMap::new();
+    for (key, value) in rows {
+        groups.entry(key).or_default().push(value);
+    }
+    groups
+}
+
+impl Loader<BlogContents> for DbLoader {
+    type Value = Vec<Content>;
+    type Error = Arc<sqlx::Error>;
+
+    async fn load(&self, keys: &[BlogContents]) -> Result<HashMap<BlogContents, Self::Value>, Self::Error> {
+        let ids: Vec<i32> = keys.iter().map(|key| key.0).collect();
+        let rows = Content::get_all_from_blogs(&self.pool, &ids).await?;
+        Ok(group(rows.into_iter().map(|row| (BlogContents(row.blog_id.unwrap_or_default()), row))))
+    }
+}
+
+impl Loader<BlogTags> for DbLoader {
+    type Value = Vec<Tag>;
+    type Error = Arc<sqlx::Error>;
+
+    async fn load(&self, keys: &[BlogTags]) -> Result<HashMap<BlogTags, Self::Value>, Self::Error> {
+        let ids: Vec<i32> = keys.iter().map(|key| key.0).collect();
+        let rows = Tag::get_tags_by_blogs(&self.pool, &ids).await?;
+        Ok(group(rows.into_iter().map(|(blog_id, tag)| (BlogTags(blog_id), tag))))
+    }
+}
+
+impl Loader<ProjectDescriptions> for DbLoader {
+    type Value = Vec<DescItem>;
+    type Error = Arc<sqlx::Error>;
+
+    async fn load(
+        &self,
+        keys: &[ProjectDescriptions],
+    ) -> Result<HashMap<ProjectDescriptions, Self::Value>, Self::Error> {
+        let ids: Vec<i32> = keys.iter().map(|key| key.0).collect();
+        let rows = DescItem::get_all_from_projects(&self.pool, &ids).await?;
+        Ok(group(rows.into_iter().map(|row| {
+            (ProjectDescriptions(row.project_id.unwrap_or_default()), row)
+        })))
+    }
+}
+
+impl Loader<ProjectTags> for DbLoader {
+    type Value = Vec<Tag>;
+    type Error = Arc<sqlx::Error>;
+
+    async fn load(&self, keys: &[ProjectTags]) -> Result<HashMap<ProjectTags, Self::Value>, Self::Error> {
+        let ids: Vec<i32> = keys.iter().map(|key| key.0).collect();
+        let rows = Tag::get_tags_by_projects(&self.pool, &ids).await?;
+        Ok(group(rows.into_iter().map(|(project_id, tag)| (ProjectTags(project_id), tag))))
+    }
+}
+
+impl Loader<ShopItemImages> for DbLoader {
+    type Value = Vec<ShopImage>;
+    type Error = Arc<sqlx::Error>;
+
+    async fn load(&self, keys: &[ShopItemImages]) -> Result<HashMap<ShopItemImages, Self::Value>, Self::Error> {
+        let ids: Vec<i32> = keys.iter().map(|key| key.0).collect();
+        let rows = ShopImage::get_all_from_shop_items(&self.pool, &ids).await?;
+        Ok(group(rows.into_iter().map(|row| {
+            (ShopItemImages(row.shop_item_id.unwrap_or_default()), row)
+        })))
+    }
+}
+
+impl Loader<ShopItemDescriptions> for DbLoader {
+    type Value = Vec<ShopItemDesc>;
+    type Error = Arc<sqlx::Error>;
+
+    async fn load(
+        &self,
+        keys: &[ShopItemDescriptions],
+    ) -> Result<HashMap<ShopItemDescriptions, Self::Value>, Self::Error> {
+        let ids: Vec<i32> = keys.iter().map(|key| key.0).collect();
+        let rows = ShopItemDesc::get_all_from_shop_items(&self.pool, &ids).await?;
+        Ok(group(rows.into_iter().map(|row| {
+            (ShopItemDescriptions(row.shop_item_id.unwrap_or_default()), row)
+        })))
+    }
+}
+
+impl Loader<ShopItemTags> for DbLoader {
+    type Value = Vec<Tag>;
+    type Error = Arc<sqlx::Error>;
+
+    async fn load(&self, keys: &[ShopItemTags]) -> Result<HashMap<ShopItemTags, Self::Value>, Self::Error> {
+        let ids: Vec<i32> = keys.iter().map(|key| key.0).collect();
+        let rows = Tag::get_tags_by_shop_items(&self.pool, &ids).await?;
+        Ok(group(rows.into_iter().map(|(shop_item_id, tag)| (ShopItemTags(shop_item_id), tag))))
+    }
+}
@@ -0,0 +1,206 @@
+//! GraphQL schema
+//!
+//! Exposes blogs, projects, shop items and tags together with their related
+//! records, so a client can fetch a list and everything it shows in one
+//! request. Relations are resolved through [`DbLoader`], which turns the
+//! lookups for all items of a list into one query per relation. Queries are
+//! rejected if they nest deeper or are more complex than the configured limits.
+
+mod loaders;
+
+pub use loaders::DbLoader;
+
+use async_graphql::dataloader::{DataLoader, Loader};
+use async_graphql::{
+    ComplexObject, Context, EmptyMutation, EmptySubscription, Error, Object, Result, Schema,
+};
+use either::{Either, Left};
+use sqlx::pool::PoolConnection;
+use sqlx::{PgPool, Postgres};
+use std::hash::Hash;
+use std::sync::Arc;
+use tracing::error;
+
+use crate::db::blog_item::{BlogItem, Content};
+use crate::db::pagination::PageParams;
+use crate::db::project_item::{DescItem, ProjectItem};
+use crate::db::shop_item::{ShopImage, ShopItem, ShopItemDesc};
+use crate::db::tag::Tag;
+use loaders::{
+    BlogContents, BlogTags, ProjectDescriptions, ProjectTags, ShopItemDescriptions, ShopItemImages,
+    ShopItemTags,
+};
+
+/// Deepest nesting allowed when `graphql_max_depth` is not configured
+pub const DEFAULT_MAX_DEPTH: usize = 6;
+/// Highest complexity allowed when `graphql_max_complexity` is not configured
+pub const DEFAULT_MAX_COMPLEXITY: usize = 250;
+
+pub type ApiSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+/// Builds the schema with the given query limits
+pub fn build_schema(max_depth: usize, max_complexity: usize) -> ApiSchema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .limit_depth(max_depth)
+        .limit_complexity(max_complexity)
+        .finish()
+}
+
+/// Takes a connection from the pool added to the request by the route
+async fn connection(ctx: &Context<'_>) -> Result<PoolConnection<Postgres>> {
+    ctx.data::<PgPool>()?
+        .acquire()
+        .await
+        .map_err(|error| {
+            error!(%error, "Failed to acquire database connection");
+            Error::new("Database is not available")
+        })
+}
+
+/// Maps a failed list query onto an error without exposing database details
+fn list_error(error: Either<sqlx::Error, ()>, message: &str) -> Error {
+    if let Left(error) = error {
+        error!(%error, "{}", message);
+    }
+    Error::new(message)
+}
+
+/// Loads the records related to one item through the request's [`DbLoader`]
+async fn load_related<K, V>(ctx: &Context<'_>, key: K) -> Result<Vec<V>>
+where
+    K: Send + Sync + Hash + Eq + Clone + 'static,
+    V: Send + Sync + Clone + 'static,
+    DbLoader: Loader<K, Value = Vec<V>, Error = Arc<sqlx::Error>>,
+{
+    let loader = ctx.data::<DataLoader<DbLoader>>()?;
+    match loader.load_one(key).await {
+        Ok(related) => Ok(related.unwrap_or_default()),
+        Err(error) => {
+            error!(%error, "Failed to load related records");
+            Err(Error::new("Failed to load related records"))
+        }
+    }
+}
+
+/// Lists are always paged in GraphQL, to keep the cost of a query bounded
+fn page_params(limit: Option<i64>, offset: Option<i64>) -> PageParams {
+    PageParams {
+        limit,
+        offset,
+        ..Default::default()
+    }
+    .always_paged()
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// A page of blogs, optionally filtered by title
+    async fn blogs(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<i64>,
+        offset: Option<i64>,
+        search: Option<String>,
+    ) -> Result<Vec<BlogItem>> {
+        let mut conn = connection(ctx).await?;
+        BlogItem::get_all(&mut conn, &page_params(limit, offset), search.as_deref())
+            .await
+            .map(|page| page.items)
+            .map_err(|error| list_error(error, "Failed to fetch blogs"))
+    }
+
+    /// A page of projects, optionally filtered by title
+    async fn projects(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<i64>,
+        offset: Option<i64>,
+        search: Option<String>,
+    ) -> Result<Vec<ProjectItem>> {
+        let mut conn = connection(ctx).await?;
+        ProjectItem::get_all(&mut conn, &page_params(limit, offset), search.as_deref())
+            .await
+            .map(|page| page.items)
+            .map_err(|error| list_error(error, "Failed to fetch projects"))
+    }
+
+    /// A page of shop items, optionally filtered by name and price
+    async fn shop_items(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<i64>,
+        offset: Option<i64>,
+        search: Option<String>,
+        min_price: Option<f32>,
+        max_price: Option<f32>,
+    ) -> Result<Vec<ShopItem>> {
+        let mut conn = connection(ctx).await?;
+        ShopItem::get_all(
+            &mut conn,
+            &page_params(limit, offset),
+            search.as_deref(),
+            min_price,
+            max_price,
+        )
+        .await
+        .map(|page| page.items)
+        .map_err(|error| list_error(error, "Failed to fetch shop items"))
+    }
+
+    /// A page of tags, optionally filtered by text
+    async fn tags(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<i64>,
+        offset: Option<i64>,
+        search: Option<String>,
+    ) -> Result<Vec<Tag>> {
+        let mut conn = connection(ctx).await?;
+        Tag::get_all(&mut conn, &page_params(limit, offset), search.as_deref())
+            .await
+            .map(|page| page.items)
+            .map_err(|error| list_error(error, "Failed to fetch tags"))
+    }
+}
+
+#[ComplexObject]
+impl BlogItem {
+    /// Content blocks of the blog, in the order they were added
+    async fn contents(&self, ctx: &Context<'_>) -> Result<Vec<Content>> {
+        load_related(ctx, BlogContents(self.id.unwrap_or_default())).await
+    }
+
+    async fn tags(&self, ctx: &Context<'_>) -> Result<Vec<Tag>> {
+        load_related(ctx, BlogTags(self.id.unwrap_or_default())).await
+    }
+}
+
+#[ComplexObject]
+impl ProjectItem {
+    /// Descriptions of the project, in the order they were added
+    async fn descriptions(&self, ctx: &Context<'_>) -> Result<Vec<DescItem>> {
+        load_related(ctx, ProjectDescriptions(self.id.unwrap_or_default())).await
+    }
+
+    async fn tags(&self, ctx: &Context<'_>) -> Result<Vec<Tag>> {
+        load_related(ctx, ProjectTags(self.id.unwrap_or_default())).await
+    }
+}
+
+#[ComplexObject]
+impl ShopItem {
+    async fn images(&self, ctx: &Context<'_>) -> Result<Vec<ShopImage>> {
+        load_related(ctx, ShopItemImages(self.id.unwrap_or_default())).await
+    }
+
+    /// Descriptions of the shop item, in the order they were added
+    async fn descriptions(&self, ctx: &Context<'_>) -> Result<Vec<ShopItemDesc>> {
+        load_related(ctx, ShopItemDescriptions(self.id.unwrap_or_default())).await
+    }
+
+    async fn tags(&self, ctx: &Context<'_>) -> Result<Vec<Tag>> {
+        load_related(ctx, ShopItemTags(self.id.unwrap_or_default())).await
+    }
+}
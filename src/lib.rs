@@ -0,0 +1,27 @@
+//! Library shared by the server and the admin command line tool
+//!
+//! The server in `main.rs` mounts the routes of this crate, while the
+//! `admin` binary in `bin/admin` works on the database through the same
+//! [`db`] modules without starting the server.
+
+#[macro_use]
+extern crate rocket;
+
+use rocket_db_pools::Database;
+
+pub mod api;
+pub mod cors;
+pub mod db;
+pub mod graphql;
+pub mod metrics;
+pub mod retention;
+pub mod routes;
+pub mod telemetry;
+
+/// Database connection pool wrapper for PostgreSQL
+///
+/// This struct represents the connection to our PostgreSQL database
+/// using SQLx as the database driver.
+#[derive(Database)]
+#[database("sqlx")]
+pub struct Db(sqlx::PgPool);
@@ -6,39 +6,42 @@
 #[macro_use]
 extern crate rocket;
 
-use rocket::http::Method;
+use rocket::fairing::AdHoc;
 use rocket::{fairing, Build};
-use rocket::Rocket;
-use rocket_cors::{AllowedOrigins, CorsOptions};
+use rocket::{Rocket, Route};
 use rocket_db_pools::Database;
+use tracing::error;
 
-mod db;
-mod routes;
-mod api;
+use projects_rust_server::{api, cors, db, graphql, metrics, retention, routes, telemetry, Db};
 
-/// Database connection pool wrapper for PostgreSQL
+/// Runs the database migrations once the pool is initialized
 /// 
-/// This struct represents the connection to our PostgreSQL database
-/// using SQLx as the database driver.
-#[derive(Database)]
-#[database("sqlx")]
-pub struct Db(sqlx::PgPool);
-
-/// Initializes the database and runs migrations
+/// A failed migration is logged and the server starts anyway, unless
+/// `require_migrations` is set, in which case launch is aborted. `/readyz`
+/// reports the server as unavailable while migrations are missing.
 /// 
 /// # Arguments
-/// * `rocket` - The Rocket instance to attach the database to
+/// * `rocket` - The Rocket instance with the database attached
 /// 
 /// # Returns
-/// * `fairing::Result` - Success if database initialization and migrations succeed
+/// * `fairing::Result` - Failure if the pool is missing, or if migrations fail while they are required
 pub async fn init_database(rocket: Rocket<Build>) -> fairing::Result {
+    let require_migrations: bool = rocket
+        .figment()
+        .extract_inner("require_migrations")
+        .unwrap_or(false);
+
     match Db::fetch(&rocket) {
-        Some(db) => match sqlx::migrate!("src/db/migrations").run(&**db).await {
+        Some(db) => match db::health::MIGRATOR.run(&**db).await {
             Ok(_) => Ok(rocket),
-            Err(e) => {
-                error!("Failed to initialize SQLx database: {}", e);
+            Err(e) if require_migrations => {
+                error!(error = %e, "Failed to run database migrations, refusing to start");
                 Err(rocket)
             }
+            Err(e) => {
+                error!(error = %e, "Failed to run database migrations");
+                Ok(rocket)
+            }
         },
         None => Err(rocket),
     }
@@ -47,8 +50,17 @@ pub async fn init_database(rocket: Rocket<Build>) -> fairing::Result {
 /// Configures and launches the Rocket web server
 /// 
 /// This function:
-/// - Sets up CORS configuration
-/// - Initializes the database connection
+/// - Sets up logging and per-request tracing
+/// - Records request and database metrics
+/// - Sets up the configured CORS policy
+/// - Serves the pre-v2 API paths as deprecated aliases
+/// - Rate limits API requests per client IP and per authenticated user
+/// - Records every change in the audit log, queryable with the admin key
+/// - Soft deletes content and users, purging them after the retention period
+/// - Replays responses for repeated `Idempotency-Key`s
+/// - Builds the GraphQL schema with its query limits
+/// - Initializes the database connection and runs migrations
+/// - Serves health, readiness and version probes
 /// - Mounts all route handlers
 /// - Launches the web server
 /// 
@@ -56,55 +68,153 @@ pub async fn init_database(rocket: Rocket<Build>) -> fairing::Result {
 /// * The configured Rocket instance
 #[launch]
 async fn rocket() -> _ {
-    let cors = CorsOptions::default()
-        .allowed_origins(AllowedOrigins::all())
-        .allowed_methods(
-            vec![Method::Get, Method::Post, Method::Patch]
-                .into_iter()
-                .map(From::from)
-                .collect(),
-        )
-        .allow_credentials(true);
-
     let rocket = rocket::build();
-    let rocket = match init_database(rocket).await {
-        Ok(rocket) => rocket,
-        Err(r) => r,
-    };
+    let log_filter: String = rocket
+        .figment()
+        .extract_inner("log_filter")
+        .unwrap_or_else(|_| telemetry::DEFAULT_FILTER.to_string());
+    let log_format: String = rocket
+        .figment()
+        .extract_inner("log_format")
+        .unwrap_or_else(|_| "json".to_string());
+    let slow_query_threshold_ms: u64 = rocket
+        .figment()
+        .extract_inner("slow_query_threshold_ms")
+        .unwrap_or(metrics::DEFAULT_SLOW_QUERY_MS);
+    telemetry::init(
+        &log_filter,
+        telemetry::LogFormat::from_name(&log_format),
+        slow_query_threshold_ms,
+    );
 
+    let cors = cors::CorsConfig::from_figment(rocket.figment())
+        .map_err(|error| error.to_string())
+        .and_then(|config| config.to_cors())
+        .unwrap_or_else(|error| panic!("Invalid CORS configuration: {}", error));
+    let legacy_api_sunset: String = rocket
+        .figment()
+        .extract_inner("legacy_api_sunset")
+        .unwrap_or_else(|_| routes::legacy::DEFAULT_SUNSET.to_string());
+    let idempotency_ttl: u64 = rocket
+        .figment()
+        .extract_inner("idempotency_ttl")
+        .unwrap_or(routes::idempotency::DEFAULT_TTL);
+    let graphql_max_depth: usize = rocket
+        .figment()
+        .extract_inner("graphql_max_depth")
+        .unwrap_or(graphql::DEFAULT_MAX_DEPTH);
+    let graphql_max_complexity: usize = rocket
+        .figment()
+        .extract_inner("graphql_max_complexity")
+        .unwrap_or(graphql::DEFAULT_MAX_COMPLEXITY);
+    let rate_limit: routes::rate_limit::RateLimitConfig = rocket
+        .figment()
+        .extract_inner("rate_limit")
+        .unwrap_or_default();
+    let admin_api_key: Option<String> = rocket.figment().extract_inner("admin_api_key").ok();
+    let soft_delete_retention_days: u32 = rocket
+        .figment()
+        .extract_inner("soft_delete_retention_days")
+        .unwrap_or(retention::DEFAULT_RETENTION_DAYS);
     rocket
-        .attach(cors.to_cors().unwrap())
+        .attach(telemetry::RequestTracing)
+        .attach(metrics::RequestMetrics)
+        .attach(cors::CorsRejections)
+        .attach(cors)
         .attach(Db::init())
+        .attach(AdHoc::on_ignite("Slow query metrics", move |rocket| async move {
+            if let Some(db) = Db::fetch(&rocket) {
+                metrics::report_slow_statements(db, slow_query_threshold_ms).await;
+            }
+            rocket
+        }))
+        .attach(AdHoc::try_on_ignite("Database migrations", init_database))
+        .attach(routes::legacy::LegacyRoutes::new(legacy_api_sunset))
+        .attach(routes::rate_limit::RateLimit)
+        .attach(routes::idempotency::Idempotency::new(idempotency_ttl))
+        .attach(retention::Retention::new(soft_delete_retention_days))
+        .manage(routes::rate_limit::RateLimiter::new(rate_limit))
+        .manage(api::admin::AdminKey::new(admin_api_key))
+        .manage(graphql::build_schema(graphql_max_depth, graphql_max_complexity))
+        .register("/", catchers![api::default_catcher])
         .mount(
             "/",
-            routes![
+            telemetry::traced(routes![
                 routes::static_files::solidjs_assets,
                 routes::static_files::solidjs_index,
                 routes::shop::shop_items,
+                routes::shop::shop_items_by_tag,
                 routes::shop::create_shop_item,
+                routes::shop::update_shop_item,
+                routes::shop::delete_shop_item,
                 routes::shop::shop_item_images,
                 routes::shop::create_shop_item_image,
                 routes::shop::shop_item_descs,
                 routes::shop::create_shop_item_desc,
-                routes::shop::create_shop_item_desc_many,
                 routes::blog::blogs,
+                routes::blog::blogs_by_tag,
                 routes::blog::blog_contents,
                 routes::blog::create_blog,
+                routes::blog::update_blog,
+                routes::blog::delete_blog,
                 routes::project::projects,
                 routes::project::projects_by_tag,
                 routes::project::add_tags_to_project,
                 routes::project::project_descs,
                 routes::project::create_project_item,
+                routes::project::update_project_item,
+                routes::project::delete_project_item,
                 routes::project::create_project_desc,
-                routes::project::create_project_desc_many,
                 routes::tag::tags,
+                routes::tag::tag_cloud,
+                routes::tag::tags_autocomplete,
                 routes::tag::tag_category,
+                routes::tag::remove_tag_category,
                 routes::tag::tag_project,
                 routes::tag::create_tag,
+                routes::tag::rename_tag,
+                routes::tag::merge_tags,
+                routes::tag::delete_tag,
                 routes::tag::tags_by_project,
                 routes::tag::tags_by_category,
+                routes::tag::tags_by_blog,
+                routes::tag::tags_by_shop_item,
+                routes::tag::tagged,
+                routes::tag::tag_blog,
+                routes::tag::untag_blog,
+                routes::tag::tag_shop_item,
+                routes::tag::untag_shop_item,
+                routes::category::categories,
+                routes::category::create_category,
+                routes::category::update_category,
+                routes::category::delete_category,
                 routes::user::users,
                 routes::user::create_user,
-            ],
+                routes::user::delete_user,
+                routes::batch::batch,
+                routes::admin::audit_log,
+                routes::admin::restore,
+                routes::admin::purge,
+                routes::graphql::graphql,
+                routes::graphql::graphiql,
+                routes::docs::openapi,
+                routes::docs::docs,
+                routes::metrics::metrics,
+                routes::health::healthz,
+                routes::health::readyz,
+                routes::health::version,
+                routes::idempotency::replay,
+                routes::rate_limit::rate_limited,
+            ]),
         )
+        .mount("/", telemetry::traced(deprecated_routes()))
+}
+
+/// Routes kept for existing clients, superseded by `POST /api/v2/batch`
+#[allow(deprecated)]
+fn deprecated_routes() -> Vec<Route> {
+    routes![
+        routes::shop::create_shop_item_desc_many,
+        routes::project::create_project_desc_many,
+    ]
 }
@@ -0,0 +1,212 @@
+//! Prometheus metrics
+//!
+//! Request counts and latencies are recorded for every route by the
+//! [`RequestMetrics`] fairing, labelled with the route's URI template rather
+//! than the requested path so that IDs don't multiply the series. Slow
+//! queries are counted from the warnings sqlx emits for statements over the
+//! threshold set by [`report_slow_statements`], and the
+//! business counters are incremented by the code that creates the records.
+//! There is no counter of orders placed, since the shop only lists items and
+//! the server has no orders to count.
+//! Everything is served in the text format from `/metrics`.
+
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::request::Request;
+use rocket::response::Response;
+use rocket::Data;
+use sqlx::{ConnectOptions, PgPool};
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+/// Duration above which a query counts as slow when `slow_query_threshold_ms` is not configured
+pub const DEFAULT_SLOW_QUERY_MS: u64 = 500;
+
+/// Route label for requests that did not match any route
+const UNMATCHED_ROUTE: &str = "unmatched";
+
+/// The metrics of the server, registered in their own registry
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+pub struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    request_duration: HistogramVec,
+    pool_connections: IntGaugeVec,
+    slow_queries: IntCounter,
+    pub blogs_created: IntCounter,
+    pub users_registered: IntCounter,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "Handled requests"),
+            &["method", "route", "status"],
+        )
+        .expect("valid metric");
+        let request_duration = HistogramVec::new(
+            HistogramOpts::new("http_request_duration_seconds", "Time taken to handle requests"),
+            &["method", "route"],
+        )
+        .expect("valid metric");
+        let pool_connections = IntGaugeVec::new(
+            Opts::new("db_pool_connections", "Connections held by the database pool"),
+            &["state"],
+        )
+        .expect("valid metric");
+        let slow_queries = IntCounter::new(
+            "db_slow_queries_total",
+            "Queries slower than the configured threshold",
+        )
+        .expect("valid metric");
+        let blogs_created =
+            IntCounter::new("blogs_created_total", "Blogs created").expect("valid metric");
+        let users_registered =
+            IntCounter::new("users_registered_total", "Users registered").expect("valid metric");
+
+        let registry = Registry::new();
+        registry.register(Box::new(requests.clone())).expect("unique metric");
+        registry.register(Box::new(request_duration.clone())).expect("unique metric");
+        registry.register(Box::new(pool_connections.clone())).expect("unique metric");
+        registry.register(Box::new(slow_queries.clone())).expect("unique metric");
+        registry.register(Box::new(blogs_created.clone())).expect("unique metric");
+        registry.register(Box::new(users_registered.clone())).expect("unique metric");
+
+        Self {
+            registry,
+            requests,
+            request_duration,
+            pool_connections,
+            slow_queries,
+            blogs_created,
+            users_registered,
+        }
+    }
+
+    /// Records the connections currently held by the pool
+    ///
+    /// # Arguments
+    /// * `size` - Open connections, idle or in use
+    /// * `idle` - Open connections that are not in use
+    pub fn set_pool_connections(&self, size: u32, idle: usize) {
+        let idle = idle as i64;
+        self.pool_connections.with_label_values(&["idle"]).set(idle);
+        self.pool_connections
+            .with_label_values(&["active"])
+            .set(i64::from(size) - idle);
+    }
+
+    /// Renders every metric in the Prometheus text format
+    pub fn render(&self) -> Result<(String, Vec<u8>), prometheus::Error> {
+        let encoder = TextEncoder::new();
+        let mut body = Vec::new();
+        encoder.encode(&self.registry.gather(), &mut body)?;
+        Ok((encoder.format_type().to_string(), body))
+    }
+}
+
+/// When a request was received, kept in its local cache
+struct RequestStart(Option<Instant>);
+
+/// Fairing that counts and times the requests of every route
+pub struct RequestMetrics;
+
+#[rocket::async_trait]
+impl Fairing for RequestMetrics {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request metrics",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _data: &mut Data<'_>) {
+        req.local_cache(|| RequestStart(Some(Instant::now())));
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        let method = req.method().as_str();
+        let route = req
+            .route()
+            .map(|route| route.uri.as_str())
+            .unwrap_or(UNMATCHED_ROUTE);
+        let status = res.status().code.to_string();
+
+        METRICS
+            .requests
+            .with_label_values(&[method, route, status.as_str()])
+            .inc();
+        if let RequestStart(Some(started)) = req.local_cache(|| RequestStart(None)) {
+            METRICS
+                .request_duration
+                .with_label_values(&[method, route])
+                .observe(started.elapsed().as_secs_f64());
+        }
+    }
+}
+
+/// Reads the duration sqlx attaches to its statement events
+struct ElapsedVisitor(Option<f64>);
+
+impl Visit for ElapsedVisitor {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        if field.name() == "elapsed_secs" {
+            self.0 = Some(value);
+        }
+    }
+
+    fn record_debug(&mut self, _field: &Field, _value: &dyn std::fmt::Debug) {}
+}
+
+/// Makes sqlx warn about statements slower than `threshold_ms`, for
+/// [`SlowQueries`] to count
+///
+/// Other statements are still logged as `databases.sqlx.log_level` says.
+/// Connections opened before keep their options, so the idle ones are closed
+/// for the pool to replace.
+pub async fn report_slow_statements(pool: &PgPool, threshold_ms: u64) {
+    let options = (*pool.connect_options())
+        .clone()
+        .log_slow_statements(log::LevelFilter::Warn, Duration::from_millis(threshold_ms));
+    pool.set_connect_options(options);
+
+    while let Some(connection) = pool.try_acquire() {
+        connection.close().await.ok();
+    }
+}
+
+/// Log layer that counts the sqlx statements slower than a threshold
+pub struct SlowQueries {
+    threshold_secs: f64,
+}
+
+impl SlowQueries {
+    /// # Arguments
+    /// * `threshold_ms` - Duration above which a query counts as slow
+    pub fn new(threshold_ms: u64) -> Self {
+        Self {
+            threshold_secs: threshold_ms as f64 / 1000.0,
+        }
+    }
+}
+
+impl<S: Subscriber> Layer<S> for SlowQueries {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        if event.metadata().target() != "sqlx::query" {
+            return;
+        }
+
+        let mut elapsed = ElapsedVisitor(None);
+        event.record(&mut elapsed);
+        if elapsed.0.is_some_and(|secs| secs >= self.threshold_secs) {
+            METRICS.slow_queries.inc();
+        }
+    }
+}
@@ -0,0 +1,98 @@
+//! Retention of soft-deleted rows
+//!
+//! Once the server has launched, [`Retention`] purges every row that was soft
+//! deleted more than `soft_delete_retention_days` ago, right away and then
+//! every hour. Purges are recorded in the audit log with `retention` as their
+//! actor. A retention of 0 days turns the job off, keeping deleted rows until
+//! an admin purges them.
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::tokio::{self, time};
+use rocket::{Orbit, Rocket};
+use rocket_db_pools::Database;
+use sqlx::PgPool;
+use std::time::Duration;
+use tracing::{debug, error, info};
+
+use crate::db::audit::{self, AuditContext};
+use crate::db::soft_delete;
+use crate::Db;
+
+/// Days soft-deleted rows are kept when `soft_delete_retention_days` is not configured
+pub const DEFAULT_RETENTION_DAYS: u32 = 30;
+
+/// How often expired rows are looked for
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Actor recorded in the audit log for purges made by the job
+const RETENTION_ACTOR: &str = "retention";
+
+/// Fairing that starts the retention job on liftoff
+pub struct Retention {
+    days: u32,
+}
+
+impl Retention {
+    /// # Arguments
+    /// * `days` - How long soft-deleted rows are kept, or 0 to keep them until purged by hand
+    pub fn new(days: u32) -> Self {
+        Self { days }
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for Retention {
+    fn info(&self) -> Info {
+        Info {
+            name: "Soft delete retention",
+            kind: Kind::Liftoff,
+        }
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        if self.days == 0 {
+            info!("Soft delete retention is off");
+            return;
+        }
+        let Some(db) = Db::fetch(rocket) else {
+            error!("No database pool, soft-deleted rows will not be purged");
+            return;
+        };
+
+        let pool = (**db).clone();
+        let days = self.days;
+        let shutdown = rocket.shutdown();
+        tokio::spawn(async move {
+            tokio::pin!(shutdown);
+            let mut interval = time::interval(PURGE_INTERVAL);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => purge_expired(&pool, days).await,
+                    _ = &mut shutdown => break,
+                }
+            }
+        });
+    }
+}
+
+/// Purges the expired rows in a single transaction, logging the outcome
+async fn purge_expired(pool: &PgPool, days: u32) {
+    let context = AuditContext {
+        actor: Some(RETENTION_ACTOR.to_string()),
+        request_id: None,
+    };
+    let result = audit::scope(context, async {
+        let mut conn = pool.acquire().await?;
+        let mut tx = audit::begin(&mut conn).await?;
+        let purged = soft_delete::purge_expired(&mut tx, days).await?;
+        tx.commit().await?;
+        Ok::<_, sqlx::Error>(purged)
+    })
+    .await;
+
+    match result {
+        Ok(0) => debug!(retention_days = days, "No soft-deleted rows to purge"),
+        Ok(purged) => info!(purged, retention_days = days, "Purged soft-deleted rows"),
+        Err(error) => error!(%error, "Failed to purge soft-deleted rows"),
+    }
+}
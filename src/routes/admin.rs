@@ -0,0 +1,181 @@
+//! Admin routes
+//!
+//! Every route here requires the admin key, see [`crate::api::admin`].
+
+use rocket::{delete, get, post};
+use rocket::http::Status;
+use rocket_db_pools::Connection;
+use sqlx::Either::{Left, Right};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+use crate::api::admin::Admin;
+use crate::api::{ApiError, ApiResponse, ApiResult, ErrorCode, FieldError};
+use crate::db::audit::{self, AuditEntry, AuditFilter};
+use crate::db::pagination::PageParams;
+use crate::db::soft_delete::{self, Entity};
+use crate::Db;
+
+/// Parses an optional RFC 3339 timestamp from the query string
+fn parse_timestamp(field: &str, value: Option<&str>) -> Result<Option<OffsetDateTime>, FieldError> {
+    value
+        .map(|value| OffsetDateTime::parse(value.trim(), &Rfc3339))
+        .transpose()
+        .map_err(|_| FieldError::new(field, "must be an RFC 3339 timestamp"))
+}
+
+/// Retrieves a page of the audit log
+///
+/// The log is paged even without `limit`, `offset` or `cursor`.
+///
+/// # Arguments
+/// * `db` - Database connection
+/// * `actor` - Only changes made by this actor, `admin` or a client IP
+/// * `action` - Only `insert`, `update` or `delete` changes
+/// * `entity_type` - Only changes to this table, e.g. `shop_item`
+/// * `entity_id` - Only changes to the row with this ID
+/// * `request_id` - Only changes made while handling this request
+/// * `since` - Only changes made at or after this RFC 3339 timestamp
+/// * `until` - Only changes made before this RFC 3339 timestamp
+/// * `page` - Pagination and sorting parameters; sortable by `id` and `created_at`
+///
+/// # Returns
+/// * `ApiResult<Vec<AuditEntry>>` - A page of audit entries on success
+/// * `ApiError` - If the admin key is missing, a filter is invalid or the database operation fails
+#[utoipa::path(
+    tag = "admin",
+    params(PageParams),
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<Vec<AuditEntry>>),
+        (status = 401, description = "Missing or wrong admin key", body = ApiError),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[get("/api/v2/admin/audit-log?<actor>&<action>&<entity_type>&<entity_id>&<request_id>&<since>&<until>&<page..>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn audit_log(
+    _admin: Admin,
+    mut db: Connection<Db>,
+    actor: Option<&str>,
+    action: Option<&str>,
+    entity_type: Option<&str>,
+    entity_id: Option<i32>,
+    request_id: Option<&str>,
+    since: Option<&str>,
+    until: Option<&str>,
+    page: PageParams,
+) -> ApiResult<Vec<AuditEntry>> {
+    let (since, until) = match (parse_timestamp("since", since), parse_timestamp("until", until)) {
+        (Ok(since), Ok(until)) => (since, until),
+        (since, until) => {
+            let errors = [since.err(), until.err()].into_iter().flatten().collect();
+            return Err(ApiError::new("Invalid filter", Status::UnprocessableEntity)
+                .with_code(ErrorCode::ValidationFailed)
+                .with_field_errors(errors));
+        }
+    };
+    let filter = AuditFilter {
+        actor,
+        action,
+        entity_type,
+        entity_id,
+        request_id,
+        since,
+        until,
+    };
+
+    match AuditEntry::get_all(&mut db, &page.always_paged(), &filter).await {
+        Ok(results) => Ok(ApiResponse::paginated(results)),
+        Err(Right(_)) => Err(ApiError::new(
+            "Invalid sort field",
+            Status::UnprocessableEntity
+        ).with_code(ErrorCode::InvalidSortField)),
+        Err(Left(error)) => Err(ApiError::from_sqlx(error, "Failed to fetch the audit log")),
+    }
+}
+
+/// Restores a soft-deleted blog, project, shop item, tag or user
+///
+/// # Arguments
+/// * `db` - Database connection
+/// * `entity` - Kind of row: `blogs`, `projects`, `shop-items`, `tags` or `users`
+/// * `id` - ID of the row
+///
+/// # Returns
+/// * `ApiResult<String>` - Success message
+/// * `ApiError` - If the row does not exist, is not deleted, or the database operation fails
+#[utoipa::path(
+    tag = "admin",
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<String>),
+        (status = 401, description = "Missing or wrong admin key", body = ApiError),
+        (status = 404, description = "No such deleted row", body = ApiError),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[post("/api/v2/admin/<entity>/<id>/restore")]
+pub async fn restore(
+    _admin: Admin,
+    mut db: Connection<Db>,
+    entity: Entity,
+    id: i32,
+) -> ApiResult<String> {
+    let failed = |error| ApiError::from_sqlx(error, "Failed to restore");
+    let mut tx = audit::begin(&mut db).await.map_err(failed)?;
+    let restored = soft_delete::restore(&mut tx, entity, id).await.map_err(failed)?;
+    tx.commit().await.map_err(failed)?;
+
+    if restored {
+        Ok(ApiResponse::success("Restored successfully".to_string()))
+    } else {
+        Err(ApiError::new(
+            "No such deleted row",
+            Status::NotFound
+        ))
+    }
+}
+
+/// Deletes a soft-deleted blog, project, shop item, tag or user for good
+///
+/// Rows that belong to it, such as a blog's contents or its tag links, are
+/// deleted as well. Rows that are not soft deleted are left alone, so they
+/// have to be deleted through their own route first.
+///
+/// # Arguments
+/// * `db` - Database connection
+/// * `entity` - Kind of row: `blogs`, `projects`, `shop-items`, `tags` or `users`
+/// * `id` - ID of the row
+///
+/// # Returns
+/// * `ApiResult<String>` - Success message
+/// * `ApiError` - If the row does not exist, is not deleted, or the database operation fails
+#[utoipa::path(
+    tag = "admin",
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<String>),
+        (status = 401, description = "Missing or wrong admin key", body = ApiError),
+        (status = 404, description = "No such deleted row", body = ApiError),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[delete("/api/v2/admin/<entity>/<id>")]
+pub async fn purge(
+    _admin: Admin,
+    mut db: Connection<Db>,
+    entity: Entity,
+    id: i32,
+) -> ApiResult<String> {
+    let failed = |error| ApiError::from_sqlx(error, "Failed to purge");
+    let mut tx = audit::begin(&mut db).await.map_err(failed)?;
+    let purged = soft_delete::purge(&mut tx, entity, id).await.map_err(failed)?;
+    tx.commit().await.map_err(failed)?;
+
+    if purged {
+        Ok(ApiResponse::success("Purged successfully".to_string()))
+    } else {
+        Err(ApiError::new(
+            "No such deleted row",
+            Status::NotFound
+        ))
+    }
+}
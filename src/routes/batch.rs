@@ -0,0 +1,330 @@
+//! Batch route module
+//!
+//! Runs a list of create and tagging operations in one database transaction.
+//! Every operation runs in its own savepoint, so a failing operation does not
+//! affect the others unless the client asks for the whole batch to be atomic.
+//! Operations cannot reference IDs created earlier in the same batch.
+
+use rocket::http::Status;
+use rocket::post;
+use rocket::serde::json::{self, Value};
+use rocket_db_pools::Connection;
+use serde::{Deserialize, Serialize};
+use sqlx::Either::{self, Left, Right};
+use sqlx::{Acquire, Postgres, Transaction};
+use tracing::warn;
+use utoipa::ToSchema;
+
+use crate::api::validation::{Validate, Validated, Validator};
+use crate::api::{ApiError, ApiResponse, ApiResult, ErrorCode, FieldError};
+use crate::db::audit;
+use crate::db::blog_item::{BlogItem, Content};
+use crate::db::project_item::{DescItem, ProjectItem};
+use crate::db::shop_item::{ShopImage, ShopItem, ShopItemDesc};
+use crate::db::tag::{BlogToTag, ProjectToTechTag, ShopItemToTag};
+use crate::metrics::METRICS;
+use crate::Db;
+
+/// Most operations accepted in a single batch
+const MAX_OPERATIONS: usize = 100;
+
+/// A single operation of a batch, e.g. `{"op": "create_blog", "data": {...}}`
+///
+/// The data of each operation is the body of the matching single-item route.
+#[derive(Deserialize, ToSchema)]
+#[serde(tag = "op", content = "data", rename_all = "snake_case")]
+pub enum BatchOperation {
+    CreateShopItem(ShopItem),
+    CreateShopItemImage(ShopImage),
+    CreateShopItemDescription(ShopItemDesc),
+    CreateBlog(BlogItem),
+    CreateBlogContent(Content),
+    CreateProject(ProjectItem),
+    CreateProjectDescription(DescItem),
+    TagProject(ProjectToTechTag),
+    TagBlog(BlogToTag),
+    TagShopItem(ShopItemToTag),
+}
+
+impl Validate for BatchOperation {
+    fn validate(&self) -> Vec<FieldError> {
+        match self {
+            BatchOperation::CreateShopItem(data) => data.validate(),
+            BatchOperation::CreateShopItemImage(data) => data.validate(),
+            BatchOperation::CreateShopItemDescription(data) => data.validate(),
+            BatchOperation::CreateBlog(data) => data.validate(),
+            BatchOperation::CreateBlogContent(data) => data.validate(),
+            BatchOperation::CreateProject(data) => data.validate(),
+            BatchOperation::CreateProjectDescription(data) => data.validate(),
+            BatchOperation::TagProject(data) => data.validate(),
+            BatchOperation::TagBlog(data) => data.validate(),
+            BatchOperation::TagShopItem(data) => data.validate(),
+        }
+    }
+}
+
+/// Maps the error of an insert that needs a parent ID onto an API error
+fn insert_error(error: Either<sqlx::Error, ()>, message: &str, parent_field: &str) -> ApiError {
+    match error {
+        Left(error) => ApiError::from_sqlx(error, message),
+        Right(_) => ApiError::new(message, Status::BadRequest)
+            .with_field_errors(vec![FieldError::new(parent_field, "is required")]),
+    }
+}
+
+/// Serializes the result of an operation for the batch response
+fn to_data<T: Serialize>(value: T) -> Result<Value, ApiError> {
+    json::to_value(value)
+        .map_err(|_| ApiError::new("Failed to serialize result", Status::InternalServerError))
+}
+
+impl BatchOperation {
+    /// Runs the operation inside the given transaction
+    async fn run(&self, tx: &mut Transaction<'_, Postgres>) -> Result<Value, ApiError> {
+        match self {
+            BatchOperation::CreateShopItem(item) => to_data(
+                item.add_tx(tx)
+                    .await
+                    .map_err(|error| ApiError::from_sqlx(error, "Failed to create shop item"))?,
+            ),
+            BatchOperation::CreateShopItemImage(image) => to_data(
+                image
+                    .add_tx(tx)
+                    .await
+                    .map_err(|error| insert_error(error, "Failed to create shop item image", "shop_item_id"))?,
+            ),
+            BatchOperation::CreateShopItemDescription(desc) => to_data(
+                desc.add_tx(tx)
+                    .await
+                    .map_err(|error| insert_error(error, "Failed to create shop item description", "shop_item_id"))?,
+            ),
+            BatchOperation::CreateBlog(blog) => to_data(
+                blog.add_tx(tx)
+                    .await
+                    .map_err(|error| ApiError::from_sqlx(error, "Failed to create blog item"))?,
+            ),
+            BatchOperation::CreateBlogContent(content) => to_data(
+                content
+                    .add_tx(tx)
+                    .await
+                    .map_err(|error| insert_error(error, "Failed to create blog content", "blog_id"))?,
+            ),
+            BatchOperation::CreateProject(project) => to_data(
+                project
+                    .add_tx(tx)
+                    .await
+                    .map_err(|error| ApiError::from_sqlx(error, "Failed to create project"))?,
+            ),
+            BatchOperation::CreateProjectDescription(desc) => to_data(
+                desc.add_tx(tx)
+                    .await
+                    .map_err(|error| insert_error(error, "Failed to create project description", "project_id"))?,
+            ),
+            BatchOperation::TagProject(link) => to_data(
+                link.add_tx(tx)
+                    .await
+                    .map_err(|error| ApiError::from_sqlx(error, "Failed to tag project"))?,
+            ),
+            BatchOperation::TagBlog(link) => to_data(
+                link.add_tx(tx)
+                    .await
+                    .map_err(|error| ApiError::from_sqlx(error, "Failed to tag blog"))?,
+            ),
+            BatchOperation::TagShopItem(link) => to_data(
+                link.add_tx(tx)
+                    .await
+                    .map_err(|error| ApiError::from_sqlx(error, "Failed to tag shop item"))?,
+            ),
+        }
+    }
+}
+
+/// Data structure for a batch of operations
+#[derive(Deserialize, ToSchema)]
+pub struct BatchRequest {
+    /// Operations to run, in order
+    pub operations: Vec<BatchOperation>,
+    /// Roll back every operation if any of them fails
+    #[serde(default)]
+    pub atomic: bool,
+}
+
+impl Validate for BatchRequest {
+    fn validate(&self) -> Vec<FieldError> {
+        Validator::new()
+            .check("operations", !self.operations.is_empty(), "must not be empty")
+            .check(
+                "operations",
+                self.operations.len() <= MAX_OPERATIONS,
+                &format!("must contain at most {} operations", MAX_OPERATIONS),
+            )
+            .each("operations", &self.operations)
+            .finish()
+    }
+}
+
+/// Outcome of a single operation of a batch
+#[derive(Serialize, ToSchema)]
+pub struct BatchOperationResult {
+    pub success: bool,
+    /// The created record, as returned by the matching single-item route
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub data: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ApiError>,
+}
+
+/// Outcome of a batch
+#[derive(Serialize, ToSchema)]
+pub struct BatchResponse {
+    /// Whether the changes of the successful operations were kept
+    pub committed: bool,
+    /// One result per operation, in request order
+    pub results: Vec<BatchOperationResult>,
+}
+
+/// Runs several operations in one database transaction
+///
+/// Without `atomic`, failed operations are rolled back on their own and the
+/// rest is committed. With `atomic`, the first failure rolls back the whole
+/// batch and the remaining operations are not run.
+///
+/// Operations cannot reference IDs created earlier in the same batch, since
+/// those are only known from the response. A new blog and its content blocks,
+/// for example, take two batches.
+///
+/// # Arguments
+/// * `db` - Database connection
+/// * `request` - Operations to run and whether the batch is atomic
+///
+/// # Returns
+/// * `ApiResult<BatchResponse>` - Per-operation results and whether they were committed
+/// * `ApiError` - If the batch is invalid or the transaction fails
+#[utoipa::path(
+    tag = "batch",
+    request_body = BatchRequest,
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<BatchResponse>),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[post("/api/v2/batch", data = "<request>", format = "json")]
+pub async fn batch(mut db: Connection<Db>, request: Validated<BatchRequest>) -> ApiResult<BatchResponse> {
+    let mut tx = audit::begin(&mut db)
+        .await
+        .map_err(|error| ApiError::from_sqlx(error, "Failed to start transaction"))?;
+    let mut results: Vec<BatchOperationResult> = Vec::with_capacity(request.operations.len());
+    let mut failed = false;
+
+    for operation in &request.operations {
+        if request.atomic && failed {
+            results.push(BatchOperationResult {
+                success: false,
+                data: None,
+                error: Some(
+                    ApiError::new("Not run because an earlier operation failed", Status::FailedDependency)
+                        .with_code(ErrorCode::NotExecuted),
+                ),
+            });
+            continue;
+        }
+
+        let mut savepoint = tx
+            .begin()
+            .await
+            .map_err(|error| ApiError::from_sqlx(error, "Failed to start transaction"))?;
+        match operation.run(&mut savepoint).await {
+            Ok(data) => {
+                savepoint
+                    .commit()
+                    .await
+                    .map_err(|error| ApiError::from_sqlx(error, "Failed to commit operation"))?;
+                results.push(BatchOperationResult {
+                    success: true,
+                    data: Some(data),
+                    error: None,
+                });
+            }
+            Err(error) => {
+                if let Err(error) = savepoint.rollback().await {
+                    warn!(%error, "Failed to roll back batch operation");
+                }
+                failed = true;
+                results.push(BatchOperationResult {
+                    success: false,
+                    data: None,
+                    error: Some(error),
+                });
+            }
+        }
+    }
+
+    let committed = !(request.atomic && failed);
+    if committed {
+        tx.commit()
+            .await
+            .map_err(|error| ApiError::from_sqlx(error, "Failed to commit transaction"))?;
+
+        let blogs_created = request
+            .operations
+            .iter()
+            .zip(&results)
+            .filter(|(operation, result)| {
+                result.success && matches!(operation, BatchOperation::CreateBlog(_))
+            })
+            .count();
+        METRICS.blogs_created.inc_by(blogs_created as u64);
+    } else {
+        if let Err(error) = tx.rollback().await {
+            warn!(%error, "Failed to roll back batch");
+        }
+    }
+
+    Ok(ApiResponse::success(BatchResponse { committed, results }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn description_batches_replace_the_bulk_description_routes() {
+        let request: BatchRequest = json::from_str(
+            r#"{
+                "atomic": true,
+                "operations": [
+                    {"op": "create_project_description", "data": {"project_id": 1, "content": "Built with Rocket"}},
+                    {"op": "create_shop_item_description", "data": {"shop_item_id": 2, "content": "Hand made"}}
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        assert!(request.atomic);
+        assert!(request.validate().is_empty());
+        assert!(matches!(
+            &request.operations[0],
+            BatchOperation::CreateProjectDescription(desc) if desc.project_id == Some(1)
+        ));
+        assert!(matches!(
+            &request.operations[1],
+            BatchOperation::CreateShopItemDescription(desc) if desc.shop_item_id == Some(2)
+        ));
+    }
+
+    #[test]
+    fn batches_are_limited_in_size() {
+        let operation =
+            r#"{"op": "create_project_description", "data": {"project_id": 1, "content": "a"}}"#;
+        let batch = |count| {
+            let operations = vec![operation; count].join(",");
+            json::from_str::<BatchRequest>(&format!(r#"{{"operations": [{}]}}"#, operations))
+                .unwrap()
+        };
+
+        assert!(batch(MAX_OPERATIONS).validate().is_empty());
+        assert_eq!(batch(MAX_OPERATIONS + 1).validate().len(), 1);
+        assert_eq!(batch(0).validate().len(), 1);
+    }
+}
@@ -1,50 +1,113 @@
 use rocket::http::Status;
-use rocket::serde::json::Json;
-use rocket::{get, post};
+use rocket::{delete, get, patch, post};
 use rocket_db_pools::Connection;
+use sqlx::Either::{Left, Right};
 
-pub use crate::db::blog_item::{BlogItem, Content};
+pub use crate::db::blog_item::{BlogItem, BlogItemPatch, Content};
+pub use crate::db::pagination::PageParams;
+use crate::db::stamps::Stamps;
 pub use crate::Db;
-pub use crate::api::{ApiResponse, ApiResult, ApiError};
+pub use crate::api::{ApiResponse, ApiResult, ApiError, ErrorCode};
+use crate::api::admin::Admin;
+use crate::api::validation::Validated;
+use crate::api::cache::{CachePolicy, Cached};
+use crate::api::concurrency::{ExpectedVersion, IfMatch, WithVersion};
 
-#[get("/api/blogs")]
-pub async fn blogs(db: Connection<Db>) -> ApiResult<Vec<BlogItem>> {
-    match BlogItem::get_all(db).await {
-        Ok(results) => Ok(ApiResponse::success(results)),
-        Err(_error) => {
+#[utoipa::path(
+    tag = "blog",
+    params(PageParams),
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<Vec<BlogItem>>),
+        (status = 304, description = "Not modified since the cached copy"),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[get("/api/v2/blogs?<q>&<page..>")]
+pub async fn blogs(mut db: Connection<Db>, q: Option<&str>, page: PageParams) -> Cached<Vec<BlogItem>> {
+    let result = match BlogItem::get_all(&mut db, &page, q).await {
+        Ok(results) => Ok(ApiResponse::paginated(results)),
+        Err(Right(_)) => {
             Err(ApiError::new(
-                "Failed to fetch blog items",
-                Status::InternalServerError
-            ))
+                "Invalid sort field",
+                Status::UnprocessableEntity
+            ).with_code(ErrorCode::InvalidSortField))
+        }
+        Err(Left(error)) => {
+            Err(ApiError::from_sqlx(error, "Failed to fetch blog items"))
+        }
+    };
+    Cached::new(result, CachePolicy::Public(60))
+}
+
+/// Retrieves all blog items associated with a specific tag
+///
+/// # Arguments
+/// * `db` - Database connection
+/// * `tag_id` - ID of the tag to filter blog items by
+///
+/// # Returns
+/// * `ApiResult<Vec<BlogItem>>` - List of blog items with the specified tag
+#[utoipa::path(
+    tag = "blog",
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<Vec<BlogItem>>),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[get("/api/v2/tags/<tag_id>/blogs")]
+pub async fn blogs_by_tag(
+    db: Connection<Db>,
+    tag_id: i32,
+) -> ApiResult<Vec<BlogItem>> {
+    match BlogItem::get_blogs_by_tag(db, tag_id).await {
+        Ok(results) => Ok(ApiResponse::success(results)),
+        Err(error) => {
+            Err(ApiError::from_sqlx(error, "Failed to fetch blogs by tag"))
         }
     }
 }
 
-#[get("/api/blog-content/<id>")]
+#[utoipa::path(
+    tag = "blog",
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<Vec<Content>>),
+        (status = 304, description = "Not modified since the cached copy"),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[get("/api/v2/blogs/<id>/contents")]
 pub async fn blog_contents(
     db: Connection<Db>,
     id: i32,
-) -> ApiResult<Vec<Content>> {
-    match Content::get_all_from_blog(db, id).await {
+) -> Cached<Vec<Content>> {
+    let result = match Content::get_all_from_blog(db, id).await {
         Ok(results) => Ok(ApiResponse::success(results)),
-        Err(_error) => {
-            Err(ApiError::new(
-                "Failed to fetch blog contents",
-                Status::InternalServerError
-            ))
+        Err(error) => {
+            Err(ApiError::from_sqlx(error, "Failed to fetch blog contents"))
         }
-    }
+    };
+    Cached::new(result, CachePolicy::Public(60))
 }
 
-#[post("/api/blog", data = "<blog_item>", format = "json")]
+#[utoipa::path(
+    tag = "blog",
+    request_body = BlogItem,
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<BlogItem>),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[post("/api/v2/blogs", data = "<blog_item>", format = "json")]
 pub async fn create_blog(
     db: Connection<Db>,
-    blog_item: Json<BlogItem>,
+    blog_item: Validated<BlogItem>,
 ) -> ApiResult<BlogItem> {
     let blog_item_deser = BlogItem {
         id: None,
         blog_title: blog_item.blog_title.clone(),
         header_img: blog_item.header_img.clone(),
+        version: None,
+        stamps: Stamps::default(),
         content: blog_item.content.clone(),
     };
     let result = blog_item_deser.add(db).await;
@@ -60,11 +123,81 @@ pub async fn create_blog(
                 ))
             }
         }
-        Err(_error) => {
-            Err(ApiError::new(
-                "Failed to create blog item",
-                Status::InternalServerError
-            ))
+        Err(error) => {
+            Err(ApiError::from_sqlx(error, "Failed to create blog item"))
         }
     }
+}
+
+/// Updates a blog item
+/// 
+/// # Arguments
+/// * `db` - Database connection
+/// * `id` - Blog item ID
+/// * `if_match` - Version the update is based on, sent as `If-Match: "v<version>"`
+/// * `patch` - Fields to change, optionally with the version the update is based on
+/// 
+/// # Returns
+/// * `WithVersion<BlogItem>` - The updated blog item, with its new version as the ETag
+/// * `ApiError` - 428 without a version, 412 or 409 if the version is stale
+#[utoipa::path(
+    tag = "blog",
+    request_body = BlogItemPatch,
+    params(("If-Match" = Option<String>, Header, description = "Version the update is based on, e.g. \"v3\", or * for any")),
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<BlogItem>,
+            headers(("ETag" = String, description = "The new version, e.g. \"v4\""))),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[patch("/api/v2/blogs/<id>", data = "<patch>", format = "json")]
+pub async fn update_blog(
+    db: Connection<Db>,
+    id: i32,
+    if_match: IfMatch,
+    patch: Validated<BlogItemPatch>,
+) -> Result<WithVersion<BlogItem>, ApiError> {
+    let expected = ExpectedVersion::resolve(&if_match, patch.version)?;
+
+    match BlogItem::update(db, id, &patch, expected.version).await {
+        Ok(result) => Ok(WithVersion(result)),
+        Err(Right(current_version)) => Err(expected.mismatch(current_version)),
+        Err(Left(sqlx::Error::RowNotFound)) => Err(ApiError::new(
+            "Blog item not found",
+            Status::NotFound
+        )),
+        Err(Left(error)) => Err(ApiError::from_sqlx(error, "Failed to update blog item")),
+    }
+}
+
+/// Soft deletes a blog item
+/// 
+/// Requires the admin key. It is hidden from every list and lookup until an
+/// admin restores or purges it.
+/// 
+/// # Arguments
+/// * `db` - Database connection
+/// * `id` - Blog item ID
+/// 
+/// # Returns
+/// - `ApiResult<String>`: Success message
+/// - `ApiError`: If the blog item does not exist or the delete fails
+#[utoipa::path(
+    tag = "blog",
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<String>),
+        (status = 401, description = "Missing or wrong admin key", body = ApiError),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[delete("/api/v2/blogs/<id>")]
+pub async fn delete_blog(_admin: Admin, db: Connection<Db>, id: i32) -> ApiResult<String> {
+    match BlogItem::delete(db, id).await {
+        Ok(true) => Ok(ApiResponse::success("Blog item deleted successfully".to_string())),
+        Ok(false) => Err(ApiError::new(
+            "Blog item not found",
+            Status::NotFound
+        )),
+        Err(error) => Err(ApiError::from_sqlx(error, "Failed to delete blog item")),
+    }
 }
\ No newline at end of file
@@ -0,0 +1,136 @@
+//! Tag category management routes
+//!
+//! This module provides CRUD endpoints for the categories that tags can be
+//! grouped under (e.g. language, framework, database).
+
+use rocket::http::Status;
+use rocket::{delete, get, patch, post};
+use rocket_db_pools::Connection;
+
+use crate::api::admin::Admin;
+use crate::db::stamps::Stamps;
+use crate::db::tag_category::TagCategory;
+use crate::Db;
+use crate::api::{ApiResponse, ApiResult, ApiError};
+use crate::api::validation::Validated;
+use crate::api::cache::{CachePolicy, Cached};
+
+/// Retrieves all tag categories
+///
+/// # Returns
+/// - `Cached<Vec<TagCategory>>`: A list of all categories on success
+/// - `ApiError`: If database operation fails
+#[utoipa::path(
+    tag = "category",
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<Vec<TagCategory>>),
+        (status = 304, description = "Not modified since the cached copy"),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[get("/api/v2/categories")]
+pub async fn categories(mut db: Connection<Db>) -> Cached<Vec<TagCategory>> {
+    let result = match TagCategory::get_all(&mut db).await {
+        Ok(results) => Ok(ApiResponse::success(results)),
+        Err(error) => Err(ApiError::from_sqlx(error, "Failed to fetch categories")),
+    };
+    Cached::new(result, CachePolicy::Public(300))
+}
+
+/// Creates a new tag category
+///
+/// # Arguments
+/// * `db` - Database connection
+/// * `category` - The category data to create
+///
+/// # Returns
+/// - `ApiResult<TagCategory>`: The created category
+/// - `ApiError`: If the name is taken (Status::Conflict) or creation fails
+#[utoipa::path(
+    tag = "category",
+    request_body = TagCategory,
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<TagCategory>),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[post("/api/v2/categories", data = "<category>", format = "json")]
+pub async fn create_category(
+    mut db: Connection<Db>,
+    category: Validated<TagCategory>,
+) -> ApiResult<TagCategory> {
+    let category_deser = TagCategory {
+        id: None,
+        name: category.name.clone(),
+        stamps: Stamps::default(),
+    };
+    match category_deser.add(&mut db).await {
+        Ok(result) => Ok(ApiResponse::success(result)),
+        Err(error) => Err(ApiError::from_sqlx(error, "Failed to create category")),
+    }
+}
+
+/// Renames an existing tag category
+///
+/// # Arguments
+/// * `db` - Database connection
+/// * `id` - Category ID
+/// * `category` - The category data holding the new name
+///
+/// # Returns
+/// - `ApiResult<TagCategory>`: The renamed category
+/// - `ApiError`: If the category does not exist, the name is taken, or the update fails
+#[utoipa::path(
+    tag = "category",
+    request_body = TagCategory,
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<TagCategory>),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[patch("/api/v2/categories/<id>", data = "<category>", format = "json")]
+pub async fn update_category(
+    db: Connection<Db>,
+    id: i32,
+    category: Validated<TagCategory>,
+) -> ApiResult<TagCategory> {
+    match TagCategory::rename(db, id, &category.name).await {
+        Ok(result) => Ok(ApiResponse::success(result)),
+        Err(sqlx::Error::RowNotFound) => Err(ApiError::new(
+            "Category not found",
+            Status::NotFound
+        )),
+        Err(error) => Err(ApiError::from_sqlx(error, "Failed to update category")),
+    }
+}
+
+/// Deletes a tag category and detaches it from every tag
+///
+/// Requires the admin key.
+///
+/// # Arguments
+/// * `db` - Database connection
+/// * `id` - Category ID
+///
+/// # Returns
+/// - `ApiResult<String>`: Success message
+/// - `ApiError`: If the category does not exist or the delete fails
+#[utoipa::path(
+    tag = "category",
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<String>),
+        (status = 401, description = "Missing or wrong admin key", body = ApiError),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[delete("/api/v2/categories/<id>")]
+pub async fn delete_category(_admin: Admin, db: Connection<Db>, id: i32) -> ApiResult<String> {
+    match TagCategory::delete(db, id).await {
+        Ok(0) => Err(ApiError::new(
+            "Category not found",
+            Status::NotFound
+        )),
+        Ok(_) => Ok(ApiResponse::success("Category deleted successfully".to_string())),
+        Err(error) => Err(ApiError::from_sqlx(error, "Failed to delete category")),
+    }
+}
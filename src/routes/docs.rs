@@ -0,0 +1,36 @@
+//! API documentation routes
+//!
+//! Serves the generated OpenAPI document and a Redoc page that renders it.
+
+use rocket::get;
+use rocket::response::content::RawHtml;
+use rocket::serde::json::Json;
+use utoipa::OpenApi;
+
+use crate::api::openapi::ApiDoc;
+
+const REDOC_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+  <head>
+    <title>Projects Rust Server API</title>
+    <meta charset="utf-8" />
+    <meta name="viewport" content="width=device-width, initial-scale=1" />
+  </head>
+  <body>
+    <redoc spec-url="/api/openapi.json"></redoc>
+    <script src="https://cdn.redoc.ly/redoc/latest/bundles/redoc.standalone.js"></script>
+  </body>
+</html>
+"#;
+
+/// Returns the OpenAPI 3 document describing every API route
+#[get("/api/openapi.json")]
+pub fn openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// Renders the OpenAPI document with Redoc
+#[get("/api/docs")]
+pub fn docs() -> RawHtml<&'static str> {
+    RawHtml(REDOC_PAGE)
+}
@@ -0,0 +1,37 @@
+//! GraphQL routes
+//!
+//! Serves the schema from [`crate::graphql`] and a GraphiQL page to explore it.
+
+use async_graphql::dataloader::DataLoader;
+use async_graphql::http::GraphiQLSource;
+use async_graphql_rocket::{GraphQLRequest, GraphQLResponse};
+use rocket::response::content::RawHtml;
+use rocket::{get, post, State};
+use sqlx::PgPool;
+
+use crate::graphql::{ApiSchema, DbLoader};
+use crate::Db;
+
+/// Executes a GraphQL query
+///
+/// Each request gets its own [`DbLoader`], so related records are batched
+/// and cached only for the duration of the query.
+#[post("/api/v2/graphql", data = "<request>")]
+pub async fn graphql(
+    schema: &State<ApiSchema>,
+    db: &State<Db>,
+    request: GraphQLRequest,
+) -> GraphQLResponse {
+    let pool = PgPool::clone(db);
+    request
+        .data(DataLoader::new(DbLoader::new(pool.clone()), rocket::tokio::spawn))
+        .data(pool)
+        .execute(schema.inner())
+        .await
+}
+
+/// Renders GraphiQL for exploring the schema
+#[get("/api/v2/graphql")]
+pub fn graphiql() -> RawHtml<String> {
+    RawHtml(GraphiQLSource::build().endpoint("/api/v2/graphql").finish())
+}
@@ -0,0 +1,109 @@
+//! Health routes
+//!
+//! Probes for the deployment: `/healthz` answers as long as the process
+//! serves requests, `/readyz` only once the database is reachable and fully
+//! migrated, and `/version` tells which build is running.
+
+use rocket::get;
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::State;
+use serde::Serialize;
+
+use crate::db::health::{pending_migrations, ping};
+use crate::Db;
+
+/// Result of a single readiness check
+#[derive(Serialize)]
+pub struct Check {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Check {
+    fn passed() -> Self {
+        Self { ok: true, error: None }
+    }
+
+    fn failed(error: impl Into<String>) -> Self {
+        Self {
+            ok: false,
+            error: Some(error.into()),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Health {
+    pub status: &'static str,
+}
+
+#[derive(Serialize)]
+pub struct Readiness {
+    pub status: &'static str,
+    pub database: Check,
+    pub migrations: Check,
+}
+
+#[derive(Serialize)]
+pub struct Version {
+    pub name: &'static str,
+    pub version: &'static str,
+    pub git_commit: &'static str,
+    pub rustc: &'static str,
+    pub profile: &'static str,
+    /// Unix timestamp of the build
+    pub built_at: u64,
+}
+
+/// Liveness probe, answering as long as the server handles requests
+#[get("/healthz")]
+pub fn healthz() -> Json<Health> {
+    Json(Health { status: "ok" })
+}
+
+/// Readiness probe, checking that Postgres answers and every migration is applied
+///
+/// # Returns
+/// * 200 with the checks if the server can serve traffic
+/// * 503 with the failed checks otherwise
+#[get("/readyz")]
+pub async fn readyz(db: &State<Db>) -> (Status, Json<Readiness>) {
+    let (database, migrations) = match ping(db).await {
+        Ok(()) => {
+            let migrations = match pending_migrations(db).await {
+                Ok(pending) if pending.is_empty() => Check::passed(),
+                Ok(pending) => Check::failed(format!("Pending migrations: {:?}", pending)),
+                Err(error) => Check::failed(format!("Failed to read applied migrations: {}", error)),
+            };
+            (Check::passed(), migrations)
+        }
+        Err(error) => (
+            Check::failed(format!("Database is not reachable: {}", error)),
+            Check::failed("Database is not reachable"),
+        ),
+    };
+
+    let ready = database.ok && migrations.ok;
+    let readiness = Readiness {
+        status: if ready { "ready" } else { "unavailable" },
+        database,
+        migrations,
+    };
+    let status = if ready { Status::Ok } else { Status::ServiceUnavailable };
+    (status, Json(readiness))
+}
+
+/// Build information of the running server
+#[get("/version")]
+pub fn version() -> Json<Version> {
+    Json(Version {
+        name: env!("CARGO_PKG_NAME"),
+        version: env!("CARGO_PKG_VERSION"),
+        git_commit: env!("BUILD_GIT_COMMIT"),
+        rustc: env!("BUILD_RUSTC_VERSION"),
+        profile: env!("BUILD_PROFILE"),
+        built_at: env!("BUILD_TIMESTAMP").parse().unwrap_or_default(),
+    })
+}
@@ -0,0 +1,299 @@
+//! Idempotent retries of POST requests
+//!
+//! A client may send an `Idempotency-Key` header with any POST whose handler
+//! takes a [`Validated`] body. The first request with a key is handled as
+//! usual and, if it succeeds, its response is stored for the configured TTL.
+//! A retry with the same key is not passed to the handler again: it is
+//! rewritten to [`replay`], which answers with the stored response, or
+//! rejects the retry if its body differs from the first request.
+//!
+//! Keys are scoped to the authenticated user and to the method and path of
+//! the request, not to the client IP, so a retry from another network is
+//! still recognised. Anonymous requests share one scope and should use random
+//! keys such as UUIDs.
+//!
+//! Every POST under `/api/v2` that takes a JSON body supports the key. The
+//! GraphQL and admin routes do not read their body through [`Validated`],
+//! which keeps it for comparing retries, so a key sent to them is rejected
+//! with 400.
+//!
+//! [`Validated`]: crate::api::validation::Validated
+
+use rocket::data::{Data, Limits};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::uri::Origin;
+use rocket::http::{ContentType, Header, Method, Status};
+use rocket::request::{self, FromRequest, Request};
+use rocket::response::{self, Responder, Response};
+use rocket::post;
+use rocket_db_pools::Database;
+use std::io::Cursor;
+use tracing::error;
+
+use crate::api::admin;
+use crate::api::cache::fnv1a;
+use crate::api::validation::RawBody;
+use crate::api::{ApiError, ErrorCode};
+use crate::db::idempotency::{IdempotencyRecord, ScopedKey};
+use crate::telemetry::request_span;
+use crate::Db;
+
+/// How long responses are kept when `idempotency_ttl` is not configured, in seconds
+pub const DEFAULT_TTL: u64 = 24 * 60 * 60;
+
+/// Longest accepted `Idempotency-Key`
+const MAX_KEY_LENGTH: usize = 255;
+
+/// Internal path that requests with an already used key are rewritten to
+const REPLAY_PATH: &str = "/api/v2/idempotency/replay";
+
+/// Paths of the POST routes that do not take a [`Validated`] body
+///
+/// [`Validated`]: crate::api::validation::Validated
+const UNSUPPORTED_PREFIXES: &[&str] = &["/api/v2/graphql", "/api/v2/admin/"];
+
+/// Whether retries of a POST to `path` can be recognised by their key
+fn supports_key(path: &str) -> bool {
+    !UNSUPPORTED_PREFIXES
+        .iter()
+        .any(|prefix| path.starts_with(prefix))
+}
+
+/// What the fairing decided for a request, kept in its local cache
+#[derive(Default)]
+enum KeyState {
+    /// The request has no `Idempotency-Key`
+    #[default]
+    Absent,
+    /// First use of the key; the response is stored once the request succeeds
+    Reserved(ScopedKey),
+    /// The key was used before by the same user for the same endpoint
+    Used(IdempotencyRecord),
+    /// The key could not be checked
+    Rejected(Status, &'static str),
+}
+
+/// Fairing that reserves idempotency keys and stores successful responses
+pub struct Idempotency {
+    ttl_seconds: u64,
+}
+
+impl Idempotency {
+    /// # Arguments
+    /// * `ttl_seconds` - How long a key and its response are kept
+    pub fn new(ttl_seconds: u64) -> Self {
+        Self { ttl_seconds }
+    }
+
+    async fn check_key(&self, req: &Request<'_>, key: &str) -> KeyState {
+        if key.is_empty() || key.len() > MAX_KEY_LENGTH {
+            return KeyState::Rejected(
+                Status::BadRequest,
+                "Idempotency-Key must be between 1 and 255 characters",
+            );
+        }
+        if !supports_key(req.uri().path().as_str()) {
+            return KeyState::Rejected(
+                Status::BadRequest,
+                "Idempotency-Key is not supported by this route",
+            );
+        }
+        let Some(db) = Db::fetch(req.rocket()) else {
+            return KeyState::Rejected(Status::ServiceUnavailable, "Database is not available");
+        };
+
+        let scoped = ScopedKey {
+            identity: admin::identity(req).unwrap_or_default().to_string(),
+            method: req.method().as_str().to_string(),
+            path: req.uri().path().to_string(),
+            key: key.to_string(),
+        };
+        match IdempotencyRecord::reserve(db, &scoped, self.ttl_seconds).await {
+            Ok(None) => KeyState::Reserved(scoped),
+            Ok(Some(record)) => KeyState::Used(record),
+            Err(error) => {
+                error!(parent: &request_span(req), %error, key = %key, "Failed to reserve idempotency key");
+                KeyState::Rejected(Status::InternalServerError, "Failed to check Idempotency-Key")
+            }
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for Idempotency {
+    fn info(&self) -> Info {
+        Info {
+            name: "Idempotency keys",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _data: &mut Data<'_>) {
+        if req.method() != Method::Post || req.uri().path() == REPLAY_PATH {
+            return;
+        }
+        let key = match req.headers().get_one("Idempotency-Key") {
+            Some(key) => key.trim().to_string(),
+            None => return,
+        };
+
+        let state = self.check_key(req, &key).await;
+        let reserved = matches!(state, KeyState::Reserved(_));
+        req.local_cache(|| state);
+        if !reserved {
+            if let Ok(origin) = Origin::parse(REPLAY_PATH) {
+                req.set_uri(origin);
+            }
+        }
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        let KeyState::Reserved(key) = req.local_cache(KeyState::default) else {
+            return;
+        };
+        let Some(db) = Db::fetch(req.rocket()) else {
+            return;
+        };
+
+        // Supported routes read their body through Validated
+        let RawBody(request_body) = req.local_cache(RawBody::default);
+        if !res.status().class().is_success() || request_body.is_empty() {
+            if let Err(error) = IdempotencyRecord::release(db, key).await {
+                error!(parent: &request_span(req), %error, key = %key.key, "Failed to release idempotency key");
+            }
+            return;
+        }
+
+        let body = match res.body_mut().to_bytes().await {
+            Ok(body) => body,
+            Err(error) => {
+                error!(parent: &request_span(req), %error, key = %key.key, "Failed to read response for idempotency key");
+                if let Err(error) = IdempotencyRecord::release(db, key).await {
+                    error!(parent: &request_span(req), %error, key = %key.key, "Failed to release idempotency key");
+                }
+                return;
+            }
+        };
+        res.set_sized_body(body.len(), Cursor::new(body.clone()));
+
+        let result = IdempotencyRecord::complete(
+            db,
+            key,
+            fnv1a(request_body.as_bytes()) as i64,
+            res.status().code as i16,
+            res.content_type().map(|content_type| content_type.to_string()),
+            &body,
+        )
+        .await;
+        if let Err(error) = result {
+            error!(parent: &request_span(req), %error, key = %key.key, "Failed to store response for idempotency key");
+        }
+    }
+}
+
+/// Request guard giving access to the fairing's decision
+///
+/// Forwards requests the fairing did not rewrite, so [`replay`] cannot be
+/// called directly.
+pub struct ReplayState<'r>(&'r KeyState);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ReplayState<'r> {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        match req.local_cache(KeyState::default) {
+            state @ (KeyState::Used(_) | KeyState::Rejected(..)) => {
+                request::Outcome::Success(ReplayState(state))
+            }
+            KeyState::Absent | KeyState::Reserved(_) => request::Outcome::Forward(Status::NotFound),
+        }
+    }
+}
+
+/// A stored response sent again for a repeated idempotency key
+pub struct Replayed(IdempotencyRecord);
+
+impl<'r> Responder<'r, 'static> for Replayed {
+    fn respond_to(self, _req: &'r Request<'_>) -> response::Result<'static> {
+        let record = self.0;
+        let status = record
+            .response_status
+            .and_then(|code| Status::from_code(code as u16))
+            .ok_or(Status::InternalServerError)?;
+        let body = record.response_body.unwrap_or_default();
+
+        let mut builder = Response::build();
+        builder
+            .status(status)
+            .header(Header::new("Idempotent-Replayed", "true"))
+            .sized_body(body.len(), Cursor::new(body));
+        if let Some(content_type) = record
+            .response_content_type
+            .and_then(|content_type| ContentType::parse_flexible(&content_type))
+        {
+            builder.header(content_type);
+        }
+
+        Ok(builder.finalize())
+    }
+}
+
+/// Answers a POST whose `Idempotency-Key` was used before
+///
+/// Only reached through the rewrite done by [`Idempotency`].
+///
+/// # Returns
+/// * `Replayed` - The stored response of the first request
+/// * `ApiError` - 422 if the key was used with a different body, 409 if the
+///   first request is still being processed
+#[post("/api/v2/idempotency/replay", data = "<body>")]
+pub async fn replay(
+    state: ReplayState<'_>,
+    limits: &Limits,
+    body: Data<'_>,
+) -> Result<Replayed, ApiError> {
+    let record = match state.0 {
+        KeyState::Used(record) => record,
+        KeyState::Rejected(status, message) => return Err(ApiError::new(*message, *status)),
+        KeyState::Absent | KeyState::Reserved(_) => {
+            return Err(ApiError::new("Resource not found", Status::NotFound))
+        }
+    };
+
+    if record.response_status.is_none() {
+        return Err(ApiError::new(
+            "A request with this Idempotency-Key is still being processed",
+            Status::Conflict,
+        ));
+    }
+
+    let limit = limits.get("json").unwrap_or(Limits::JSON);
+    let body = body
+        .open(limit)
+        .into_string()
+        .await
+        .map_err(|_| ApiError::new("Failed to read request body", Status::BadRequest))?;
+    if record.request_hash != Some(fnv1a(body.as_bytes()) as i64) {
+        return Err(ApiError::new(
+            "Idempotency-Key was already used with a different request body",
+            Status::UnprocessableEntity,
+        )
+        .with_code(ErrorCode::IdempotencyKeyReused));
+    }
+
+    Ok(Replayed(record.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_are_rejected_by_routes_without_a_validated_body() {
+        assert!(supports_key("/api/v2/tags"));
+        assert!(supports_key("/api/v2/batch"));
+        assert!(!supports_key("/api/v2/graphql"));
+        assert!(!supports_key("/api/v2/admin/blogs/1/restore"));
+    }
+}
@@ -0,0 +1,238 @@
+//! Deprecated aliases for the pre-v2 API paths
+//!
+//! Every handler is mounted under `/api/v2`. Requests to one of the old
+//! `/api/...` paths are rewritten to the matching v2 path before routing, and
+//! the response is marked with `Deprecation`, `Sunset` and a `Link` to the
+//! successor path, so existing frontends keep working while they migrate.
+//! Responses of the deprecated v2 routes are marked the same way.
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::uri::Origin;
+use rocket::http::{Header, Method};
+use rocket::{Data, Request, Response};
+
+/// Sunset date used when `legacy_api_sunset` is not configured
+pub const DEFAULT_SUNSET: &str = "Fri, 01 Oct 2027 00:00:00 GMT";
+
+/// Old paths and their v2 successors
+///
+/// `<name>` segments match any value and are copied into the successor path.
+const LEGACY_ROUTES: &[(Method, &str, &str)] = &[
+    (Method::Get, "/api/shopitems", "/api/v2/shop-items"),
+    (Method::Get, "/api/shopitems-by-tag/<tag_id>", "/api/v2/tags/<tag_id>/shop-items"),
+    (Method::Post, "/api/shopitem", "/api/v2/shop-items"),
+    (Method::Get, "/api/shopitemimages/<id>", "/api/v2/shop-items/<id>/images"),
+    (Method::Post, "/api/shopitemimage", "/api/v2/shop-item-images"),
+    (Method::Get, "/api/shopitemdescs/<id>", "/api/v2/shop-items/<id>/descriptions"),
+    (Method::Post, "/api/shopitemdesc", "/api/v2/shop-item-descriptions"),
+    (Method::Post, "/api/shopitemdesc/many", "/api/v2/shop-item-descriptions/batch"),
+    (Method::Get, "/api/blogs", "/api/v2/blogs"),
+    (Method::Get, "/api/blogs-by-tag/<tag_id>", "/api/v2/tags/<tag_id>/blogs"),
+    (Method::Get, "/api/blog-content/<id>", "/api/v2/blogs/<id>/contents"),
+    (Method::Post, "/api/blog", "/api/v2/blogs"),
+    (Method::Get, "/api/projects", "/api/v2/projects"),
+    (Method::Get, "/api/projects-by-tag/<tag_id>", "/api/v2/tags/<tag_id>/projects"),
+    (Method::Post, "/api/project/tag", "/api/v2/project-tags/batch"),
+    (Method::Get, "/api/project_descs/<id>", "/api/v2/projects/<id>/descriptions"),
+    (Method::Post, "/api/project", "/api/v2/projects"),
+    (Method::Post, "/api/project_desc", "/api/v2/project-descriptions"),
+    (Method::Post, "/api/project_desc_many", "/api/v2/project-descriptions/batch"),
+    (Method::Get, "/api/tags", "/api/v2/tags"),
+    (Method::Get, "/api/tags/cloud", "/api/v2/tags/cloud"),
+    (Method::Get, "/api/tags/autocomplete", "/api/v2/tags/autocomplete"),
+    (Method::Post, "/api/tag", "/api/v2/tags"),
+    (Method::Patch, "/api/tag/<id>", "/api/v2/tags/<id>"),
+    (Method::Post, "/api/tag/merge", "/api/v2/tags/merge"),
+    (Method::Delete, "/api/tag/<id>", "/api/v2/tags/<id>"),
+    (Method::Get, "/api/tags/by-project/<id>", "/api/v2/projects/<id>/tags"),
+    (Method::Get, "/api/tags/by-blog/<id>", "/api/v2/blogs/<id>/tags"),
+    (Method::Get, "/api/tags/by-shop-item/<id>", "/api/v2/shop-items/<id>/tags"),
+    (Method::Get, "/api/tagged/<tag>", "/api/v2/tagged/<tag>"),
+    (Method::Get, "/api/tags-by-category/<category>", "/api/v2/categories/<category>/tags"),
+    (Method::Post, "/api/tag_category", "/api/v2/tag-categories"),
+    (Method::Delete, "/api/tag_category/<tag_id>/<category>", "/api/v2/tags/<tag_id>/categories/<category>"),
+    (Method::Post, "/api/tag_project", "/api/v2/project-tags"),
+    (Method::Post, "/api/tag_blog", "/api/v2/blog-tags"),
+    (Method::Delete, "/api/tag_blog/<blog_id>/<tag_id>", "/api/v2/blogs/<blog_id>/tags/<tag_id>"),
+    (Method::Post, "/api/tag_shop_item", "/api/v2/shop-item-tags"),
+    (Method::Delete, "/api/tag_shop_item/<shop_item_id>/<tag_id>", "/api/v2/shop-items/<shop_item_id>/tags/<tag_id>"),
+    (Method::Get, "/api/categories", "/api/v2/categories"),
+    (Method::Post, "/api/category", "/api/v2/categories"),
+    (Method::Patch, "/api/category/<id>", "/api/v2/categories/<id>"),
+    (Method::Delete, "/api/category/<id>", "/api/v2/categories/<id>"),
+    (Method::Get, "/api/users", "/api/v2/users"),
+    (Method::Post, "/api/user", "/api/v2/users"),
+];
+
+/// Deprecated v2 paths and the paths superseding them
+const DEPRECATED_ROUTES: &[(Method, &str, &str)] = &[
+    (Method::Post, "/api/v2/shop-item-descriptions/batch", "/api/v2/batch"),
+    (Method::Post, "/api/v2/project-descriptions/batch", "/api/v2/batch"),
+];
+
+/// Finds the v2 path for an old path, filling in its parameters
+fn successor_path(method: Method, path: &str) -> Option<String> {
+    let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+
+    LEGACY_ROUTES
+        .iter()
+        .filter(|(route_method, _, _)| *route_method == method)
+        .find_map(|(_, legacy, successor)| {
+            let pattern: Vec<&str> = legacy.trim_start_matches('/').split('/').collect();
+            if pattern.len() != segments.len() {
+                return None;
+            }
+
+            let mut successor = successor.to_string();
+            for (expected, actual) in pattern.iter().zip(&segments) {
+                if expected.starts_with('<') {
+                    successor = successor.replace(*expected, actual);
+                } else if expected != actual {
+                    return None;
+                }
+            }
+            Some(successor)
+        })
+}
+
+/// Finds the path superseding a deprecated v2 path
+fn superseding_path(method: Method, path: &str) -> Option<&'static str> {
+    DEPRECATED_ROUTES
+        .iter()
+        .find(|(route_method, deprecated, _)| *route_method == method && *deprecated == path)
+        .map(|(_, _, successor)| *successor)
+}
+
+/// The path a deprecated request should move to, if any
+#[derive(Default)]
+struct Successor(Option<String>);
+
+/// Fairing that serves the old API paths as deprecated aliases
+pub struct LegacyRoutes {
+    sunset: String,
+}
+
+impl LegacyRoutes {
+    /// # Arguments
+    /// * `sunset` - HTTP date after which the old paths may be removed
+    pub fn new(sunset: impl Into<String>) -> Self {
+        Self {
+            sunset: sunset.into(),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for LegacyRoutes {
+    fn info(&self) -> Info {
+        Info {
+            name: "Legacy API path aliases",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _data: &mut Data<'_>) {
+        let mut successor = None;
+        if let Some(path) = successor_path(req.method(), req.uri().path().as_str()) {
+            let uri = match req.uri().query() {
+                Some(query) => format!("{}?{}", path, query),
+                None => path.clone(),
+            };
+            let Ok(origin) = Origin::parse_owned(uri) else {
+                return;
+            };
+            req.set_uri(origin);
+            successor = Some(path);
+        }
+
+        // Old paths of deprecated v2 routes point straight to their replacement
+        if let Some(path) = superseding_path(req.method(), req.uri().path().as_str()) {
+            successor = Some(path.to_string());
+        }
+        if successor.is_some() {
+            req.local_cache(move || Successor(successor));
+        }
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        if let Successor(Some(successor)) = req.local_cache(Successor::default) {
+            res.set_header(Header::new("Deprecation", "true"));
+            res.set_header(Header::new("Sunset", self.sunset.clone()));
+            res.set_header(Header::new(
+                "Link",
+                format!("<{}>; rel=\"successor-version\"", successor),
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_paths_map_to_their_successor() {
+        assert_eq!(
+            successor_path(Method::Get, "/api/shopitems").as_deref(),
+            Some("/api/v2/shop-items")
+        );
+        assert_eq!(
+            successor_path(Method::Get, "/api/tags/cloud").as_deref(),
+            Some("/api/v2/tags/cloud")
+        );
+    }
+
+    #[test]
+    fn parameters_are_copied_into_the_successor() {
+        assert_eq!(
+            successor_path(Method::Get, "/api/blog-content/7").as_deref(),
+            Some("/api/v2/blogs/7/contents")
+        );
+        assert_eq!(
+            successor_path(Method::Delete, "/api/tag_blog/3/9").as_deref(),
+            Some("/api/v2/blogs/3/tags/9")
+        );
+        assert_eq!(
+            successor_path(Method::Delete, "/api/tag_category/4/language").as_deref(),
+            Some("/api/v2/tags/4/categories/language")
+        );
+    }
+
+    #[test]
+    fn paths_only_match_routes_of_the_same_method() {
+        assert_eq!(
+            successor_path(Method::Post, "/api/tag").as_deref(),
+            Some("/api/v2/tags")
+        );
+        assert_eq!(successor_path(Method::Get, "/api/tag"), None);
+        assert_eq!(successor_path(Method::Delete, "/api/blogs"), None);
+    }
+
+    #[test]
+    fn unknown_paths_are_not_rewritten() {
+        assert_eq!(successor_path(Method::Get, "/api/v2/tags"), None);
+        assert_eq!(successor_path(Method::Get, "/api/blog-content"), None);
+        assert_eq!(
+            successor_path(Method::Get, "/api/blog-content/7/extra"),
+            None
+        );
+        assert_eq!(successor_path(Method::Get, "/api/tags/by-project"), None);
+    }
+
+    #[test]
+    fn deprecated_v2_paths_name_the_batch_endpoint() {
+        assert_eq!(
+            superseding_path(Method::Post, "/api/v2/project-descriptions/batch"),
+            Some("/api/v2/batch")
+        );
+        assert_eq!(
+            superseding_path(Method::Post, "/api/v2/shop-item-descriptions/batch"),
+            Some("/api/v2/batch")
+        );
+        assert_eq!(
+            superseding_path(Method::Get, "/api/v2/project-descriptions/batch"),
+            None
+        );
+        assert_eq!(superseding_path(Method::Post, "/api/v2/batch"), None);
+    }
+}
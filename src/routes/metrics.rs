@@ -0,0 +1,26 @@
+//! Metrics route
+//!
+//! Serves the metrics from [`crate::metrics`] for Prometheus to scrape.
+
+use rocket::get;
+use rocket::http::{ContentType, Status};
+use rocket::State;
+use tracing::error;
+
+use crate::metrics::METRICS;
+use crate::Db;
+
+/// Returns every metric in the Prometheus text format
+///
+/// The pool gauge is refreshed on each scrape.
+#[get("/metrics")]
+pub fn metrics(db: &State<Db>) -> Result<(ContentType, Vec<u8>), Status> {
+    METRICS.set_pool_connections(db.size(), db.num_idle());
+
+    let (format_type, body) = METRICS.render().map_err(|error| {
+        error!(%error, "Failed to render metrics");
+        Status::InternalServerError
+    })?;
+    let content_type = ContentType::parse_flexible(&format_type).unwrap_or(ContentType::Plain);
+    Ok((content_type, body))
+}
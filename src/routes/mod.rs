@@ -1,5 +1,15 @@
+pub mod admin;
+pub mod batch;
 pub mod blog;
+pub mod category;
+pub mod docs;
+pub mod graphql;
+pub mod health;
+pub mod idempotency;
+pub mod legacy;
+pub mod metrics;
 pub mod project;
+pub mod rate_limit;
 pub mod shop;
 pub mod static_files;
 pub mod tag;
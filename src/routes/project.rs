@@ -6,30 +6,54 @@
 //! - Project-tag associations
 
 use rocket::http::Status;
-use rocket::serde::json::Json;
-use rocket::{get, post};
+use rocket::{delete, get, patch, post};
 use rocket_db_pools::Connection;
 use serde::{Deserialize, Serialize};
-use sqlx::Acquire;
+use utoipa::ToSchema;
 use sqlx::Either::{Left, Right};
 
-use crate::db::project_item::{DescItem, ProjectItem};
+use crate::api::admin::Admin;
+use crate::db::audit;
+use crate::db::pagination::PageParams;
+use crate::db::project_item::{DescItem, ProjectItem, ProjectItemPatch};
+use crate::db::stamps::Stamps;
 use crate::db::tag::Tag;
 use crate::Db;
-use crate::api::{ApiResponse, ApiResult, ApiError};
+use crate::api::{ApiResponse, ApiResult, ApiError, ErrorCode, FieldError};
+use crate::api::validation::{Validate, Validated, Validator};
+use crate::api::cache::{CachePolicy, Cached};
+use crate::api::concurrency::{ExpectedVersion, IfMatch, WithVersion};
 
-/// Retrieves all projects
+/// Retrieves a page of projects
+/// 
+/// # Arguments
+/// * `db` - Database connection
+/// * `q` - Optional case-insensitive search on the project title
+/// * `page` - Pagination and sorting parameters; sortable by `id` and `title`
 /// 
 /// # Returns
-/// * `ApiResult<Vec<ProjectItem>>` - List of all projects on success
-#[get("/api/projects")]
-pub async fn projects(db: Connection<Db>) -> ApiResult<Vec<ProjectItem>> {
-    match ProjectItem::get_all(db).await {
-        Ok(results) => Ok(ApiResponse::success(results)),
-        Err(_error) => {
-            Err(ApiError::new("Failed to fetch project items", Status::InternalServerError))
+/// * `Cached<Vec<ProjectItem>>` - A page of projects on success
+#[utoipa::path(
+    tag = "project",
+    params(PageParams),
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<Vec<ProjectItem>>),
+        (status = 304, description = "Not modified since the cached copy"),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[get("/api/v2/projects?<q>&<page..>")]
+pub async fn projects(mut db: Connection<Db>, q: Option<&str>, page: PageParams) -> Cached<Vec<ProjectItem>> {
+    let result = match ProjectItem::get_all(&mut db, &page, q).await {
+        Ok(results) => Ok(ApiResponse::paginated(results)),
+        Err(Right(_)) => {
+            Err(ApiError::new("Invalid sort field", Status::UnprocessableEntity).with_code(ErrorCode::InvalidSortField))
         }
-    }
+        Err(Left(error)) => {
+            Err(ApiError::from_sqlx(error, "Failed to fetch project items"))
+        }
+    };
+    Cached::new(result, CachePolicy::Public(60))
 }
 
 /// Retrieves all projects associated with a specific tag
@@ -40,21 +64,28 @@ pub async fn projects(db: Connection<Db>) -> ApiResult<Vec<ProjectItem>> {
 /// 
 /// # Returns
 /// * `ApiResult<Vec<ProjectItem>>` - List of projects with the specified tag
-#[get("/api/projects-by-tag/<tag_id>")]
+#[utoipa::path(
+    tag = "project",
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<Vec<ProjectItem>>),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[get("/api/v2/tags/<tag_id>/projects")]
 pub async fn projects_by_tag(
     db: Connection<Db>,
     tag_id: i32,
 ) -> ApiResult<Vec<ProjectItem>> {
     match ProjectItem::get_projects_by_tag(db, tag_id).await {
         Ok(results) => Ok(ApiResponse::success(results)),
-        Err(_error) => {
-            Err(ApiError::new("Failed to fetch projects by tag", Status::InternalServerError))
+        Err(error) => {
+            Err(ApiError::from_sqlx(error, "Failed to fetch projects by tag"))
         }
     }
 }
 
 /// Data structure for associating multiple tags with a project
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct ProjectToTagsData {
     /// The project to add tags to
     pub project: ProjectItem,
@@ -62,6 +93,15 @@ pub struct ProjectToTagsData {
     pub tags: Vec<Tag>,
 }
 
+impl Validate for ProjectToTagsData {
+    fn validate(&self) -> Vec<FieldError> {
+        Validator::new()
+            .nested("project", &self.project)
+            .each("tags", &self.tags)
+            .finish()
+    }
+}
+
 /// Associates multiple tags with a project
 /// 
 /// # Arguments
@@ -69,19 +109,24 @@ pub struct ProjectToTagsData {
 /// * `data` - Project and tags data
 /// 
 /// # Returns
-/// * `ApiResult<()>` - Success or failure of the operation
-#[post("/api/project/tag", data = "<data>", format = "json")]
-pub async fn add_tags_to_project(db: Connection<Db>, data: Json<ProjectToTagsData>) -> ApiResult<()> {
+/// * `ApiResult<String>` - Success message
+#[utoipa::path(
+    tag = "project",
+    request_body = ProjectToTagsData,
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<String>),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[post("/api/v2/project-tags/batch", data = "<data>", format = "json")]
+pub async fn add_tags_to_project(db: Connection<Db>, data: Validated<ProjectToTagsData>) -> ApiResult<String> {
     let project_item = &data.project;
     let tags = data.tags.iter().collect();
 
     match project_item.add_tag(db, tags).await {
-        Ok(_result) => Ok(ApiResponse::success(())),
-        Err(_error) => {
-            Err(ApiError::new(
-                "Failed to add tags to project",
-                Status::InternalServerError
-            ))
+        Ok(_result) => Ok(ApiResponse::success("Tags added to project successfully".to_string())),
+        Err(error) => {
+            Err(ApiError::from_sqlx(error, "Failed to add tags to project"))
         }
     }
 }
@@ -93,21 +138,27 @@ pub async fn add_tags_to_project(db: Connection<Db>, data: Json<ProjectToTagsDat
 /// * `id` - Project ID
 /// 
 /// # Returns
-/// * `ApiResult<Vec<DescItem>>` - List of project descriptions
-#[get("/api/project_descs/<id>")]
+/// * `Cached<Vec<DescItem>>` - List of project descriptions
+#[utoipa::path(
+    tag = "project",
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<Vec<DescItem>>),
+        (status = 304, description = "Not modified since the cached copy"),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[get("/api/v2/projects/<id>/descriptions")]
 pub async fn project_descs(
     db: Connection<Db>,
     id: i32,
-) -> ApiResult<Vec<DescItem>> {
-    match DescItem::get_all_from_project(db, id).await {
+) -> Cached<Vec<DescItem>> {
+    let result = match DescItem::get_all_from_project(db, id).await {
         Ok(results) => Ok(ApiResponse::success(results)),
-        Err(_error) => {
-            Err(ApiError::new(
-                "Failed to fetch project descriptions",
-                Status::InternalServerError
-            ))
+        Err(error) => {
+            Err(ApiError::from_sqlx(error, "Failed to fetch project descriptions"))
         }
-    }
+    };
+    Cached::new(result, CachePolicy::Public(60))
 }
 
 /// Creates a new project
@@ -118,15 +169,25 @@ pub async fn project_descs(
 /// 
 /// # Returns
 /// * `ApiResult<ProjectItem>` - Created project with assigned ID
-#[post("/api/project", data = "<project_item>", format = "json")]
+#[utoipa::path(
+    tag = "project",
+    request_body = ProjectItem,
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<ProjectItem>),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[post("/api/v2/projects", data = "<project_item>", format = "json")]
 pub async fn create_project_item(
     db: Connection<Db>,
-    project_item: Json<ProjectItem>,
+    project_item: Validated<ProjectItem>,
 ) -> ApiResult<ProjectItem> {
     let project_item_deser = ProjectItem {
         id: None,
         title: project_item.title.clone(),
         thumbnail_img_link: project_item.thumbnail_img_link.clone(),
+        version: None,
+        stamps: Stamps::default(),
         desc: project_item.desc.clone(),
     };
 
@@ -140,15 +201,86 @@ pub async fn create_project_item(
                 ))
             }
         }
-        Err(_error) => {
-            Err(ApiError::new(
-                "Failed to create project",
-                Status::InternalServerError
-            ))
+        Err(error) => {
+            Err(ApiError::from_sqlx(error, "Failed to create project"))
         }
     }
 }
 
+/// Updates a project
+/// 
+/// # Arguments
+/// * `db` - Database connection
+/// * `id` - Project ID
+/// * `if_match` - Version the update is based on, sent as `If-Match: "v<version>"`
+/// * `patch` - Fields to change, optionally with the version the update is based on
+/// 
+/// # Returns
+/// * `WithVersion<ProjectItem>` - The updated project, with its new version as the ETag
+/// * `ApiError` - 428 without a version, 412 or 409 if the version is stale
+#[utoipa::path(
+    tag = "project",
+    request_body = ProjectItemPatch,
+    params(("If-Match" = Option<String>, Header, description = "Version the update is based on, e.g. \"v3\", or * for any")),
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<ProjectItem>,
+            headers(("ETag" = String, description = "The new version, e.g. \"v4\""))),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[patch("/api/v2/projects/<id>", data = "<patch>", format = "json")]
+pub async fn update_project_item(
+    db: Connection<Db>,
+    id: i32,
+    if_match: IfMatch,
+    patch: Validated<ProjectItemPatch>,
+) -> Result<WithVersion<ProjectItem>, ApiError> {
+    let expected = ExpectedVersion::resolve(&if_match, patch.version)?;
+
+    match ProjectItem::update(db, id, &patch, expected.version).await {
+        Ok(result) => Ok(WithVersion(result)),
+        Err(Right(current_version)) => Err(expected.mismatch(current_version)),
+        Err(Left(sqlx::Error::RowNotFound)) => Err(ApiError::new(
+            "Project not found",
+            Status::NotFound
+        )),
+        Err(Left(error)) => Err(ApiError::from_sqlx(error, "Failed to update project")),
+    }
+}
+
+/// Soft deletes a project
+/// 
+/// Requires the admin key. It is hidden from every list and lookup until an
+/// admin restores or purges it.
+/// 
+/// # Arguments
+/// * `db` - Database connection
+/// * `id` - Project ID
+/// 
+/// # Returns
+/// - `ApiResult<String>`: Success message
+/// - `ApiError`: If the project does not exist or the delete fails
+#[utoipa::path(
+    tag = "project",
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<String>),
+        (status = 401, description = "Missing or wrong admin key", body = ApiError),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[delete("/api/v2/projects/<id>")]
+pub async fn delete_project_item(_admin: Admin, db: Connection<Db>, id: i32) -> ApiResult<String> {
+    match ProjectItem::delete(db, id).await {
+        Ok(true) => Ok(ApiResponse::success("Project deleted successfully".to_string())),
+        Ok(false) => Err(ApiError::new(
+            "Project not found",
+            Status::NotFound
+        )),
+        Err(error) => Err(ApiError::from_sqlx(error, "Failed to delete project")),
+    }
+}
+
+
 /// Creates a new project description
 /// 
 /// # Arguments
@@ -157,31 +289,37 @@ pub async fn create_project_item(
 /// 
 /// # Returns
 /// * `ApiResult<DescItem>` - Created description with assigned ID
-#[post("/api/project_desc", data = "<project_desc>", format = "json")]
+#[utoipa::path(
+    tag = "project",
+    request_body = DescItem,
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<DescItem>),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[post("/api/v2/project-descriptions", data = "<project_desc>", format = "json")]
 pub async fn create_project_desc(
     db: Connection<Db>,
-    project_desc: Json<DescItem>,
+    project_desc: Validated<DescItem>,
 ) -> ApiResult<DescItem> {
     let project_desc_deser = DescItem {
         id: None,
         project_id: project_desc.project_id,
         content: project_desc.content.clone(),
+        stamps: Stamps::default(),
     };
 
     let result = match project_desc_deser.add(db).await {
         Ok(query_result) => query_result,
         Err(error) => match error {
-            Left(_) => {
+            Left(error) => {
+                return Err(ApiError::from_sqlx(error, "Failed to create project description"));
+            }
+            Right(_) => {
                 return Err(ApiError::new(
                     "Failed to create project description: Invalid input",
                     Status::BadRequest
-                ));
-            }
-            Right(_error) => {
-                return Err(ApiError::new(
-                    "Failed to create project description",
-                    Status::InternalServerError
-                ));
+                ).with_field_errors(vec![FieldError::new("project_id", "is required")]));
             }
         },
     };
@@ -197,54 +335,58 @@ pub async fn create_project_desc(
 
 /// Creates multiple project descriptions in a single transaction
 /// 
+/// Deprecated in favour of `POST /api/v2/batch` with `"atomic": true` and
+/// one `create_project_description` operation per description, which
+/// likewise creates all of them or none. A batch takes at most 100
+/// operations.
+/// 
 /// # Arguments
 /// * `db` - Database connection
 /// * `project_descs` - List of project descriptions to create
 /// 
 /// # Returns
-/// * `ApiResult<()>` - Success or failure of the operation
-#[post("/api/project_desc_many", data = "<project_descs>", format = "json")]
+/// * `ApiResult<String>` - Success message
+#[utoipa::path(
+    tag = "project",
+    request_body = Vec<DescItem>,
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<String>),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[deprecated = "use POST /api/v2/batch"]
+#[post("/api/v2/project-descriptions/batch", data = "<project_descs>", format = "json")]
 pub async fn create_project_desc_many(
     mut db: Connection<Db>,
-    project_descs: Json<Vec<DescItem>>,
-) -> ApiResult<()> {
-    let mut tx = match (*db).begin().await {
+    project_descs: Validated<Vec<DescItem>>,
+) -> ApiResult<String> {
+    let mut tx = match audit::begin(&mut db).await {
         Ok(tx) => tx,
-        Err(_error) => {
-            return Err(ApiError::new(
-                "Failed to create project descriptions",
-                Status::InternalServerError
-            ));
+        Err(error) => {
+            return Err(ApiError::from_sqlx(error, "Failed to create project descriptions"));
         }
     };
 
     for project_desc in project_descs.iter() {
         let result = project_desc.add_tx(&mut tx).await;
         match result {
-            Err(error) => {
-                if error.is_left() {
-                    return Err(ApiError::new(
-                        "Failed to create project descriptions: Invalid input",
-                        Status::BadRequest
-                    ));
-                } else {
-                    return Err(ApiError::new(
-                        "Failed to create project descriptions",
-                        Status::InternalServerError
-                    ));
-                }
+            Err(Left(error)) => {
+                return Err(ApiError::from_sqlx(error, "Failed to create project descriptions"));
+            }
+            Err(Right(_)) => {
+                return Err(ApiError::new(
+                    "Failed to create project descriptions: Invalid input",
+                    Status::BadRequest
+                ).with_field_errors(vec![FieldError::new("project_id", "is required")]));
             }
             _ => continue,
         }
     }
 
     match tx.commit().await {
-        Ok(_) => Ok(ApiResponse::success(())),
-        Err(_error) => {
-            Err(ApiError::new(
-                "Failed to create project descriptions",
-                Status::InternalServerError
-            ))
+        Ok(_) => Ok(ApiResponse::success("Project descriptions created successfully".to_string())),
+        Err(error) => {
+            Err(ApiError::from_sqlx(error, "Failed to create project descriptions"))
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,552 @@
+//! Rate limiting
+//!
+//! Every API request takes a token from the bucket of its client IP and route
+//! group. Requests sending credentials also take one from the bucket of their
+//! user in that group, so a user is limited whatever IP they come from, while
+//! anonymous requests are limited by IP alone. Buckets refill continuously, so a client may burst up to the
+//! group's capacity and then continue at its refill rate. Requests over the
+//! limit are not passed to their handler: they are rewritten to
+//! [`rate_limited`], which answers with 429 and `Retry-After`.
+//!
+//! Routes can take tokens from further buckets through [`RateLimiter`], such
+//! as the per-username `login` bucket. Buckets live in memory by default, or
+//! in Postgres when several instances serve the same clients.
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::uri::Origin;
+use rocket::http::{Header, Method, Status};
+use rocket::request::{self, FromRequest, Request};
+use rocket::response::{self, Responder};
+use rocket::{get, Data};
+use rocket_db_pools::Database;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+use tracing::{error, warn};
+
+use crate::api::admin;
+use crate::api::{ApiError, ErrorCode};
+use crate::db::rate_limit;
+use crate::db::user::LockoutPolicy;
+use crate::telemetry::request_span;
+use crate::Db;
+
+/// Internal path that requests over their limit are rewritten to
+const RATE_LIMITED_PATH: &str = "/api/v2/rate-limited";
+
+/// Routes that create accounts or check passwords, limited the most
+const AUTH_ROUTES: &[(Method, &str)] = &[(Method::Post, "/api/v2/users")];
+
+/// Routes that only read data although they are sent with `POST`
+const READ_ROUTES: &[(Method, &str)] = &[(Method::Post, "/api/v2/graphql")];
+
+/// Prefix of the bucket keys of authenticated users, which IPs never start with
+const USER_KEY_PREFIX: &str = "user:";
+
+/// In-memory buckets kept before idle ones are dropped
+const MAX_MEMORY_BUCKETS: usize = 10_000;
+
+/// Where buckets are kept
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// Per instance, lost on restart
+    #[default]
+    Memory,
+    /// Shared by every instance using the database
+    Postgres,
+}
+
+/// Size and refill rate of a bucket
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Limit {
+    /// Requests that may be sent at once
+    pub burst: u32,
+    /// Requests allowed per minute once the burst is used up
+    pub per_minute: u32,
+}
+
+impl Limit {
+    fn refill_per_second(&self) -> f64 {
+        f64::from(self.per_minute) / 60.0
+    }
+}
+
+/// Rate limits as configured in the `rate_limit` table
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    pub backend: Backend,
+    /// `GET` requests and GraphQL queries
+    pub read: Limit,
+    /// Requests that change data
+    pub write: Limit,
+    /// Account creation, per IP
+    pub auth: Limit,
+    /// Login attempts per username, whatever IP they come from
+    pub login: Limit,
+    pub lockout: LockoutPolicy,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            backend: Backend::Memory,
+            read: Limit {
+                burst: 120,
+                per_minute: 120,
+            },
+            write: Limit {
+                burst: 30,
+                per_minute: 30,
+            },
+            auth: Limit {
+                burst: 5,
+                per_minute: 5,
+            },
+            login: Limit {
+                burst: 10,
+                per_minute: 5,
+            },
+            lockout: LockoutPolicy::default(),
+        }
+    }
+}
+
+/// Route groups with their own limits
+#[derive(Debug, Clone, Copy)]
+pub enum Group {
+    Read,
+    Write,
+    Auth,
+    Login,
+}
+
+impl Group {
+    fn name(self) -> &'static str {
+        match self {
+            Group::Read => "read",
+            Group::Write => "write",
+            Group::Auth => "auth",
+            Group::Login => "login",
+        }
+    }
+
+    /// The group limiting a request by client IP, if the request is limited at all
+    fn for_request(method: Method, path: &str) -> Option<Self> {
+        if !path.starts_with("/api/") {
+            return None;
+        }
+        if AUTH_ROUTES
+            .iter()
+            .any(|(route_method, route_path)| *route_method == method && *route_path == path)
+        {
+            return Some(Group::Auth);
+        }
+        if READ_ROUTES
+            .iter()
+            .any(|(route_method, route_path)| *route_method == method && *route_path == path)
+        {
+            return Some(Group::Read);
+        }
+
+        match method {
+            Method::Get | Method::Head => Some(Group::Read),
+            Method::Options => None,
+            _ => Some(Group::Write),
+        }
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Token buckets for every client, managed as state
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn lockout(&self) -> &LockoutPolicy {
+        &self.config.lockout
+    }
+
+    fn limit(&self, group: Group) -> Limit {
+        match group {
+            Group::Read => self.config.read,
+            Group::Write => self.config.write,
+            Group::Auth => self.config.auth,
+            Group::Login => self.config.login,
+        }
+    }
+
+    /// Takes a token from the bucket of `key` in `group`
+    ///
+    /// Limits are not enforced if the Postgres backend fails, so that an
+    /// unavailable database does not also block the requests that don't need it.
+    ///
+    /// # Arguments
+    /// * `pool` - Database for the Postgres backend
+    /// * `key` - Who the request is counted for, e.g. the client IP
+    ///
+    /// # Returns
+    /// * `Err(seconds)` - How long to wait before the next token is available
+    pub async fn check(&self, pool: Option<&PgPool>, group: Group, key: &str) -> Result<(), u64> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+        let limit = self.limit(group);
+        let key = format!("{}:{}", group.name(), key);
+        let refill = limit.refill_per_second();
+
+        let tokens = match (self.config.backend, pool) {
+            (Backend::Postgres, Some(pool)) => {
+                match rate_limit::take(pool, &key, f64::from(limit.burst), refill).await {
+                    Ok(bucket) if bucket.allowed => return Ok(()),
+                    Ok(bucket) => bucket.tokens,
+                    Err(error) => {
+                        error!(%error, key = %key, "Failed to check rate limit");
+                        return Ok(());
+                    }
+                }
+            }
+            _ => match self.take_in_memory(&key, limit, Instant::now()) {
+                Ok(()) => return Ok(()),
+                Err(tokens) => tokens,
+            },
+        };
+
+        if refill <= 0.0 {
+            return Err(u64::MAX);
+        }
+        Err(((1.0 - tokens) / refill).ceil().max(1.0) as u64)
+    }
+
+    /// Takes a token from the client IP's bucket in `group`, and from the
+    /// user's bucket if the request is authenticated
+    ///
+    /// # Returns
+    /// * `Err(seconds)` - How long to wait before both buckets have a token
+    async fn check_request(
+        &self,
+        pool: Option<&PgPool>,
+        group: Group,
+        ip: &str,
+        user: Option<&str>,
+    ) -> Result<(), u64> {
+        self.check(pool, group, ip).await?;
+        match user {
+            Some(user) => {
+                self.check(pool, group, &format!("{}{}", USER_KEY_PREFIX, user))
+                    .await
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Takes a token from an in-memory bucket at `now`, returning the tokens
+    /// left if there was none
+    fn take_in_memory(&self, key: &str, limit: Limit, now: Instant) -> Result<(), f64> {
+        let capacity = f64::from(limit.burst);
+        let refill = limit.refill_per_second();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        if buckets.len() >= MAX_MEMORY_BUCKETS {
+            // Buckets that have refilled completely behave like new ones
+            buckets.retain(|_, bucket| {
+                bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * refill < capacity
+            });
+        }
+
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+        bucket.tokens =
+            (bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * refill).min(capacity);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(bucket.tokens)
+        }
+    }
+
+    /// Drops stored buckets nobody used for a day
+    async fn prune(&self, pool: &PgPool) {
+        if let Err(error) = rate_limit::prune(pool).await {
+            error!(%error, "Failed to prune rate limit buckets");
+        }
+    }
+}
+
+/// Seconds a limited request has to wait, kept in its local cache
+#[derive(Default)]
+struct Limited(Option<u64>);
+
+/// Fairing that enforces the per-IP and per-user limits of every API request
+pub struct RateLimit;
+
+#[rocket::async_trait]
+impl Fairing for RateLimit {
+    fn info(&self) -> Info {
+        Info {
+            name: "Rate limiting",
+            kind: Kind::Request,
+        }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _data: &mut Data<'_>) {
+        let Some(limiter) = req.rocket().state::<RateLimiter>() else {
+            return;
+        };
+        let Some(group) = Group::for_request(req.method(), req.uri().path().as_str()) else {
+            return;
+        };
+        let Some(ip) = req.client_ip() else {
+            return;
+        };
+
+        let pool = Db::fetch(req.rocket()).map(|db| &**db);
+        if limiter.config.backend == Backend::Postgres && prune_due() {
+            if let Some(pool) = pool {
+                limiter.prune(pool).await;
+            }
+        }
+
+        let user = admin::identity(req);
+        if let Err(retry_after) = limiter
+            .check_request(pool, group, &ip.to_string(), user)
+            .await
+        {
+            warn!(
+                parent: &request_span(req),
+                %ip,
+                user,
+                group = group.name(),
+                retry_after,
+                "Rate limit exceeded"
+            );
+            req.local_cache(|| Limited(Some(retry_after)));
+            if let Ok(origin) = Origin::parse(RATE_LIMITED_PATH) {
+                req.set_method(Method::Get);
+                req.set_uri(origin);
+            }
+        }
+    }
+}
+
+/// Whether this request should also prune the stored buckets, once every thousand requests
+fn prune_due() -> bool {
+    static REQUESTS: AtomicU64 = AtomicU64::new(0);
+    REQUESTS.fetch_add(1, Ordering::Relaxed).is_multiple_of(1000)
+}
+
+/// An error sent with a `Retry-After` header
+pub struct Throttled {
+    pub error: ApiError,
+    pub retry_after: Option<u64>,
+}
+
+impl Throttled {
+    /// 429 for a request over its rate limit
+    pub fn too_many_requests(retry_after: u64) -> Self {
+        Self {
+            error: ApiError::new("Too many requests, please retry later", Status::TooManyRequests)
+                .with_code(ErrorCode::RateLimited),
+            retry_after: Some(retry_after),
+        }
+    }
+}
+
+impl From<ApiError> for Throttled {
+    fn from(error: ApiError) -> Self {
+        Self {
+            error,
+            retry_after: None,
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for Throttled {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let mut response = self.error.respond_to(req)?;
+        if let Some(retry_after) = self.retry_after {
+            response.set_header(Header::new("Retry-After", retry_after.to_string()));
+        }
+        Ok(response)
+    }
+}
+
+/// Request guard giving access to the fairing's decision
+pub struct RateLimitState(Option<u64>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RateLimitState {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        request::Outcome::Success(RateLimitState(req.local_cache(Limited::default).0))
+    }
+}
+
+/// Answers a request that was over its rate limit
+///
+/// Only reached through the rewrite done by [`RateLimit`].
+#[get("/api/v2/rate-limited")]
+pub fn rate_limited(state: RateLimitState) -> Throttled {
+    match state.0 {
+        Some(retry_after) => Throttled::too_many_requests(retry_after),
+        None => ApiError::new("Resource not found", Status::NotFound).into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const LIMIT: Limit = Limit {
+        burst: 2,
+        per_minute: 6,
+    };
+
+    #[test]
+    fn burst_is_available_at_once() {
+        let limiter = RateLimiter::new(RateLimitConfig::default());
+        let now = Instant::now();
+
+        assert_eq!(limiter.take_in_memory("a", LIMIT, now), Ok(()));
+        assert_eq!(limiter.take_in_memory("a", LIMIT, now), Ok(()));
+        assert_eq!(limiter.take_in_memory("a", LIMIT, now), Err(0.0));
+    }
+
+    #[test]
+    fn buckets_refill_at_the_configured_rate() {
+        let limiter = RateLimiter::new(RateLimitConfig::default());
+        let start = Instant::now();
+        for _ in 0..LIMIT.burst {
+            limiter.take_in_memory("a", LIMIT, start).unwrap();
+        }
+
+        // Six per minute is one token every ten seconds
+        let tokens = limiter
+            .take_in_memory("a", LIMIT, start + Duration::from_secs(5))
+            .unwrap_err();
+        assert!((tokens - 0.5).abs() < 1e-9);
+        assert_eq!(
+            limiter.take_in_memory("a", LIMIT, start + Duration::from_secs(10)),
+            Ok(())
+        );
+        assert!(limiter
+            .take_in_memory("a", LIMIT, start + Duration::from_secs(10))
+            .is_err());
+    }
+
+    #[test]
+    fn refill_is_capped_at_the_burst() {
+        let limiter = RateLimiter::new(RateLimitConfig::default());
+        let start = Instant::now();
+        limiter.take_in_memory("a", LIMIT, start).unwrap();
+
+        let later = start + Duration::from_secs(3600);
+        assert_eq!(limiter.take_in_memory("a", LIMIT, later), Ok(()));
+        assert_eq!(limiter.take_in_memory("a", LIMIT, later), Ok(()));
+        assert!(limiter.take_in_memory("a", LIMIT, later).is_err());
+    }
+
+    #[test]
+    fn buckets_are_kept_per_key() {
+        let limiter = RateLimiter::new(RateLimitConfig::default());
+        let now = Instant::now();
+        for _ in 0..LIMIT.burst {
+            limiter.take_in_memory("a", LIMIT, now).unwrap();
+        }
+
+        assert!(limiter.take_in_memory("a", LIMIT, now).is_err());
+        assert_eq!(limiter.take_in_memory("b", LIMIT, now), Ok(()));
+    }
+
+    #[rocket::async_test]
+    async fn limited_requests_are_told_when_to_retry() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            write: LIMIT,
+            ..RateLimitConfig::default()
+        });
+        for _ in 0..LIMIT.burst {
+            assert_eq!(limiter.check(None, Group::Write, "127.0.0.1").await, Ok(()));
+        }
+
+        assert_eq!(
+            limiter.check(None, Group::Write, "127.0.0.1").await,
+            Err(10)
+        );
+        assert_eq!(limiter.check(None, Group::Read, "127.0.0.1").await, Ok(()));
+    }
+
+    #[rocket::async_test]
+    async fn users_are_limited_whatever_ip_they_come_from() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            write: LIMIT,
+            ..RateLimitConfig::default()
+        });
+        let admin = Some("admin");
+        assert_eq!(
+            limiter
+                .check_request(None, Group::Write, "10.0.0.1", admin)
+                .await,
+            Ok(())
+        );
+        assert_eq!(
+            limiter
+                .check_request(None, Group::Write, "10.0.0.2", admin)
+                .await,
+            Ok(())
+        );
+
+        assert_eq!(
+            limiter
+                .check_request(None, Group::Write, "10.0.0.3", admin)
+                .await,
+            Err(10)
+        );
+        // Anonymous requests from the same IP are not held back by the user's bucket
+        assert_eq!(
+            limiter
+                .check_request(None, Group::Write, "10.0.0.3", None)
+                .await,
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn requests_are_grouped_by_method_and_path() {
+        let group = |method, path| Group::for_request(method, path).map(Group::name);
+
+        assert_eq!(group(Method::Get, "/api/v2/tags"), Some("read"));
+        assert_eq!(group(Method::Post, "/api/v2/tags"), Some("write"));
+        assert_eq!(group(Method::Delete, "/api/v2/tags/1"), Some("write"));
+        assert_eq!(group(Method::Post, "/api/v2/users"), Some("auth"));
+        assert_eq!(group(Method::Get, "/api/v2/users"), Some("read"));
+        assert_eq!(group(Method::Post, "/api/v2/graphql"), Some("read"));
+        assert_eq!(group(Method::Options, "/api/v2/tags"), None);
+        assert_eq!(group(Method::Get, "/metrics"), None);
+    }
+}
@@ -6,28 +6,84 @@
 //! - Shop item description management
 
 use rocket::http::Status;
-use rocket::serde::json::Json;
-use rocket::{get, post};
+use rocket::{delete, get, patch, post};
 use rocket_db_pools::Connection;
 
-use crate::db::shop_item::{ShopImage, ShopItem, ShopItemDesc, ShopItemDescMany};
+use crate::api::admin::Admin;
+use crate::db::audit;
+use crate::db::pagination::PageParams;
+use crate::db::shop_item::{ShopImage, ShopItem, ShopItemDesc, ShopItemDescMany, ShopItemPatch};
+use crate::db::stamps::Stamps;
 use crate::Db;
-use crate::api::{ApiResponse, ApiResult, ApiError};
+use crate::api::{ApiResponse, ApiResult, ApiError, ErrorCode, FieldError};
+use crate::api::validation::Validated;
+use crate::api::cache::{CachePolicy, Cached};
+use crate::api::concurrency::{ExpectedVersion, IfMatch, WithVersion};
 use sqlx::Either::{Left, Right};
-use sqlx::Acquire;
+use tracing::warn;
 
-/// Retrieves all shop items
+/// Retrieves a page of shop items
+/// 
+/// # Arguments
+/// * `db` - Database connection
+/// * `q` - Optional case-insensitive search on the item name
+/// * `min_price` - Optional lower bound on the price
+/// * `max_price` - Optional upper bound on the price
+/// * `page` - Pagination and sorting parameters; sortable by `id`, `name` and `price`
+/// 
+/// # Returns
+/// * `Cached<Vec<ShopItem>>` - A page of shop items on success
+#[utoipa::path(
+    tag = "shop",
+    params(PageParams),
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<Vec<ShopItem>>),
+        (status = 304, description = "Not modified since the cached copy"),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[get("/api/v2/shop-items?<q>&<min_price>&<max_price>&<page..>")]
+pub async fn shop_items(
+    mut db: Connection<Db>,
+    q: Option<&str>,
+    min_price: Option<f32>,
+    max_price: Option<f32>,
+    page: PageParams,
+) -> Cached<Vec<ShopItem>> {
+    let result = match ShopItem::get_all(&mut db, &page, q, min_price, max_price).await {
+        Ok(results) => Ok(ApiResponse::paginated(results)),
+        Err(Right(_)) => Err(ApiError::new(
+            "Invalid sort field",
+            Status::UnprocessableEntity
+        ).with_code(ErrorCode::InvalidSortField)),
+        Err(Left(error)) => Err(ApiError::from_sqlx(error, "Failed to fetch shop items")),
+    };
+    Cached::new(result, CachePolicy::Public(30))
+}
+
+/// Retrieves all shop items associated with a specific tag
+/// 
+/// # Arguments
+/// * `db` - Database connection
+/// * `tag_id` - ID of the tag to filter shop items by
 /// 
 /// # Returns
-/// * `ApiResult<Vec<ShopItem>>` - List of all shop items on success
-#[get("/api/shopitems")]
-pub async fn shop_items(db: Connection<Db>) -> ApiResult<Vec<ShopItem>> {
-    match ShopItem::get_all(db).await {
+/// * `ApiResult<Vec<ShopItem>>` - List of shop items with the specified tag
+#[utoipa::path(
+    tag = "shop",
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<Vec<ShopItem>>),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[get("/api/v2/tags/<tag_id>/shop-items")]
+pub async fn shop_items_by_tag(
+    db: Connection<Db>,
+    tag_id: i32,
+) -> ApiResult<Vec<ShopItem>> {
+    match ShopItem::get_shop_items_by_tag(db, tag_id).await {
         Ok(results) => Ok(ApiResponse::success(results)),
-        Err(_) => Err(ApiError::new(
-            "Failed to fetch shop items",
-            Status::InternalServerError
-        )),
+        Err(error) => Err(ApiError::from_sqlx(error, "Failed to fetch shop items by tag")),
     }
 }
 
@@ -39,16 +95,26 @@ pub async fn shop_items(db: Connection<Db>) -> ApiResult<Vec<ShopItem>> {
 /// 
 /// # Returns
 /// * `ApiResult<ShopItem>` - Created shop item with assigned ID
-#[post("/api/shopitem", data = "<shop_item>", format = "json")]
+#[utoipa::path(
+    tag = "shop",
+    request_body = ShopItem,
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<ShopItem>),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[post("/api/v2/shop-items", data = "<shop_item>", format = "json")]
 pub async fn create_shop_item(
     db: Connection<Db>,
-    shop_item: Json<ShopItem>,
+    shop_item: Validated<ShopItem>,
 ) -> ApiResult<ShopItem> {
     let shop_item_deser = ShopItem {
         id: None,
         iname: shop_item.iname.clone(),
         img_link: shop_item.img_link.clone(),
         price: shop_item.price,
+        version: None,
+        stamps: Stamps::default(),
     };
     
     match shop_item_deser.add(db).await {
@@ -62,10 +128,80 @@ pub async fn create_shop_item(
                 ))
             }
         }
-        Err(_) => Err(ApiError::new(
-            "Failed to create shop item",
-            Status::InternalServerError
+        Err(error) => Err(ApiError::from_sqlx(error, "Failed to create shop item")),
+    }
+}
+
+/// Updates a shop item
+/// 
+/// # Arguments
+/// * `db` - Database connection
+/// * `id` - Shop item ID
+/// * `if_match` - Version the update is based on, sent as `If-Match: "v<version>"`
+/// * `patch` - Fields to change, optionally with the version the update is based on
+/// 
+/// # Returns
+/// * `WithVersion<ShopItem>` - The updated shop item, with its new version as the ETag
+/// * `ApiError` - 428 without a version, 412 or 409 if the version is stale
+#[utoipa::path(
+    tag = "shop",
+    request_body = ShopItemPatch,
+    params(("If-Match" = Option<String>, Header, description = "Version the update is based on, e.g. \"v3\", or * for any")),
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<ShopItem>,
+            headers(("ETag" = String, description = "The new version, e.g. \"v4\""))),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[patch("/api/v2/shop-items/<id>", data = "<patch>", format = "json")]
+pub async fn update_shop_item(
+    db: Connection<Db>,
+    id: i32,
+    if_match: IfMatch,
+    patch: Validated<ShopItemPatch>,
+) -> Result<WithVersion<ShopItem>, ApiError> {
+    let expected = ExpectedVersion::resolve(&if_match, patch.version)?;
+
+    match ShopItem::update(db, id, &patch, expected.version).await {
+        Ok(result) => Ok(WithVersion(result)),
+        Err(Right(current_version)) => Err(expected.mismatch(current_version)),
+        Err(Left(sqlx::Error::RowNotFound)) => Err(ApiError::new(
+            "Shop item not found",
+            Status::NotFound
         )),
+        Err(Left(error)) => Err(ApiError::from_sqlx(error, "Failed to update shop item")),
+    }
+}
+
+/// Soft deletes a shop item
+/// 
+/// Requires the admin key. It is hidden from every list and lookup until an
+/// admin restores or purges it.
+/// 
+/// # Arguments
+/// * `db` - Database connection
+/// * `id` - Shop item ID
+/// 
+/// # Returns
+/// - `ApiResult<String>`: Success message
+/// - `ApiError`: If the shop item does not exist or the delete fails
+#[utoipa::path(
+    tag = "shop",
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<String>),
+        (status = 401, description = "Missing or wrong admin key", body = ApiError),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[delete("/api/v2/shop-items/<id>")]
+pub async fn delete_shop_item(_admin: Admin, db: Connection<Db>, id: i32) -> ApiResult<String> {
+    match ShopItem::delete(db, id).await {
+        Ok(true) => Ok(ApiResponse::success("Shop item deleted successfully".to_string())),
+        Ok(false) => Err(ApiError::new(
+            "Shop item not found",
+            Status::NotFound
+        )),
+        Err(error) => Err(ApiError::from_sqlx(error, "Failed to delete shop item")),
     }
 }
 
@@ -76,19 +212,25 @@ pub async fn create_shop_item(
 /// * `id` - Shop item ID
 /// 
 /// # Returns
-/// * `ApiResult<Vec<ShopImage>>` - List of shop item images
-#[get("/api/shopitemimages/<id>")]
+/// * `Cached<Vec<ShopImage>>` - List of shop item images
+#[utoipa::path(
+    tag = "shop",
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<Vec<ShopImage>>),
+        (status = 304, description = "Not modified since the cached copy"),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[get("/api/v2/shop-items/<id>/images")]
 pub async fn shop_item_images(
     db: Connection<Db>,
     id: i32,
-) -> ApiResult<Vec<ShopImage>> {
-    match ShopImage::get_all_from_shop_item(db, id).await {
+) -> Cached<Vec<ShopImage>> {
+    let result = match ShopImage::get_all_from_shop_item(db, id).await {
         Ok(results) => Ok(ApiResponse::success(results)),
-        Err(_) => Err(ApiError::new(
-            "Failed to fetch shop item images",
-            Status::InternalServerError
-        )),
-    }
+        Err(error) => Err(ApiError::from_sqlx(error, "Failed to fetch shop item images")),
+    };
+    Cached::new(result, CachePolicy::Public(30))
 }
 
 /// Creates a new shop item image
@@ -99,32 +241,38 @@ pub async fn shop_item_images(
 /// 
 /// # Returns
 /// * `ApiResult<ShopImage>` - Created shop item image with assigned ID
-#[post("/api/shopitemimage", data = "<shop_item_image>", format = "json")]
+#[utoipa::path(
+    tag = "shop",
+    request_body = ShopImage,
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<ShopImage>),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[post("/api/v2/shop-item-images", data = "<shop_item_image>", format = "json")]
 pub async fn create_shop_item_image(
     db: Connection<Db>,
-    shop_item_image: Json<ShopImage>,
+    shop_item_image: Validated<ShopImage>,
 ) -> ApiResult<ShopImage> {
     let shop_item_desc_deser = ShopImage {
         id: None,
         shop_item_id: shop_item_image.shop_item_id,
         tooltip: shop_item_image.tooltip.clone(),
         img_link: shop_item_image.img_link.clone(),
+        stamps: Stamps::default(),
     };
     
     let result = match shop_item_desc_deser.add(db).await {
         Ok(query_result) => query_result,
         Err(error) => match error {
-            Left(_) => {
-                return Err(ApiError::new(
-                    "Failed to create shop item image",
-                    Status::InternalServerError
-                ));
+            Left(error) => {
+                return Err(ApiError::from_sqlx(error, "Failed to create shop item image"));
             }
             Right(_) => {
                 return Err(ApiError::new(
                     "Invalid shop_item_id",
                     Status::BadRequest
-                ));
+                ).with_field_errors(vec![FieldError::new("shop_item_id", "is required")]));
             }
         },
     };
@@ -145,19 +293,25 @@ pub async fn create_shop_item_image(
 /// * `id` - Shop item ID
 /// 
 /// # Returns
-/// * `ApiResult<Vec<ShopItemDesc>>` - List of shop item descriptions
-#[get("/api/shopitemdescs/<id>")]
+/// * `Cached<Vec<ShopItemDesc>>` - List of shop item descriptions
+#[utoipa::path(
+    tag = "shop",
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<Vec<ShopItemDesc>>),
+        (status = 304, description = "Not modified since the cached copy"),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[get("/api/v2/shop-items/<id>/descriptions")]
 pub async fn shop_item_descs(
     db: Connection<Db>,
     id: i32,
-) -> ApiResult<Vec<ShopItemDesc>> {
-    match ShopItemDesc::get_all_from_shop_item(db, id).await {
+) -> Cached<Vec<ShopItemDesc>> {
+    let result = match ShopItemDesc::get_all_from_shop_item(db, id).await {
         Ok(results) => Ok(ApiResponse::success(results)),
-        Err(_) => Err(ApiError::new(
-            "Failed to fetch shop item descriptions",
-            Status::InternalServerError
-        )),
-    }
+        Err(error) => Err(ApiError::from_sqlx(error, "Failed to fetch shop item descriptions")),
+    };
+    Cached::new(result, CachePolicy::Public(30))
 }
 
 /// Creates a new shop item description
@@ -168,31 +322,37 @@ pub async fn shop_item_descs(
 /// 
 /// # Returns
 /// * `ApiResult<ShopItemDesc>` - Created shop item description with assigned ID
-#[post("/api/shopitemdesc", data = "<shop_item_desc>", format = "json")]
+#[utoipa::path(
+    tag = "shop",
+    request_body = ShopItemDesc,
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<ShopItemDesc>),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[post("/api/v2/shop-item-descriptions", data = "<shop_item_desc>", format = "json")]
 pub async fn create_shop_item_desc(
     db: Connection<Db>,
-    shop_item_desc: Json<ShopItemDesc>,
+    shop_item_desc: Validated<ShopItemDesc>,
 ) -> ApiResult<ShopItemDesc> {
     let shop_item_desc_deser = ShopItemDesc {
         id: None,
         shop_item_id: shop_item_desc.shop_item_id,
         content: shop_item_desc.content.clone(),
+        stamps: Stamps::default(),
     };
     
     let result = match shop_item_desc_deser.add(db).await {
         Ok(query_result) => query_result,
         Err(error) => match error {
-            Left(_) => {
-                return Err(ApiError::new(
-                    "Failed to create shop item description",
-                    Status::InternalServerError
-                ));
+            Left(error) => {
+                return Err(ApiError::from_sqlx(error, "Failed to create shop item description"));
             }
             Right(_) => {
                 return Err(ApiError::new(
                     "Invalid shop_item_id",
                     Status::BadRequest
-                ));
+                ).with_field_errors(vec![FieldError::new("shop_item_id", "is required")]));
             }
         },
     };
@@ -208,24 +368,35 @@ pub async fn create_shop_item_desc(
 
 /// Creates multiple shop item descriptions in a single transaction
 /// 
+/// Deprecated in favour of `POST /api/v2/batch` with `"atomic": true` and
+/// one `create_shop_item_description` operation per entry of `contents`,
+/// each with the same `shop_item_id`, which likewise creates all of them or
+/// none. A batch takes at most 100 operations.
+/// 
 /// # Arguments
 /// * `db` - Database connection
 /// * `shop_item_desc_many` - Multiple shop item descriptions to create
 /// 
 /// # Returns
-/// * `ApiResult<()>` - Success or failure of the operation
-#[post("/api/shopitemdesc/many", data = "<shop_item_desc_many>", format = "json")]
+/// * `ApiResult<String>` - Success message
+#[utoipa::path(
+    tag = "shop",
+    request_body = ShopItemDescMany,
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<String>),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[deprecated = "use POST /api/v2/batch"]
+#[post("/api/v2/shop-item-descriptions/batch", data = "<shop_item_desc_many>", format = "json")]
 pub async fn create_shop_item_desc_many(
     mut db: Connection<Db>,
-    shop_item_desc_many: Json<ShopItemDescMany>,
-) -> ApiResult<()> {
-    let mut tx = match (*db).begin().await {
+    shop_item_desc_many: Validated<ShopItemDescMany>,
+) -> ApiResult<String> {
+    let mut tx = match audit::begin(&mut db).await {
         Ok(tx) => tx,
-        Err(_) => {
-            return Err(ApiError::new(
-                "Failed to start transaction",
-                Status::InternalServerError
-            ));
+        Err(error) => {
+            return Err(ApiError::from_sqlx(error, "Failed to start transaction"));
         }
     };
 
@@ -240,21 +411,17 @@ pub async fn create_shop_item_desc_many(
         .await
         {
             Ok(_) => continue,
-            Err(_) => {
-                let _ = tx.rollback().await;
-                return Err(ApiError::new(
-                    "Failed to create shop item descriptions",
-                    Status::InternalServerError
-                ));
+            Err(error) => {
+                if let Err(rollback_error) = tx.rollback().await {
+                    warn!(error = %rollback_error, "Failed to roll back shop item descriptions");
+                }
+                return Err(ApiError::from_sqlx(error, "Failed to create shop item descriptions"));
             }
         };
     }
 
     match tx.commit().await {
-        Ok(_) => Ok(ApiResponse::success(())),
-        Err(_) => Err(ApiError::new(
-            "Failed to commit transaction",
-            Status::InternalServerError
-        )),
+        Ok(_) => Ok(ApiResponse::success("Shop item descriptions created successfully".to_string())),
+        Err(error) => Err(ApiError::from_sqlx(error, "Failed to commit transaction")),
     }
 }
@@ -4,31 +4,116 @@
 //! and associating tags with projects and categories.
 
 use rocket::http::Status;
-use rocket::serde::json::Json;
-use rocket::{get, post};
+use rocket::{delete, get, patch, post};
 use rocket_db_pools::Connection;
 
 use serde::{Deserialize, Serialize};
-use std::str::FromStr;
+use utoipa::ToSchema;
+use sqlx::Either::{Left, Right};
 
-use crate::db::tag::{ProjectToTechTag, Tag};
-use crate::db::tag_category_join::TagCategory;
+use crate::api::admin::Admin;
+use crate::db::pagination::PageParams;
+use crate::db::stamps::Stamps;
+use crate::db::tag::{BlogToTag, ProjectToTechTag, ShopItemToTag, Tag, TaggedItems, TagUsage};
+use crate::db::tag_category::TagCategory;
 use crate::Db;
-use crate::api::{ApiResponse, ApiResult, ApiError};
+use crate::api::{ApiResponse, ApiResult, ApiError, ErrorCode, FieldError};
+use crate::api::validation::{Validate, Validated, Validator};
+use crate::api::cache::{CachePolicy, Cached};
 
-/// Retrieves all tags from the database
+/// Retrieves a page of tags from the database
+/// 
+/// # Arguments
+/// * `db` - Database connection
+/// * `q` - Optional case-insensitive search on the tag text
+/// * `page` - Pagination and sorting parameters; sortable by `id` and `text`
+/// 
+/// # Returns
+/// - `Cached<Vec<Tag>>`: A page of tags on success
+/// - `ApiError`: If the sort field is invalid or database operation fails
+#[utoipa::path(
+    tag = "tag",
+    params(PageParams),
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<Vec<Tag>>),
+        (status = 304, description = "Not modified since the cached copy"),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[get("/api/v2/tags?<q>&<page..>")]
+pub async fn tags(mut db: Connection<Db>, q: Option<&str>, page: PageParams) -> Cached<Vec<Tag>> {
+    let result = match Tag::get_all(&mut db, &page, q).await {
+        Ok(results) => Ok(ApiResponse::paginated(results)),
+        Err(Right(_)) => Err(ApiError::new(
+            "Invalid sort field",
+            Status::UnprocessableEntity
+        ).with_code(ErrorCode::InvalidSortField)),
+        Err(Left(error)) => Err(ApiError::from_sqlx(error, "Failed to fetch tags")),
+    };
+    Cached::new(result, CachePolicy::Public(60))
+}
+
+/// Retrieves every tag with the number of items using it
+/// 
+/// # Arguments
+/// * `db` - Database connection
+/// * `category` - Optional category name to filter tags by
+/// * `min_count` - Optional minimum usage count, defaults to 0
 /// 
 /// # Returns
-/// - `ApiResult<Vec<Tag>>`: A list of all tags on success
+/// - `Cached<Vec<TagUsage>>`: Tags with their usage counts, most used first
 /// - `ApiError`: If database operation fails
-#[get("/api/tags")]
-pub async fn tags(db: Connection<Db>) -> ApiResult<Vec<Tag>> {
-    match Tag::get_all(db).await {
+#[utoipa::path(
+    tag = "tag",
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<Vec<TagUsage>>),
+        (status = 304, description = "Not modified since the cached copy"),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[get("/api/v2/tags/cloud?<category>&<min_count>")]
+pub async fn tag_cloud(
+    db: Connection<Db>,
+    category: Option<&str>,
+    min_count: Option<i64>,
+) -> Cached<Vec<TagUsage>> {
+    let result = match Tag::get_usage_counts(db, category, min_count.unwrap_or(0)).await {
         Ok(results) => Ok(ApiResponse::success(results)),
-        Err(_) => Err(ApiError::new(
-            "Failed to fetch tags",
-            Status::InternalServerError
-        )),
+        Err(error) => Err(ApiError::from_sqlx(error, "Failed to fetch tag cloud")),
+    };
+    Cached::new(result, CachePolicy::Public(60))
+}
+
+/// Maximum number of suggestions returned by the autocomplete endpoint
+const AUTOCOMPLETE_MAX_LIMIT: i64 = 50;
+
+/// Suggests tags whose text starts with the given prefix
+/// 
+/// # Arguments
+/// * `db` - Database connection
+/// * `prefix` - Case-insensitive prefix to match
+/// * `limit` - Optional maximum number of suggestions, defaults to 10 and is capped at 50
+/// 
+/// # Returns
+/// - `ApiResult<Vec<Tag>>`: Matching tags in alphabetical order
+/// - `ApiError`: If database operation fails
+#[utoipa::path(
+    tag = "tag",
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<Vec<Tag>>),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[get("/api/v2/tags/autocomplete?<prefix>&<limit>")]
+pub async fn tags_autocomplete(
+    db: Connection<Db>,
+    prefix: &str,
+    limit: Option<i64>,
+) -> ApiResult<Vec<Tag>> {
+    let limit = limit.unwrap_or(10).clamp(1, AUTOCOMPLETE_MAX_LIMIT);
+    match Tag::autocomplete(db, prefix, limit).await {
+        Ok(results) => Ok(ApiResponse::success(results)),
+        Err(error) => Err(ApiError::from_sqlx(error, "Failed to fetch tag suggestions")),
     }
 }
 
@@ -41,21 +126,156 @@ pub async fn tags(db: Connection<Db>) -> ApiResult<Vec<Tag>> {
 /// # Returns
 /// - `ApiResult<Tag>`: The created or existing tag
 /// - `ApiError`: If tag creation fails
-#[post("/api/tag", data = "<tag>", format = "json")]
+#[utoipa::path(
+    tag = "tag",
+    request_body = Tag,
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<Tag>),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[post("/api/v2/tags", data = "<tag>", format = "json")]
 pub async fn create_tag(
-    db: Connection<Db>,
-    tag: Json<Tag>,
+    mut db: Connection<Db>,
+    tag: Validated<Tag>,
 ) -> ApiResult<Tag> {
     let tag_deser = Tag {
         id: None,
         text: tag.text.clone(),
+        categories: Vec::new(),
+        stamps: Stamps::default(),
     };
-    match tag_deser.add_or_get(db).await {
+    match tag_deser.add_or_get(&mut db).await {
         Ok(result) => Ok(ApiResponse::success(result)),
-        Err(_) => Err(ApiError::new(
-            "Failed to create tag",
-            Status::InternalServerError
+        Err(error) => Err(ApiError::from_sqlx(error, "Failed to create tag")),
+    }
+}
+
+/// Renames an existing tag
+/// 
+/// # Arguments
+/// * `db` - Database connection
+/// * `id` - Tag ID
+/// * `tag` - The tag data holding the new text
+/// 
+/// # Returns
+/// - `ApiResult<Tag>`: The renamed tag
+/// - `ApiError`: If the tag does not exist, the text is taken by another tag, or the update fails
+#[utoipa::path(
+    tag = "tag",
+    request_body = Tag,
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<Tag>),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[patch("/api/v2/tags/<id>", data = "<tag>", format = "json")]
+pub async fn rename_tag(
+    db: Connection<Db>,
+    id: i32,
+    tag: Validated<Tag>,
+) -> ApiResult<Tag> {
+    match Tag::rename(db, id, &tag.text).await {
+        Ok(result) => Ok(ApiResponse::success(result)),
+        Err(sqlx::Error::RowNotFound) => Err(ApiError::new(
+            "Tag not found",
+            Status::NotFound
         )),
+        Err(error) => Err(ApiError::from_sqlx(error, "Failed to rename tag")),
+    }
+}
+
+/// Data structure for merging one tag into another
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct TagMergeData {
+    /// The tag to merge and delete
+    pub source_id: i32,
+    /// The tag that receives all references of the source tag
+    pub target_id: i32,
+}
+
+impl Validate for TagMergeData {
+    fn validate(&self) -> Vec<FieldError> {
+        Validator::new()
+            .id("source_id", self.source_id)
+            .id("target_id", self.target_id)
+            .check("target_id", self.source_id != self.target_id, "must differ from source_id")
+            .finish()
+    }
+}
+
+/// Merges one tag into another
+/// 
+/// All project and category references of the source tag are moved to the
+/// target tag, and the source tag is deleted.
+/// 
+/// # Arguments
+/// * `db` - Database connection
+/// * `data` - Source and target tag IDs
+/// 
+/// # Returns
+/// - `ApiResult<Tag>`: The target tag after the merge
+/// - `ApiError`: If the IDs are equal, either tag does not exist, or the merge fails
+#[utoipa::path(
+    tag = "tag",
+    request_body = TagMergeData,
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<Tag>),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[post("/api/v2/tags/merge", data = "<data>", format = "json")]
+pub async fn merge_tags(db: Connection<Db>, data: Validated<TagMergeData>) -> ApiResult<Tag> {
+    match Tag::merge(db, data.source_id, data.target_id).await {
+        Ok(result) => Ok(ApiResponse::success(result)),
+        Err(sqlx::Error::RowNotFound) => Err(ApiError::new(
+            "Tag not found",
+            Status::NotFound
+        )),
+        Err(error) => Err(ApiError::from_sqlx(error, "Failed to merge tags")),
+    }
+}
+
+/// Soft deletes a tag
+/// 
+/// Requires the admin key. A tag that is still used by a project, blog or
+/// shop item is only deleted when `force` is set. Its references are kept, so
+/// an admin can restore it.
+/// 
+/// # Arguments
+/// * `db` - Database connection
+/// * `id` - Tag ID
+/// * `force` - Whether to delete the tag even if it is still in use
+/// 
+/// # Returns
+/// - `ApiResult<String>`: Success message
+/// - `ApiError`: If the tag is in use (Status::Conflict), does not exist, or the delete fails
+#[utoipa::path(
+    tag = "tag",
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<String>),
+        (status = 401, description = "Missing or wrong admin key", body = ApiError),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[delete("/api/v2/tags/<id>?<force>")]
+pub async fn delete_tag(
+    _admin: Admin,
+    db: Connection<Db>,
+    id: i32,
+    force: Option<bool>,
+) -> ApiResult<String> {
+    match Tag::delete(db, id, force.unwrap_or(false)).await {
+        Ok(0) => Err(ApiError::new(
+            "Tag not found",
+            Status::NotFound
+        )),
+        Ok(_) => Ok(ApiResponse::success("Tag deleted successfully".to_string())),
+        Err(Right(usage)) => Err(ApiError::new(
+            format!("Tag is still used in {} place(s); pass force=true to delete it anyway", usage),
+            Status::Conflict
+        )),
+        Err(Left(error)) => Err(ApiError::from_sqlx(error, "Failed to delete tag")),
     }
 }
 
@@ -68,17 +288,106 @@ pub async fn create_tag(
 /// # Returns
 /// - `ApiResult<Vec<Tag>>`: List of tags associated with the project
 /// - `ApiError`: If fetching fails
-#[get("/api/tags/by-project/<id>")]
+#[utoipa::path(
+    tag = "tag",
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<Vec<Tag>>),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[get("/api/v2/projects/<id>/tags")]
 pub async fn tags_by_project(
     db: Connection<Db>,
     id: i32,
 ) -> ApiResult<Vec<Tag>> {
     match Tag::get_tags_by_project(db, &id).await {
         Ok(results) => Ok(ApiResponse::success(results)),
-        Err(_) => Err(ApiError::new(
-            "Failed to fetch tags by project",
-            Status::InternalServerError
+        Err(error) => Err(ApiError::from_sqlx(error, "Failed to fetch tags by project")),
+    }
+}
+
+/// Retrieves all tags attached to a specific blog
+/// 
+/// # Arguments
+/// * `db` - Database connection
+/// * `id` - Blog ID
+/// 
+/// # Returns
+/// - `ApiResult<Vec<Tag>>`: List of tags attached to the blog
+/// - `ApiError`: If fetching fails
+#[utoipa::path(
+    tag = "tag",
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<Vec<Tag>>),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[get("/api/v2/blogs/<id>/tags")]
+pub async fn tags_by_blog(
+    db: Connection<Db>,
+    id: i32,
+) -> ApiResult<Vec<Tag>> {
+    match Tag::get_tags_by_blog(db, id).await {
+        Ok(results) => Ok(ApiResponse::success(results)),
+        Err(error) => Err(ApiError::from_sqlx(error, "Failed to fetch tags by blog")),
+    }
+}
+
+/// Retrieves all tags attached to a specific shop item
+/// 
+/// # Arguments
+/// * `db` - Database connection
+/// * `id` - Shop item ID
+/// 
+/// # Returns
+/// - `ApiResult<Vec<Tag>>`: List of tags attached to the shop item
+/// - `ApiError`: If fetching fails
+#[utoipa::path(
+    tag = "tag",
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<Vec<Tag>>),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[get("/api/v2/shop-items/<id>/tags")]
+pub async fn tags_by_shop_item(
+    db: Connection<Db>,
+    id: i32,
+) -> ApiResult<Vec<Tag>> {
+    match Tag::get_tags_by_shop_item(db, id).await {
+        Ok(results) => Ok(ApiResponse::success(results)),
+        Err(error) => Err(ApiError::from_sqlx(error, "Failed to fetch tags by shop item")),
+    }
+}
+
+/// Retrieves every project, blog and shop item tagged with a given tag
+/// 
+/// # Arguments
+/// * `db` - Database connection
+/// * `tag` - Tag text, matched case-insensitively
+/// 
+/// # Returns
+/// - `ApiResult<TaggedItems>`: The tag and everything tagged with it
+/// - `ApiError`: If the tag is unknown (Status::NotFound) or fetching fails
+#[utoipa::path(
+    tag = "tag",
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<TaggedItems>),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[get("/api/v2/tagged/<tag>")]
+pub async fn tagged(
+    db: Connection<Db>,
+    tag: String,
+) -> ApiResult<TaggedItems> {
+    match TaggedItems::get_by_tag(db, &tag).await {
+        Ok(Some(result)) => Ok(ApiResponse::success(result)),
+        Ok(None) => Err(ApiError::new(
+            "Unknown tag",
+            Status::NotFound
         )),
+        Err(error) => Err(ApiError::from_sqlx(error, "Failed to fetch tagged items")),
     }
 }
 
@@ -90,35 +399,49 @@ pub async fn tags_by_project(
 /// 
 /// # Returns
 /// - `ApiResult<Vec<Tag>>`: List of tags in the category
-/// - `ApiError`: If category is invalid or fetching fails
-#[get("/api/tags-by-category/<category>")]
+/// - `ApiError`: If category is unknown (Status::NotFound) or fetching fails
+#[utoipa::path(
+    tag = "tag",
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<Vec<Tag>>),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[get("/api/v2/categories/<category>/tags")]
 pub async fn tags_by_category(
-    db: Connection<Db>,
+    mut db: Connection<Db>,
     category: String,
 ) -> ApiResult<Vec<Tag>> {
-    let category = match TagCategory::from_str(category.as_str()) {
-        Ok(result) => result,
-        Err(_error) => return Err(ApiError::new(
-            "Invalid category",
-            Status::UnprocessableEntity
+    let category_id = match TagCategory::get_by_name(&mut db, &category).await {
+        Ok(Some(TagCategory { id: Some(id), .. })) => id,
+        Ok(_) => return Err(ApiError::new(
+            "Unknown category",
+            Status::NotFound
         )),
+        Err(error) => return Err(ApiError::from_sqlx(error, "Failed to fetch tags by category")),
     };
-    match Tag::get_tags_by_category(db, category).await {
+    match Tag::get_tags_by_category(db, category_id).await {
         Ok(results) => Ok(ApiResponse::success(results)),
-        Err(_) => Err(ApiError::new(
-            "Failed to fetch tags by category",
-            Status::InternalServerError
-        )),
+        Err(error) => Err(ApiError::from_sqlx(error, "Failed to fetch tags by category")),
     }
 }
 
 /// Data structure for associating a tag with a category
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct TagAndCategoryData {
     /// The tag to be associated
     pub tag: Tag,
-    /// The category to associate the tag with
-    pub category: TagCategory,
+    /// Name of the category to associate the tag with
+    pub category: String,
+}
+
+impl Validate for TagAndCategoryData {
+    fn validate(&self) -> Vec<FieldError> {
+        Validator::new()
+            .nested("tag", &self.tag)
+            .not_blank("category", &self.category)
+            .finish()
+    }
 }
 
 /// Associates a tag with a category
@@ -129,17 +452,76 @@ pub struct TagAndCategoryData {
 /// 
 /// # Returns
 /// - `ApiResult<String>`: Success message
-/// - `ApiError`: If association fails
-#[post("/api/tag_category", data = "<data>", format = "json")]
-pub async fn tag_category(db: Connection<Db>, data: Json<TagAndCategoryData>) -> ApiResult<String> {
-    let result = data.tag.add_category(db, &data.category).await;
+/// - `ApiError`: If the category is unknown (Status::NotFound) or association fails
+#[utoipa::path(
+    tag = "tag",
+    request_body = TagAndCategoryData,
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<String>),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[post("/api/v2/tag-categories", data = "<data>", format = "json")]
+pub async fn tag_category(mut db: Connection<Db>, data: Validated<TagAndCategoryData>) -> ApiResult<String> {
+    let category = match TagCategory::get_by_name(&mut db, &data.category).await {
+        Ok(Some(category)) => category,
+        Ok(None) => return Err(ApiError::new(
+            "Unknown category",
+            Status::NotFound
+        )),
+        Err(error) => return Err(ApiError::from_sqlx(error, "Failed to add tag category")),
+    };
+    let result = data.tag.add_category(&mut db, &category).await;
 
     match result {
-        Ok(_) => Ok(ApiResponse::success("Tag category added successfully".to_string())),
-        Err(_) => Err(ApiError::new(
-            "Failed to add tag category",
-            Status::InternalServerError
+        Ok(Some(_)) => Ok(ApiResponse::success("Tag category added successfully".to_string())),
+        Ok(None) => Err(ApiError::new(
+            "Unknown tag",
+            Status::NotFound
+        )),
+        Err(error) => Err(ApiError::from_sqlx(error, "Failed to add tag category")),
+    }
+}
+
+/// Removes a category from a tag
+/// 
+/// # Arguments
+/// * `db` - Database connection
+/// * `tag_id` - Tag ID
+/// * `category` - Category name as string
+/// 
+/// # Returns
+/// - `ApiResult<String>`: Success message
+/// - `ApiError`: If the category or assignment does not exist (Status::NotFound) or removal fails
+#[utoipa::path(
+    tag = "tag",
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<String>),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[delete("/api/v2/tags/<tag_id>/categories/<category>")]
+pub async fn remove_tag_category(
+    mut db: Connection<Db>,
+    tag_id: i32,
+    category: String,
+) -> ApiResult<String> {
+    let category_id = match TagCategory::get_by_name(&mut db, &category).await {
+        Ok(Some(TagCategory { id: Some(id), .. })) => id,
+        Ok(_) => return Err(ApiError::new(
+            "Unknown category",
+            Status::NotFound
+        )),
+        Err(error) => return Err(ApiError::from_sqlx(error, "Failed to remove tag category")),
+    };
+
+    match Tag::remove_category(db, tag_id, category_id).await {
+        Ok(0) => Err(ApiError::new(
+            "Tag does not have this category",
+            Status::NotFound
         )),
+        Ok(_) => Ok(ApiResponse::success("Tag category removed successfully".to_string())),
+        Err(error) => Err(ApiError::from_sqlx(error, "Failed to remove tag category")),
     }
 }
 
@@ -152,15 +534,132 @@ pub async fn tag_category(db: Connection<Db>, data: Json<TagAndCategoryData>) ->
 /// # Returns
 /// - `ApiResult<String>`: Success message
 /// - `ApiError`: If association fails
-#[post("/api/tag_project", data = "<data>", format = "json")]
-pub async fn tag_project(db: Connection<Db>, data: Json<ProjectToTechTag>) -> ApiResult<String> {
+#[utoipa::path(
+    tag = "tag",
+    request_body = ProjectToTechTag,
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<String>),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[post("/api/v2/project-tags", data = "<data>", format = "json")]
+pub async fn tag_project(db: Connection<Db>, data: Validated<ProjectToTechTag>) -> ApiResult<String> {
     let result = data.add(db).await;
 
     match result {
         Ok(_) => Ok(ApiResponse::success("Tag project added successfully".to_string())),
-        Err(_) => Err(ApiError::new(
-            "Failed to add tag project",
-            Status::InternalServerError
+        Err(error) => Err(ApiError::from_sqlx(error, "Failed to add tag project")),
+    }
+}
+
+/// Attaches a tag to a blog
+/// 
+/// # Arguments
+/// * `db` - Database connection
+/// * `data` - Blog and tag association data
+/// 
+/// # Returns
+/// - `ApiResult<String>`: Success message
+/// - `ApiError`: If association fails
+#[utoipa::path(
+    tag = "tag",
+    request_body = BlogToTag,
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<String>),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[post("/api/v2/blog-tags", data = "<data>", format = "json")]
+pub async fn tag_blog(db: Connection<Db>, data: Validated<BlogToTag>) -> ApiResult<String> {
+    match data.add(db).await {
+        Ok(_) => Ok(ApiResponse::success("Tag blog added successfully".to_string())),
+        Err(error) => Err(ApiError::from_sqlx(error, "Failed to add tag blog")),
+    }
+}
+
+/// Detaches a tag from a blog
+/// 
+/// # Arguments
+/// * `db` - Database connection
+/// * `blog_id` - Blog ID
+/// * `tag_id` - Tag ID
+/// 
+/// # Returns
+/// - `ApiResult<String>`: Success message
+/// - `ApiError`: If the blog does not have the tag (Status::NotFound) or removal fails
+#[utoipa::path(
+    tag = "tag",
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<String>),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[delete("/api/v2/blogs/<blog_id>/tags/<tag_id>")]
+pub async fn untag_blog(db: Connection<Db>, blog_id: i32, tag_id: i32) -> ApiResult<String> {
+    match BlogToTag::remove(db, blog_id, tag_id).await {
+        Ok(0) => Err(ApiError::new(
+            "Blog does not have this tag",
+            Status::NotFound
+        )),
+        Ok(_) => Ok(ApiResponse::success("Tag blog removed successfully".to_string())),
+        Err(error) => Err(ApiError::from_sqlx(error, "Failed to remove tag blog")),
+    }
+}
+
+/// Attaches a tag to a shop item
+/// 
+/// # Arguments
+/// * `db` - Database connection
+/// * `data` - Shop item and tag association data
+/// 
+/// # Returns
+/// - `ApiResult<String>`: Success message
+/// - `ApiError`: If association fails
+#[utoipa::path(
+    tag = "tag",
+    request_body = ShopItemToTag,
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<String>),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[post("/api/v2/shop-item-tags", data = "<data>", format = "json")]
+pub async fn tag_shop_item(db: Connection<Db>, data: Validated<ShopItemToTag>) -> ApiResult<String> {
+    match data.add(db).await {
+        Ok(_) => Ok(ApiResponse::success("Tag shop item added successfully".to_string())),
+        Err(error) => Err(ApiError::from_sqlx(error, "Failed to add tag shop item")),
+    }
+}
+
+/// Detaches a tag from a shop item
+/// 
+/// # Arguments
+/// * `db` - Database connection
+/// * `shop_item_id` - Shop item ID
+/// * `tag_id` - Tag ID
+/// 
+/// # Returns
+/// - `ApiResult<String>`: Success message
+/// - `ApiError`: If the shop item does not have the tag (Status::NotFound) or removal fails
+#[utoipa::path(
+    tag = "tag",
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<String>),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[delete("/api/v2/shop-items/<shop_item_id>/tags/<tag_id>")]
+pub async fn untag_shop_item(
+    db: Connection<Db>,
+    shop_item_id: i32,
+    tag_id: i32,
+) -> ApiResult<String> {
+    match ShopItemToTag::remove(db, shop_item_id, tag_id).await {
+        Ok(0) => Err(ApiError::new(
+            "Shop item does not have this tag",
+            Status::NotFound
         )),
+        Ok(_) => Ok(ApiResponse::success("Tag shop item removed successfully".to_string())),
+        Err(error) => Err(ApiError::from_sqlx(error, "Failed to remove tag shop item")),
     }
 }
\ No newline at end of file
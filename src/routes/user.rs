@@ -3,31 +3,50 @@
 //! This module handles all user-related API endpoints, including:
 //! - User registration
 //! - User retrieval
-//! - User authentication (planned)
 
 use rocket::http::Status;
-use rocket::serde::json::Json;
-use rocket::{get, post};
+use rocket::{delete, get, post};
 use rocket_db_pools::Connection;
+use sqlx::Either::{Left, Right};
 
+use crate::api::admin::Admin;
+use crate::db::pagination::PageParams;
 use crate::db::user::User;
 use crate::Db;
-use crate::api::{ApiResponse, ApiResult, ApiError};
+use crate::api::{ApiResponse, ApiResult, ApiError, ErrorCode};
+use crate::api::validation::Validated;
+use crate::api::cache::{CachePolicy, Cached};
 
-/// Retrieves all users from the system
+/// Retrieves a page of users from the system
+/// 
+/// # Arguments
+/// * `db` - Database connection
+/// * `q` - Optional case-insensitive search on the username or email
+/// * `page` - Pagination and sorting parameters; sortable by `id`, `username` and `email`
 /// 
 /// # Returns
-/// * `ApiResult<Vec<User>>` - List of all users on success
-/// * `ApiError` - If database operation fails
-#[get("/api/users")]
-pub async fn users(db: Connection<Db>) -> ApiResult<Vec<User>> {
-    match User::get_all_users(db).await {
-        Ok(results) => Ok(ApiResponse::success(results)),
-        Err(_) => Err(ApiError::new(
-            "Failed to fetch users",
-            Status::InternalServerError
-        )),
-    }
+/// * `Cached<Vec<User>>` - A page of users on success
+/// * `ApiError` - If the sort field is invalid or database operation fails
+#[utoipa::path(
+    tag = "user",
+    params(PageParams),
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<Vec<User>>),
+        (status = 304, description = "Not modified since the cached copy"),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[get("/api/v2/users?<q>&<page..>")]
+pub async fn users(db: Connection<Db>, q: Option<&str>, page: PageParams) -> Cached<Vec<User>> {
+    let result = match User::get_all_users(db, &page, q).await {
+        Ok(results) => Ok(ApiResponse::paginated(results)),
+        Err(Right(_)) => Err(ApiError::new(
+            "Invalid sort field",
+            Status::UnprocessableEntity
+        ).with_code(ErrorCode::InvalidSortField)),
+        Err(Left(error)) => Err(ApiError::from_sqlx(error, "Failed to fetch users")),
+    };
+    Cached::new(result, CachePolicy::Private)
 }
 
 /// Creates a new user account
@@ -39,13 +58,21 @@ pub async fn users(db: Connection<Db>) -> ApiResult<Vec<User>> {
 /// # Returns
 /// * `ApiResult<User>` - Created user with assigned ID on success
 /// * `ApiError` - If user creation fails due to:
-///   - Duplicate username/email (Status::Conflict)
+///   - Duplicate username/email (Status::Conflict, with the offending field listed)
 ///   - Database error (Status::InternalServerError)
 ///   - Missing ID in response (Status::NotFound)
-#[post("/api/user", data = "<user>", format = "json")]
+#[utoipa::path(
+    tag = "user",
+    request_body = User,
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<User>),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[post("/api/v2/users", data = "<user>", format = "json")]
 pub async fn create_user(
-    db: Connection<Db>,
-    user: Json<User>,
+    mut db: Connection<Db>,
+    user: Validated<User>,
 ) -> ApiResult<User> {
     let user_deser = User {
         id: None,
@@ -54,7 +81,7 @@ pub async fn create_user(
         email: user.email.clone(),
     };
 
-    match user_deser.add(db).await {
+    match user_deser.add(&mut db).await {
         Ok(result) => {
             match result.id {
                 Some(_) => Ok(ApiResponse::success(result)),
@@ -64,19 +91,38 @@ pub async fn create_user(
                 )),
             }
         }
-        Err(error) => {
-            // Check if it's a unique constraint violation (e.g., duplicate username/email)
-            if error.to_string().contains("unique constraint") {
-                Err(ApiError::new(
-                    "Username or email already exists",
-                    Status::Conflict
-                ))
-            } else {
-                Err(ApiError::new(
-                    "Failed to create user",
-                    Status::InternalServerError
-                ))
-            }
-        }
+        Err(error) => Err(ApiError::from_sqlx(error, "Failed to create user")),
     }
-}
\ No newline at end of file
+}
+
+/// Soft deletes a user
+/// 
+/// Requires the admin key. The user can no longer log in, but their
+/// username stays taken until an admin purges them.
+/// 
+/// # Arguments
+/// * `db` - Database connection
+/// * `id` - User ID
+/// 
+/// # Returns
+/// - `ApiResult<String>`: Success message
+/// - `ApiError`: If the user does not exist or the delete fails
+#[utoipa::path(
+    tag = "user",
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<String>),
+        (status = 401, description = "Missing or wrong admin key", body = ApiError),
+        (status = "default", description = "Error", body = ApiError),
+    )
+)]
+#[delete("/api/v2/users/<id>")]
+pub async fn delete_user(_admin: Admin, db: Connection<Db>, id: i32) -> ApiResult<String> {
+    match User::delete(db, id).await {
+        Ok(true) => Ok(ApiResponse::success("User deleted successfully".to_string())),
+        Ok(false) => Err(ApiError::new(
+            "User not found",
+            Status::NotFound
+        )),
+        Err(error) => Err(ApiError::from_sqlx(error, "Failed to delete user")),
+    }
+}
@@ -0,0 +1,203 @@
+//! Logging and request tracing
+//!
+//! Logs are written through `tracing`, as JSON lines by default or as plain
+//! text for local development. Verbosity is set per module with `log_filter`,
+//! e.g. `"info,projects_rust_server::db=debug,sqlx=warn"`, and `RUST_LOG`
+//! takes precedence when it is set. Rocket's own log records are forwarded
+//! into the same output, and sqlx statements are passed on to
+//! [`SlowQueries`] to be counted.
+//!
+//! Every request gets an ID, taken from its `X-Request-Id` header or
+//! generated, which is sent back on the response. Handlers run inside a span
+//! carrying that ID, so everything logged while handling a request can be
+//! traced back to it, and inside an audit context that attributes their
+//! database changes to the request and its actor.
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Header;
+use rocket::request::Request;
+use rocket::response::Response;
+use rocket::route::{self, Handler, Route};
+use rocket::Data;
+use std::time::Instant;
+use tracing::{info, info_span, warn, Instrument, Span};
+use tracing_subscriber::filter::{LevelFilter, Targets};
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{fmt, EnvFilter};
+use uuid::Uuid;
+
+use crate::api::admin;
+use crate::db::audit::{self, AuditContext};
+use crate::metrics::SlowQueries;
+
+/// Filter used when neither `RUST_LOG` nor `log_filter` is set
+pub const DEFAULT_FILTER: &str = "info";
+
+/// Header carrying the request ID in both directions
+const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// Longest request ID accepted from a client
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+/// How log lines are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Json,
+    Text,
+}
+
+impl LogFormat {
+    /// Parses the value of `log_format`, defaulting to JSON
+    pub fn from_name(name: &str) -> Self {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "text" | "pretty" => LogFormat::Text,
+            _ => LogFormat::Json,
+        }
+    }
+}
+
+/// Installs the global subscriber
+///
+/// # Arguments
+/// * `filter` - Per-module filter directives, used when `RUST_LOG` is not set
+/// * `format` - Whether to write JSON lines or plain text
+/// * `slow_query_ms` - Duration above which a query is counted as slow
+pub fn init(filter: &str, format: LogFormat, slow_query_ms: u64) {
+    let filter = EnvFilter::try_from_default_env()
+        .or_else(|_| EnvFilter::try_new(filter))
+        .unwrap_or_else(|error| {
+            eprintln!("Invalid log_filter '{}', using '{}': {}", filter, DEFAULT_FILTER, error);
+            EnvFilter::new(DEFAULT_FILTER)
+        });
+    let output = match format {
+        LogFormat::Json => fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(false)
+            .boxed(),
+        LogFormat::Text => fmt::layer().boxed(),
+    };
+    // Statements are counted whatever the log filter says about sqlx
+    let statements = Targets::new().with_target("sqlx::query", LevelFilter::TRACE);
+
+    let result = tracing_subscriber::registry()
+        .with(output.with_filter(filter))
+        .with(SlowQueries::new(slow_query_ms).with_filter(statements))
+        .try_init();
+    if let Err(error) = result {
+        eprintln!("Failed to install log subscriber: {}", error);
+    }
+}
+
+/// Request ID and span of a request, kept in its local cache
+struct RequestContext {
+    request_id: Option<String>,
+    span: Span,
+    started: Option<Instant>,
+}
+
+impl RequestContext {
+    fn none() -> Self {
+        Self {
+            request_id: None,
+            span: Span::none(),
+            started: None,
+        }
+    }
+}
+
+/// Whether a client-supplied request ID is safe to log and echo back
+fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LENGTH
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'))
+}
+
+/// The span of the request, or a disabled span outside of one
+pub fn request_span(req: &Request<'_>) -> Span {
+    req.local_cache(RequestContext::none).span.clone()
+}
+
+/// Fairing that assigns request IDs and logs every request
+pub struct RequestTracing;
+
+#[rocket::async_trait]
+impl Fairing for RequestTracing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request tracing",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _data: &mut Data<'_>) {
+        let request_id = req
+            .headers()
+            .get_one(REQUEST_ID_HEADER)
+            .map(str::trim)
+            .filter(|id| is_valid_request_id(id))
+            .map(String::from)
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+        let span = info_span!(
+            "request",
+            request_id = %request_id,
+            method = %req.method(),
+            path = %req.uri().path(),
+        );
+        req.local_cache(|| RequestContext {
+            request_id: Some(request_id),
+            span,
+            started: Some(Instant::now()),
+        });
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        let context = req.local_cache(RequestContext::none);
+        if let Some(request_id) = &context.request_id {
+            res.set_header(Header::new(REQUEST_ID_HEADER, request_id.clone()));
+        }
+
+        let status = res.status().code;
+        let elapsed_ms = context
+            .started
+            .map(|started| started.elapsed().as_secs_f64() * 1000.0)
+            .unwrap_or_default();
+        if res.status().class().is_server_error() {
+            warn!(parent: &context.span, status, elapsed_ms, "Request failed");
+        } else {
+            info!(parent: &context.span, status, elapsed_ms, "Request finished");
+        }
+    }
+}
+
+/// A route handler that runs inside the span and audit context of its request
+#[derive(Clone)]
+struct Traced(Box<dyn Handler>);
+
+#[rocket::async_trait]
+impl Handler for Traced {
+    async fn handle<'r>(&self, req: &'r Request<'_>, data: Data<'r>) -> route::Outcome<'r> {
+        let context = AuditContext {
+            actor: admin::actor(req),
+            request_id: req.local_cache(RequestContext::none).request_id.clone(),
+        };
+        audit::scope(context, self.0.handle(req, data))
+            .instrument(request_span(req))
+            .await
+    }
+}
+
+/// Wraps the handlers of `routes` so that they log inside their request's
+/// span and record changes in its audit context
+pub fn traced(routes: Vec<Route>) -> Vec<Route> {
+    routes
+        .into_iter()
+        .map(|mut route| {
+            route.handler = Box::new(Traced(route.handler));
+            route
+        })
+        .collect()
+}